@@ -0,0 +1,7 @@
+ALTER TABLE orders
+    DROP COLUMN IF EXISTS currency,
+    DROP COLUMN IF EXISTS grand_total,
+    DROP COLUMN IF EXISTS shipping_total,
+    DROP COLUMN IF EXISTS tax_total,
+    DROP COLUMN IF EXISTS discount_total,
+    DROP COLUMN IF EXISTS subtotal;
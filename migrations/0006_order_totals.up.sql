@@ -0,0 +1,18 @@
+ALTER TABLE orders
+    ADD COLUMN subtotal NUMERIC(12, 2) NOT NULL DEFAULT 0 CHECK (subtotal >= 0),
+    ADD COLUMN discount_total NUMERIC(12, 2) NOT NULL DEFAULT 0 CHECK (discount_total >= 0),
+    ADD COLUMN tax_total NUMERIC(12, 2) NOT NULL DEFAULT 0 CHECK (tax_total >= 0),
+    ADD COLUMN shipping_total NUMERIC(12, 2) NOT NULL DEFAULT 0 CHECK (shipping_total >= 0),
+    ADD COLUMN grand_total NUMERIC(12, 2) NOT NULL DEFAULT 0 CHECK (grand_total >= 0),
+    ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD' CHECK (currency ~ '^[A-Z]{3}$');
+
+-- Existing orders only have items, so their totals are the sum of the items
+UPDATE orders
+SET subtotal = items.subtotal,
+    grand_total = items.subtotal
+FROM (
+    SELECT order_id, SUM(price * quantity) AS subtotal
+    FROM order_items
+    GROUP BY order_id
+) AS items
+WHERE orders.id = items.order_id;
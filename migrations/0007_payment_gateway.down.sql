@@ -0,0 +1,8 @@
+ALTER TABLE payments DROP CONSTRAINT IF EXISTS payments_status_check;
+
+ALTER TABLE payments
+    DROP COLUMN IF EXISTS updated_at,
+    DROP COLUMN IF EXISTS provider_reference,
+    DROP COLUMN IF EXISTS provider,
+    DROP COLUMN IF EXISTS currency,
+    DROP COLUMN IF EXISTS amount;
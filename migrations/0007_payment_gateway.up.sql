@@ -0,0 +1,17 @@
+ALTER TABLE payments
+    ADD COLUMN amount NUMERIC(12, 2) NOT NULL DEFAULT 0 CHECK (amount >= 0),
+    ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD' CHECK (currency ~ '^[A-Z]{3}$'),
+    ADD COLUMN provider TEXT,
+    ADD COLUMN provider_reference TEXT,
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+UPDATE payments
+SET amount = orders.grand_total,
+    currency = orders.currency
+FROM orders
+WHERE orders.id = payments.order_id;
+
+ALTER TABLE payments
+    ADD CONSTRAINT payments_status_check CHECK (status IN (
+        'pending', 'authorized', 'paid', 'voided', 'failed', 'refunded'
+    ));
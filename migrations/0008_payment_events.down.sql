@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS payments_provider_reference_idx;
+
+DROP TABLE IF EXISTS payment_events;
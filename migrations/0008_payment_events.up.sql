@@ -0,0 +1,15 @@
+CREATE TABLE payment_events (
+    id UUID PRIMARY KEY,
+    provider TEXT NOT NULL,
+    event_id TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    processed_at TIMESTAMPTZ,
+    error TEXT,
+    UNIQUE (provider, event_id)
+);
+
+CREATE INDEX payment_events_unprocessed_idx ON payment_events (received_at) WHERE processed_at IS NULL;
+
+CREATE INDEX payments_provider_reference_idx ON payments (provider, provider_reference);
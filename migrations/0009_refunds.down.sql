@@ -0,0 +1,20 @@
+DROP TABLE IF EXISTS refund_items;
+DROP TABLE IF EXISTS refunds;
+
+UPDATE orders SET status = 'paid' WHERE status = 'partially_refunded';
+UPDATE payments SET status = 'paid' WHERE status = 'partially_refunded';
+
+ALTER TABLE orders
+    DROP CONSTRAINT orders_status_check,
+    ADD CONSTRAINT orders_status_check CHECK (status IN (
+        'pending', 'awaiting_payment', 'paid', 'fulfilling', 'shipped', 'delivered',
+        'cancelled', 'refunded'
+    ));
+
+ALTER TABLE payments
+    DROP CONSTRAINT payments_status_check,
+    ADD CONSTRAINT payments_status_check CHECK (status IN (
+        'pending', 'authorized', 'paid', 'voided', 'failed', 'refunded'
+    )),
+    DROP CONSTRAINT payments_refunded_amount_check,
+    DROP COLUMN refunded_amount;
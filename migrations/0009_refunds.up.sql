@@ -0,0 +1,39 @@
+ALTER TABLE payments
+    ADD COLUMN refunded_amount NUMERIC(12, 2) NOT NULL DEFAULT 0,
+    ADD CONSTRAINT payments_refunded_amount_check
+        CHECK (refunded_amount >= 0 AND refunded_amount <= amount),
+    DROP CONSTRAINT payments_status_check,
+    ADD CONSTRAINT payments_status_check CHECK (status IN (
+        'pending', 'authorized', 'paid', 'voided', 'failed', 'partially_refunded', 'refunded'
+    ));
+
+ALTER TABLE orders
+    DROP CONSTRAINT orders_status_check,
+    ADD CONSTRAINT orders_status_check CHECK (status IN (
+        'pending', 'awaiting_payment', 'paid', 'fulfilling', 'shipped', 'delivered',
+        'cancelled', 'partially_refunded', 'refunded'
+    ));
+
+CREATE TABLE refunds (
+    id UUID PRIMARY KEY,
+    payment_id UUID NOT NULL REFERENCES payments (id) ON DELETE CASCADE,
+    amount NUMERIC(12, 2) NOT NULL CHECK (amount > 0),
+    reason TEXT,
+    restocked BOOLEAN NOT NULL DEFAULT false,
+    provider_reference TEXT,
+    created_by UUID REFERENCES users (id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX refunds_payment_id_idx ON refunds (payment_id);
+
+CREATE TABLE refund_items (
+    id UUID PRIMARY KEY,
+    refund_id UUID NOT NULL REFERENCES refunds (id) ON DELETE CASCADE,
+    order_item_id UUID NOT NULL REFERENCES order_items (id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    amount NUMERIC(12, 2) NOT NULL CHECK (amount >= 0)
+);
+
+CREATE INDEX refund_items_refund_id_idx ON refund_items (refund_id);
+CREATE INDEX refund_items_order_item_id_idx ON refund_items (order_item_id);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS payments_active_order_id_idx;
+
+DROP TABLE IF EXISTS idempotency_keys;
@@ -0,0 +1,24 @@
+CREATE TABLE idempotency_keys (
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    request_hash TEXT NOT NULL,
+    status_code SMALLINT,
+    response_body JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, key)
+);
+
+-- Only one payment of an order may be in progress or settled; failed and voided ones are history
+UPDATE payments
+SET status = 'voided'
+WHERE status = 'pending'
+  AND EXISTS (
+      SELECT 1 FROM payments AS older
+      WHERE older.order_id = payments.order_id
+        AND older.status NOT IN ('voided', 'failed')
+        AND (older.created_at, older.id) < (payments.created_at, payments.id)
+  );
+
+CREATE UNIQUE INDEX payments_active_order_id_idx
+    ON payments (order_id)
+    WHERE status NOT IN ('voided', 'failed');
@@ -0,0 +1,4 @@
+-- Carts without a user belong to visitors, identified by a signed cookie
+ALTER TABLE carts ALTER COLUMN user_id DROP NOT NULL;
+
+CREATE INDEX carts_guest_updated_at_idx ON carts (updated_at) WHERE user_id IS NULL;
@@ -0,0 +1,7 @@
+ALTER TABLE orders DROP COLUMN IF EXISTS coupon_id;
+
+DROP TABLE IF EXISTS coupon_redemptions;
+
+DROP TABLE IF EXISTS coupon_products;
+
+DROP TABLE IF EXISTS coupons;
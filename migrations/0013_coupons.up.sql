@@ -0,0 +1,36 @@
+CREATE TABLE coupons (
+    id UUID PRIMARY KEY,
+    code TEXT NOT NULL CHECK (code ~ '^[A-Z0-9_-]{3,32}$'),
+    discount_type TEXT NOT NULL CHECK (discount_type IN ('percentage', 'fixed')),
+    value NUMERIC(12, 2) NOT NULL CHECK (value > 0),
+    min_order_value NUMERIC(12, 2) CHECK (min_order_value >= 0),
+    max_uses INT CHECK (max_uses > 0),
+    max_uses_per_user INT CHECK (max_uses_per_user > 0),
+    uses INT NOT NULL DEFAULT 0 CHECK (uses >= 0),
+    starts_at TIMESTAMPTZ,
+    ends_at TIMESTAMPTZ,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (code),
+    CHECK (discount_type <> 'percentage' OR value <= 100),
+    CHECK (max_uses IS NULL OR uses <= max_uses),
+    CHECK (starts_at IS NULL OR ends_at IS NULL OR starts_at < ends_at)
+);
+
+-- Products a coupon is restricted to; a coupon without any applies to every product
+CREATE TABLE coupon_products (
+    coupon_id UUID NOT NULL REFERENCES coupons (id) ON DELETE CASCADE,
+    product_id UUID NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+    PRIMARY KEY (coupon_id, product_id)
+);
+
+CREATE TABLE coupon_redemptions (
+    order_id UUID PRIMARY KEY REFERENCES orders (id) ON DELETE CASCADE,
+    coupon_id UUID NOT NULL REFERENCES coupons (id),
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX coupon_redemptions_coupon_user_idx ON coupon_redemptions (coupon_id, user_id);
+
+ALTER TABLE orders ADD COLUMN coupon_id UUID REFERENCES coupons (id);
@@ -0,0 +1,25 @@
+ALTER TABLE products
+    ADD COLUMN tax_class TEXT NOT NULL DEFAULT 'standard' CHECK (tax_class ~ '^[a-z0-9_]{1,32}$');
+
+-- Rates per region (ISO 3166-1 country or ISO 3166-2 subdivision) and product tax class.
+-- A subdivision's rate takes precedence over its country's.
+CREATE TABLE tax_rates (
+    id UUID PRIMARY KEY,
+    region TEXT NOT NULL CHECK (region ~ '^[A-Z]{2}(-[A-Z0-9]{1,3})?$'),
+    tax_class TEXT NOT NULL CHECK (tax_class ~ '^[a-z0-9_]{1,32}$'),
+    rate NUMERIC(6, 3) NOT NULL CHECK (rate >= 0 AND rate <= 100), -- Percentage
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (region, tax_class)
+);
+
+ALTER TABLE orders
+    ADD COLUMN tax_region TEXT CHECK (tax_region ~ '^[A-Z]{2}(-[A-Z0-9]{1,3})?$'),
+    ADD COLUMN prices_include_tax BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Per line breakdown, filled whenever the totals of the order are recalculated
+ALTER TABLE order_items
+    ADD COLUMN tax_class TEXT NOT NULL DEFAULT 'standard',
+    ADD COLUMN discount_amount NUMERIC(12, 2) NOT NULL DEFAULT 0 CHECK (discount_amount >= 0),
+    ADD COLUMN tax_rate NUMERIC(6, 3) NOT NULL DEFAULT 0,
+    ADD COLUMN tax_amount NUMERIC(12, 2) NOT NULL DEFAULT 0 CHECK (tax_amount >= 0);
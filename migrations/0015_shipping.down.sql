@@ -0,0 +1,20 @@
+ALTER TABLE orders
+    DROP CONSTRAINT IF EXISTS orders_shipping_check,
+    DROP COLUMN IF EXISTS shipping_method,
+    DROP COLUMN IF EXISTS shipping_address;
+
+DROP TABLE IF EXISTS shipping_rates;
+
+DROP TABLE IF EXISTS shipping_zone_regions;
+
+DROP TABLE IF EXISTS shipping_zones;
+
+DROP TABLE IF EXISTS shipping_methods;
+
+ALTER TABLE products
+    DROP COLUMN IF EXISTS height_cm,
+    DROP COLUMN IF EXISTS width_cm,
+    DROP COLUMN IF EXISTS length_cm,
+    DROP COLUMN IF EXISTS weight_kg;
+
+DROP TABLE IF EXISTS addresses;
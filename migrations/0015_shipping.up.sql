@@ -0,0 +1,68 @@
+-- Address book of every user. At most one address per user is the default.
+CREATE TABLE addresses (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,                 -- Recipient
+    line1 TEXT NOT NULL,
+    line2 TEXT,
+    city TEXT NOT NULL,
+    postal_code TEXT NOT NULL,
+    region TEXT NOT NULL CHECK (region ~ '^[A-Z]{2}(-[A-Z0-9]{1,3})?$'),
+    phone TEXT,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX addresses_user_id_idx ON addresses (user_id);
+CREATE UNIQUE INDEX addresses_default_idx ON addresses (user_id) WHERE is_default;
+
+-- Package of one unit of the product, used to compute shipping costs
+ALTER TABLE products
+    ADD COLUMN weight_kg NUMERIC(10, 3) NOT NULL DEFAULT 0 CHECK (weight_kg >= 0),
+    ADD COLUMN length_cm NUMERIC(8, 1) NOT NULL DEFAULT 0 CHECK (length_cm >= 0),
+    ADD COLUMN width_cm NUMERIC(8, 1) NOT NULL DEFAULT 0 CHECK (width_cm >= 0),
+    ADD COLUMN height_cm NUMERIC(8, 1) NOT NULL DEFAULT 0 CHECK (height_cm >= 0);
+
+CREATE TABLE shipping_methods (
+    id UUID PRIMARY KEY,
+    code TEXT NOT NULL UNIQUE CHECK (code ~ '^[a-z0-9_]{1,32}$'),
+    name TEXT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE shipping_zones (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Countries and subdivisions served by each zone. A subdivision listed in a zone is served by
+-- that zone rather than by the zone of its country.
+CREATE TABLE shipping_zone_regions (
+    region TEXT PRIMARY KEY CHECK (region ~ '^[A-Z]{2}(-[A-Z0-9]{1,3})?$'),
+    zone_id UUID NOT NULL REFERENCES shipping_zones(id) ON DELETE CASCADE
+);
+
+CREATE INDEX shipping_zone_regions_zone_id_idx ON shipping_zone_regions (zone_id);
+
+-- Price of a method in a zone, by weight bracket. The bracket with the smallest
+-- max_weight_kg covering the parcel applies; a NULL max_weight_kg covers any weight.
+CREATE TABLE shipping_rates (
+    id UUID PRIMARY KEY,
+    zone_id UUID NOT NULL REFERENCES shipping_zones(id) ON DELETE CASCADE,
+    method_id UUID NOT NULL REFERENCES shipping_methods(id) ON DELETE CASCADE,
+    max_weight_kg NUMERIC(10, 3) CHECK (max_weight_kg > 0),
+    price NUMERIC(12, 2) NOT NULL CHECK (price >= 0),
+    free_over NUMERIC(12, 2) CHECK (free_over >= 0), -- Merchandise total above which shipping is free
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE NULLS NOT DISTINCT (zone_id, method_id, max_weight_kg)
+);
+
+-- Destination and method as they were when the order was placed
+ALTER TABLE orders
+    ADD COLUMN shipping_address JSONB,
+    ADD COLUMN shipping_method JSONB,
+    ADD CONSTRAINT orders_shipping_check
+        CHECK ((shipping_address IS NULL) = (shipping_method IS NULL));
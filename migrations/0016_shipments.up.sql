@@ -0,0 +1,26 @@
+-- Parcels sent for an order. An order can be split over several shipments.
+CREATE TABLE shipments (
+    id UUID PRIMARY KEY,
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    carrier TEXT,
+    tracking_number TEXT,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'shipped', 'delivered')),
+    shipped_at TIMESTAMPTZ,
+    delivered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK (status = 'pending' OR shipped_at IS NOT NULL),
+    CHECK (status <> 'delivered' OR delivered_at IS NOT NULL)
+);
+
+CREATE INDEX shipments_order_id_idx ON shipments (order_id);
+
+-- Units of each order item packed in a shipment
+CREATE TABLE shipment_items (
+    shipment_id UUID NOT NULL REFERENCES shipments(id) ON DELETE CASCADE,
+    order_item_id UUID NOT NULL REFERENCES order_items(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    PRIMARY KEY (shipment_id, order_item_id)
+);
+
+CREATE INDEX shipment_items_order_item_id_idx ON shipment_items (order_item_id);
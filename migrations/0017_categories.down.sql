@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS coupon_categories;
+
+DROP TABLE IF EXISTS product_categories;
+
+DROP TABLE IF EXISTS categories;
@@ -0,0 +1,18 @@
+DELETE FROM cart_items WHERE variant_id IS NOT NULL;
+
+ALTER TABLE cart_items
+    DROP CONSTRAINT IF EXISTS cart_items_cart_id_product_id_variant_id_key,
+    DROP COLUMN IF EXISTS variant_id,
+    ADD CONSTRAINT cart_items_cart_id_product_id_key UNIQUE (cart_id, product_id);
+
+ALTER TABLE order_items
+    DROP COLUMN IF EXISTS sku,
+    DROP COLUMN IF EXISTS variant_id;
+
+DROP TABLE IF EXISTS variant_option_values;
+
+DROP TABLE IF EXISTS product_variants;
+
+DROP TABLE IF EXISTS option_values;
+
+DROP TABLE IF EXISTS option_types;
@@ -0,0 +1,51 @@
+-- Options a product is sold in, e.g. "Size" with the values "S", "M" and "L"
+CREATE TABLE option_types (
+    id UUID PRIMARY KEY,
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (product_id, name)
+);
+
+CREATE TABLE option_values (
+    id UUID PRIMARY KEY,
+    option_type_id UUID NOT NULL REFERENCES option_types(id) ON DELETE CASCADE,
+    value TEXT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (option_type_id, value),
+    UNIQUE (id, option_type_id)
+);
+
+-- A combination of option values with its own SKU and stock
+CREATE TABLE product_variants (
+    id UUID PRIMARY KEY,
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    sku TEXT NOT NULL UNIQUE CHECK (sku ~ '^[A-Za-z0-9._-]{1,64}$'),
+    price NUMERIC(12, 2) CHECK (price >= 0), -- NULL to sell at the product's price
+    stock INTEGER NOT NULL DEFAULT 0 CHECK (stock >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX product_variants_product_id_idx ON product_variants (product_id);
+
+-- One value of each option type of the product per variant. Option values used by a variant
+-- cannot be deleted.
+CREATE TABLE variant_option_values (
+    variant_id UUID NOT NULL REFERENCES product_variants(id) ON DELETE CASCADE,
+    option_type_id UUID NOT NULL,
+    option_value_id UUID NOT NULL,
+    PRIMARY KEY (variant_id, option_type_id),
+    FOREIGN KEY (option_value_id, option_type_id) REFERENCES option_values(id, option_type_id)
+);
+
+ALTER TABLE order_items
+    ADD COLUMN variant_id UUID REFERENCES product_variants(id),
+    ADD COLUMN sku TEXT; -- SKU of the variant when it was ordered
+
+ALTER TABLE cart_items
+    ADD COLUMN variant_id UUID REFERENCES product_variants(id) ON DELETE CASCADE,
+    DROP CONSTRAINT cart_items_cart_id_product_id_key,
+    ADD CONSTRAINT cart_items_cart_id_product_id_variant_id_key
+        UNIQUE NULLS NOT DISTINCT (cart_id, product_id, variant_id);
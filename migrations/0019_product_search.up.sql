@@ -0,0 +1,8 @@
+-- Words of the name (weighted A) and description (weighted B) of every product. The simple
+-- configuration does not stem, so prefixes typed so far match the words they start.
+ALTER TABLE products ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('simple', name), 'A') ||
+    setweight(to_tsvector('simple', COALESCE(description, '')), 'B')
+) STORED;
+
+CREATE INDEX products_search_vector_idx ON products USING GIN (search_vector);
@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS order_status_history_order_id_idx;
+CREATE INDEX order_status_history_order_id_idx ON order_status_history (order_id, created_at);
+
+ALTER TABLE order_status_history ALTER COLUMN created_at SET DEFAULT now();
+
+ALTER TABLE order_status_history DROP COLUMN IF EXISTS seq;
@@ -0,0 +1,15 @@
+-- Changes recorded in one transaction share its now(), so the history is ordered by a sequence
+-- instead. Existing entries are numbered in their previous order.
+ALTER TABLE order_status_history ADD COLUMN seq BIGSERIAL NOT NULL;
+
+UPDATE order_status_history
+SET seq = ordered.seq
+FROM (
+    SELECT id, row_number() OVER (ORDER BY created_at, id) AS seq FROM order_status_history
+) AS ordered
+WHERE ordered.id = order_status_history.id;
+
+ALTER TABLE order_status_history ALTER COLUMN created_at SET DEFAULT clock_timestamp();
+
+DROP INDEX order_status_history_order_id_idx;
+CREATE INDEX order_status_history_order_id_idx ON order_status_history (order_id, seq);
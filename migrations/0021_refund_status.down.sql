@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS refunds_pending_idx;
+
+DELETE FROM refunds WHERE status <> 'succeeded';
+
+ALTER TABLE refunds
+    DROP COLUMN IF EXISTS updated_at,
+    DROP COLUMN IF EXISTS status;
@@ -0,0 +1,10 @@
+-- Refunds are recorded as pending before the provider is called, and only count towards the
+-- payment's refunded amount once the provider accepted them. Existing refunds all succeeded.
+ALTER TABLE refunds
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'succeeded'
+        CHECK (status IN ('pending', 'succeeded', 'failed')),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+ALTER TABLE refunds ALTER COLUMN status SET DEFAULT 'pending';
+
+CREATE INDEX refunds_pending_idx ON refunds (payment_id) WHERE status = 'pending';
@@ -0,0 +1,8 @@
+UPDATE payments SET status = 'pending' WHERE status = 'authorizing';
+UPDATE payments SET status = 'authorized' WHERE status = 'capturing';
+
+ALTER TABLE payments
+    DROP CONSTRAINT payments_status_check,
+    ADD CONSTRAINT payments_status_check CHECK (status IN (
+        'pending', 'authorized', 'paid', 'voided', 'failed', 'partially_refunded', 'refunded'
+    ));
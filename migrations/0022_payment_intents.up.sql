@@ -0,0 +1,9 @@
+-- A payment is moved to authorizing or capturing, and committed, before the provider is
+-- called. It keeps that status until the provider's answer is recorded, so an answer that
+-- could not be recorded is never mistaken for a call that was never made.
+ALTER TABLE payments
+    DROP CONSTRAINT payments_status_check,
+    ADD CONSTRAINT payments_status_check CHECK (status IN (
+        'pending', 'authorizing', 'authorized', 'capturing', 'paid', 'voided', 'failed',
+        'partially_refunded', 'refunded'
+    ));
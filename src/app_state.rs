@@ -1,15 +1,12 @@
-use tokio_postgres::Client;
-use std::sync::Arc;
+use deadpool_postgres::Pool;
 
 pub struct AppState {
-    pub db_client: Arc<Client>,
+    pub db_pool: Pool,
 }
 
 impl AppState {
-    // Função para inicializar o estado com o cliente do banco de dados
-    pub fn new(client: Client) -> Self {
-        AppState {
-            db_client: Arc::new(client),
-        }
+    // Função para inicializar o estado com o pool de conexões do banco de dados
+    pub fn new(pool: Pool) -> Self {
+        AppState { db_pool: pool }
     }
 }
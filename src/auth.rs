@@ -1,33 +1,128 @@
-use actix_web::{dev::Payload, error, web, HttpRequest, HttpResponse, FromRequest};
-use futures::future::{ready, Ready};
-use actix_web::Error;
+use std::marker::PhantomData;
 
+use actix_web::{web, FromRequest, HttpRequest};
+use deadpool_postgres::Pool;
+use futures::future::LocalBoxFuture;
+use uuid::Uuid;
+
+use crate::errors::AppError;
 use crate::jwt::{validate_token, Claims};
+use crate::models::user_model::UserRole;
 
 pub struct AuthenticatedUser(pub Claims);
 
+impl AuthenticatedUser {
+    /// Returns true if the user has the admin role.
+    pub fn is_admin(&self) -> bool {
+        self.0.role == UserRole::Admin
+    }
+
+    /// Ensures the user owns the resource (identified by its owner's ID) or is an admin.
+    pub fn ensure_self_or_admin(&self, owner_id: Uuid) -> Result<(), AppError> {
+        if self.0.sub == owner_id || self.is_admin() {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden(
+                "You do not have permission to access this resource".to_string(),
+            ))
+        }
+    }
+}
+
+// Extracts the token from an `Authorization: Bearer <token>` header.
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("Authorization")?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::to_string)
+}
+
 impl FromRequest for AuthenticatedUser {
-    type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
-        if let Some(auth_header) = req.headers().get("Authorization") {
-            if let Ok(auth_str) = auth_header.to_str() {
-                if auth_str.starts_with("Bearer ") {
-                    let token = &auth_str[7..];
-                    match validate_token(token) {
-                        Ok(claims) => return ready(Ok(AuthenticatedUser(claims))),
-                        Err(err) => {
-                            eprintln!("Token validation failed: {:?}", err);
-                            return ready(Err(actix_web::error::ErrorUnauthorized("Unauthorized")));
-                        }
-                    }
+        let token = bearer_token(req);
+        let pool = req.app_data::<web::Data<Pool>>().cloned();
+
+        Box::pin(async move {
+            let Some(token) = token else {
+                eprintln!("Authorization header missing or invalid");
+                return Err(AppError::Unauthorized(
+                    "Authorization header missing or invalid".to_string(),
+                ));
+            };
+
+            // The revocation list lives in the database
+            let pool =
+                pool.ok_or_else(|| AppError::Internal("Database pool not configured".to_string()))?;
+            let client = pool.get().await?;
+
+            match validate_token(&client, &token).await {
+                Ok(claims) => Ok(AuthenticatedUser(claims)),
+                Err(err) => {
+                    eprintln!("Token validation failed: {:?}", err);
+                    Err(err)
                 }
             }
+        })
+    }
+}
+
+/// Extractor for endpoints open to visitors: `None` without an `Authorization` header.
+/// A header carrying an invalid token is still rejected with 401.
+pub struct OptionalUser(pub Option<AuthenticatedUser>);
+
+impl FromRequest for OptionalUser {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut actix_web::dev::Payload) -> Self::Future {
+        if req.headers().get("Authorization").is_none() {
+            return Box::pin(async { Ok(OptionalUser(None)) });
         }
-        eprintln!("Authorization header missing or invalid");
-        ready(Err(actix_web::error::ErrorUnauthorized("Unauthorized")))
+
+        let authenticated = AuthenticatedUser::from_request(req, payload);
+        Box::pin(async move { Ok(OptionalUser(Some(authenticated.await?))) })
     }
 }
 
+/// Marker trait for the roles that can be required by `RequireRole`.
+pub trait Role {
+    const ROLE: UserRole;
+}
+
+/// Marker for the admin role, used as `RequireRole<Admin>`.
+pub struct Admin;
+
+impl Role for Admin {
+    const ROLE: UserRole = UserRole::Admin;
+}
+
+/// Extractor that only succeeds for authenticated users currently holding the role `R`.
+/// Requests without a valid token are rejected with 401, those with the wrong role with 403.
+pub struct RequireRole<R: Role>(pub Claims, PhantomData<R>);
+
+impl<R: Role> FromRequest for RequireRole<R> {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let authenticated = AuthenticatedUser::from_request(req, payload);
+
+        Box::pin(async move {
+            let AuthenticatedUser(claims) = authenticated.await?;
 
+            if claims.role == R::ROLE {
+                Ok(RequireRole(claims, PhantomData))
+            } else {
+                Err(AppError::Forbidden(format!(
+                    "This action requires the {} role",
+                    R::ROLE.as_str()
+                )))
+            }
+        })
+    }
+}
@@ -0,0 +1,205 @@
+use actix_web::{web, HttpResponse};
+use deadpool_postgres::Pool;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthenticatedUser,
+    controllers::product_controller::present,
+    errors::AppError,
+    models::address_model::{Address, AddressChanges, NewAddress},
+    tax::is_valid_region,
+};
+
+/// Represents the request body to add an address to the address book.
+#[derive(Deserialize)]
+pub struct CreateAddressRequest {
+    pub name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub postal_code: String,
+    pub region: String, // "DE", or "US-CA" where taxes or shipping depend on the subdivision
+    pub phone: Option<String>,
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+/// Body of an address update. Omitted fields are left unchanged, and an explicit `null`
+/// clears `line2` or `phone`.
+#[derive(Deserialize)]
+pub struct UpdateAddressRequest {
+    pub name: Option<String>,
+    pub line1: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub line2: Option<Option<String>>,
+    pub city: Option<String>,
+    pub postal_code: Option<String>,
+    pub region: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub phone: Option<Option<String>>,
+    pub is_default: Option<bool>,
+}
+
+// Trims a required field, rejecting it if nothing is left.
+fn required(field: &str, value: &str) -> Result<String, AppError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(AppError::validation(format!("{} must not be empty", field)));
+    }
+
+    Ok(value.to_string())
+}
+
+// Normalizes a region code, rejecting anything rates could not be looked up by.
+fn region(value: &str) -> Result<String, AppError> {
+    let region = value.trim().to_uppercase();
+    if !is_valid_region(&region) {
+        return Err(AppError::validation(
+            "region must be an ISO 3166 country or subdivision code, e.g. \"DE\" or \"US-CA\"",
+        ));
+    }
+
+    Ok(region)
+}
+
+// Trims an optional field, treating a blank value as absent.
+fn optional(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+/// Handler listing the addresses of the authenticated user, the default one first.
+pub async fn list_addresses(
+    pool: web::Data<Pool>,
+    auth_user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+    let addresses = Address::list_for_user(&client, auth_user.0.sub).await?;
+
+    Ok(HttpResponse::Ok().json(addresses))
+}
+
+/// Handler adding an address to the address book of the authenticated user.
+/// The first address becomes the default one.
+pub async fn create_address(
+    pool: web::Data<Pool>,
+    auth_user: AuthenticatedUser,
+    body: web::Json<CreateAddressRequest>,
+) -> Result<HttpResponse, AppError> {
+    let name = required("name", &body.name)?;
+    let line1 = required("line1", &body.line1)?;
+    let city = required("city", &body.city)?;
+    let postal_code = required("postal_code", &body.postal_code)?;
+    let region = region(&body.region)?;
+    let line2 = optional(body.line2.as_deref());
+    let phone = optional(body.phone.as_deref());
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let address = Address::create(
+        &transaction,
+        auth_user.0.sub,
+        &NewAddress {
+            name: &name,
+            line1: &line1,
+            line2: line2.as_deref(),
+            city: &city,
+            postal_code: &postal_code,
+            region: &region,
+            phone: phone.as_deref(),
+            is_default: body.is_default,
+        },
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Created().json(address))
+}
+
+/// Handler to retrieve an address of the authenticated user.
+pub async fn get_address(
+    pool: web::Data<Pool>,
+    auth_user: AuthenticatedUser,
+    address_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    let address = Address::get_for_user(&client, auth_user.0.sub, *address_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Address not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(address))
+}
+
+/// Handler to change an address of the authenticated user. Only the fields present in the
+/// body are changed; orders already placed keep the address they were shipped to.
+pub async fn update_address(
+    pool: web::Data<Pool>,
+    auth_user: AuthenticatedUser,
+    address_id: web::Path<Uuid>,
+    body: web::Json<UpdateAddressRequest>,
+) -> Result<HttpResponse, AppError> {
+    if body.is_default == Some(false) {
+        return Err(AppError::validation(
+            "Make another address the default instead of unsetting this one",
+        ));
+    }
+
+    let changes = AddressChanges {
+        name: body
+            .name
+            .as_deref()
+            .map(|name| required("name", name))
+            .transpose()?,
+        line1: body
+            .line1
+            .as_deref()
+            .map(|line1| required("line1", line1))
+            .transpose()?,
+        line2: body.line2.as_ref().map(|line2| optional(line2.as_deref())),
+        city: body
+            .city
+            .as_deref()
+            .map(|city| required("city", city))
+            .transpose()?,
+        postal_code: body
+            .postal_code
+            .as_deref()
+            .map(|postal_code| required("postal_code", postal_code))
+            .transpose()?,
+        region: body.region.as_deref().map(region).transpose()?,
+        phone: body.phone.as_ref().map(|phone| optional(phone.as_deref())),
+        is_default: body.is_default,
+    };
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let address = Address::update(&transaction, auth_user.0.sub, *address_id, &changes)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Address not found".to_string()))?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(address))
+}
+
+/// Handler to remove an address from the address book of the authenticated user.
+pub async fn delete_address(
+    pool: web::Data<Pool>,
+    auth_user: AuthenticatedUser,
+    address_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    if !Address::delete(&client, auth_user.0.sub, *address_id).await? {
+        return Err(AppError::NotFound("Address not found".to_string()));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,367 @@
+use std::env;
+
+use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
+    web, HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use deadpool_postgres::{GenericClient, Pool};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    auth::{AuthenticatedUser, OptionalUser},
+    controllers::order_controller::{place_order, CreateOrderRequest, OrderItemRequest},
+    crypto::{hmac_sha256_hex, verify_hmac_sha256},
+    errors::AppError,
+    models::{
+        cart_model::{Cart, GUEST_CART_TTL_DAYS},
+        order_items_model::OrderItem,
+    },
+};
+
+/// Cookie identifying the cart of a visitor, holding `"{cart_id}.{signature}"`.
+pub const GUEST_CART_COOKIE: &str = "guest_cart";
+
+/// Represents the request body to add a product to the cart.
+#[derive(Deserialize)]
+pub struct AddCartItemRequest {
+    pub product_id: Uuid,
+    pub variant_id: Option<Uuid>, // Required for products sold in variants
+    pub quantity: i32,            // Added to the quantity already in the cart
+}
+
+/// Optional request body of the checkout.
+#[derive(Deserialize)]
+pub struct CheckoutRequest {
+    pub coupon_code: Option<String>, // Discount code to redeem on the order
+    pub tax_region: Option<String>,  // Same as for `POST /orders`
+    pub address_id: Option<Uuid>,
+    pub shipping_method: Option<String>,
+}
+
+/// Represents the request body to change the quantity of a cart item.
+#[derive(Deserialize)]
+pub struct UpdateCartItemRequest {
+    pub quantity: i32,
+}
+
+// Returns the key signing guest cart cookies, GUEST_CART_SECRET or else JWT_SECRET.
+fn guest_cart_secret() -> String {
+    env::var("GUEST_CART_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+        .unwrap_or_else(|| env::var("JWT_SECRET").expect("JWT_SECRET not set"))
+}
+
+/// Returns the guest cart named by the request's cookie, if the cookie's signature is valid.
+pub(crate) fn guest_cart_id(req: &HttpRequest) -> Option<Uuid> {
+    let cookie = req.cookie(GUEST_CART_COOKIE)?;
+    let (cart_id, signature) = cookie.value().split_once('.')?;
+
+    if !verify_hmac_sha256(
+        guest_cart_secret().as_bytes(),
+        cart_id.as_bytes(),
+        signature,
+    ) {
+        return None;
+    }
+
+    cart_id.parse().ok()
+}
+
+// Builds the signed cookie handing a guest cart to a visitor.
+fn guest_cart_cookie(cart_id: Uuid) -> Cookie<'static> {
+    let cart_id = cart_id.to_string();
+    let signature = hmac_sha256_hex(guest_cart_secret().as_bytes(), cart_id.as_bytes());
+
+    Cookie::build(GUEST_CART_COOKIE, format!("{}.{}", cart_id, signature))
+        .path("/")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::days(GUEST_CART_TTL_DAYS.into()))
+        .finish()
+}
+
+/// Builds the cookie removing a visitor's guest cart cookie, once the cart was merged.
+pub(crate) fn guest_cart_removal() -> Cookie<'static> {
+    let mut cookie = Cookie::build(GUEST_CART_COOKIE, "").path("/").finish();
+    cookie.make_removal();
+    cookie
+}
+
+// Resolves the cart a request works on: the user's cart, or the visitor's guest cart, if
+// they have one. For visitors, also returns the cookie to send back, renewing its expiry, or
+// removing it when the cart it names no longer exists.
+async fn find_request_cart(
+    client: &impl GenericClient,
+    req: &HttpRequest,
+    user: &OptionalUser,
+) -> Result<(Option<Uuid>, Option<Cookie<'static>>), AppError> {
+    if let Some(user) = &user.0 {
+        return Ok((Some(Cart::id_for_user(client, user.0.sub).await?), None));
+    }
+
+    let cart_id = Cart::find_guest(client, guest_cart_id(req)).await?;
+    let cookie = match cart_id {
+        Some(cart_id) => Some(guest_cart_cookie(cart_id)),
+        None if req.cookie(GUEST_CART_COOKIE).is_some() => Some(guest_cart_removal()),
+        None => None,
+    };
+
+    Ok((cart_id, cookie))
+}
+
+// Like `find_request_cart`, but creates a cart for visitors who have none yet. Only used to
+// add items, so reads of visitors don't store empty carts.
+async fn request_cart(
+    client: &impl GenericClient,
+    req: &HttpRequest,
+    user: &OptionalUser,
+) -> Result<(Uuid, Option<Cookie<'static>>), AppError> {
+    match find_request_cart(client, req, user).await? {
+        (Some(cart_id), cookie) => Ok((cart_id, cookie)),
+        (None, _) => {
+            let cart_id = Cart::create_guest(client).await?;
+            Ok((cart_id, Some(guest_cart_cookie(cart_id))))
+        }
+    }
+}
+
+// Sends a response, setting the guest cart cookie if there is one.
+fn respond(
+    mut response: HttpResponseBuilder,
+    cookie: Option<Cookie<'static>>,
+    body: &impl Serialize,
+) -> HttpResponse {
+    if let Some(cookie) = cookie {
+        response.cookie(cookie);
+    }
+
+    response.json(body)
+}
+
+// Rejects quantities that cannot be put in a cart.
+fn validate_quantity(quantity: i32) -> Result<(), AppError> {
+    if quantity <= 0 {
+        return Err(AppError::validation("Quantity must be greater than zero"));
+    }
+
+    Ok(())
+}
+
+/// Handler returning the cart of the authenticated user, or of the visitor.
+/// Items are priced at the current product prices; items whose price changed since the
+/// last view report the old price, and items that cannot be ordered carry a stock warning.
+pub async fn get_cart(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    user: OptionalUser,
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let (cart_id, cookie) = find_request_cart(&transaction, &req, &user).await?;
+    let cart = match cart_id {
+        Some(cart_id) => Cart::view(&transaction, cart_id).await?,
+        None => Cart::empty(),
+    };
+
+    transaction.commit().await?;
+
+    Ok(respond(HttpResponse::Ok(), cookie, &cart))
+}
+
+/// Handler removing every item from the cart of the authenticated user, or of the visitor.
+pub async fn clear_cart(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    user: OptionalUser,
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let (cart_id, cookie) = find_request_cart(&transaction, &req, &user).await?;
+    if let Some(cart_id) = cart_id {
+        Cart::clear(&transaction, cart_id).await?;
+    }
+
+    transaction.commit().await?;
+
+    let mut response = HttpResponse::NoContent();
+    if let Some(cookie) = cookie {
+        response.cookie(cookie);
+    }
+    Ok(response.finish())
+}
+
+/// Handler adding a product to the cart of the authenticated user, or of the visitor.
+/// The cart does not reserve stock; quantities above the stock are accepted with a warning.
+pub async fn add_cart_item(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    user: OptionalUser,
+    body: web::Json<AddCartItemRequest>,
+) -> Result<HttpResponse, AppError> {
+    validate_quantity(body.quantity)?;
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let (cart_id, cookie) = request_cart(&transaction, &req, &user).await?;
+    Cart::add_item(
+        &transaction,
+        cart_id,
+        body.product_id,
+        body.variant_id,
+        body.quantity,
+    )
+    .await?;
+    let cart = Cart::view(&transaction, cart_id).await?;
+
+    transaction.commit().await?;
+
+    Ok(respond(HttpResponse::Ok(), cookie, &cart))
+}
+
+/// Handler changing the quantity of an item in the cart of the authenticated user, or of
+/// the visitor.
+pub async fn update_cart_item(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    user: OptionalUser,
+    item_id: web::Path<Uuid>,
+    body: web::Json<UpdateCartItemRequest>,
+) -> Result<HttpResponse, AppError> {
+    validate_quantity(body.quantity)?;
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let (cart_id, cookie) = find_request_cart(&transaction, &req, &user).await?;
+    let cart_id = cart_id.ok_or_else(|| AppError::NotFound("Cart item not found".to_string()))?;
+    if !Cart::set_item_quantity(&transaction, cart_id, *item_id, body.quantity).await? {
+        return Err(AppError::NotFound("Cart item not found".to_string()));
+    }
+    let cart = Cart::view(&transaction, cart_id).await?;
+
+    transaction.commit().await?;
+
+    Ok(respond(HttpResponse::Ok(), cookie, &cart))
+}
+
+/// Handler removing an item from the cart of the authenticated user, or of the visitor.
+pub async fn remove_cart_item(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    user: OptionalUser,
+    item_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let (cart_id, cookie) = find_request_cart(&transaction, &req, &user).await?;
+    let cart_id = cart_id.ok_or_else(|| AppError::NotFound("Cart item not found".to_string()))?;
+    if !Cart::remove_item(&transaction, cart_id, *item_id).await? {
+        return Err(AppError::NotFound("Cart item not found".to_string()));
+    }
+    let cart = Cart::view(&transaction, cart_id).await?;
+
+    transaction.commit().await?;
+
+    Ok(respond(HttpResponse::Ok(), cookie, &cart))
+}
+
+/// Handler converting the cart of the authenticated user into an order.
+///
+/// Checkout is refused while the cart shows outdated prices, so customers are only charged
+/// prices they have seen, or while an item is short of stock. On success the cart is emptied
+/// and the order is returned, with the same body as `POST /orders`. Visitors must log in
+/// first, which merges their guest cart into their own.
+pub async fn checkout(
+    pool: web::Data<Pool>,
+    auth_user: AuthenticatedUser,
+    body: Option<web::Json<CheckoutRequest>>,
+) -> Result<HttpResponse, AppError> {
+    let body = body.map(web::Json::into_inner);
+    let user_id = auth_user.0.sub;
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let cart_id = Cart::id_for_user(&transaction, user_id).await?;
+    let mut items = Cart::items(&transaction, cart_id).await?;
+
+    if items.is_empty() {
+        return Err(AppError::validation("The cart is empty"));
+    }
+
+    let changed: Vec<_> = items
+        .iter()
+        .filter_map(|item| {
+            item.previous_price.map(|previous_price| {
+                json!({
+                    "product_id": item.product_id,
+                    "variant_id": item.variant_id,
+                    "previous_price": previous_price,
+                    "unit_price": item.unit_price,
+                })
+            })
+        })
+        .collect();
+    if !changed.is_empty() {
+        return Err(AppError::Conflict {
+            message: "Prices changed since the cart was last viewed".to_string(),
+            details: Some(json!({ "items": changed })),
+        });
+    }
+
+    // Lock products in the same order as order creation, and report every shortage at once.
+    items.sort_by_key(|item| (item.product_id, item.variant_id));
+    let mut shortages = Vec::new();
+    for item in &items {
+        let in_stock = OrderItem::check_stock(
+            &transaction,
+            item.product_id,
+            item.variant_id,
+            item.quantity,
+        )
+        .await?;
+        if !in_stock {
+            shortages.push(json!({
+                "product_id": item.product_id,
+                "variant_id": item.variant_id,
+                "requested": item.quantity,
+            }));
+        }
+    }
+    if !shortages.is_empty() {
+        return Err(AppError::Conflict {
+            message: "Insufficient stock for some items of the cart".to_string(),
+            details: Some(json!({ "items": shortages })),
+        });
+    }
+
+    let request = CreateOrderRequest {
+        items: items
+            .iter()
+            .map(|item| OrderItemRequest {
+                product_id: item.product_id,
+                variant_id: item.variant_id,
+                quantity: item.quantity,
+            })
+            .collect(),
+        coupon_code: body.as_ref().and_then(|body| body.coupon_code.clone()),
+        tax_region: body.as_ref().and_then(|body| body.tax_region.clone()),
+        address_id: body.as_ref().and_then(|body| body.address_id),
+        shipping_method: body.and_then(|body| body.shipping_method),
+    };
+    let order = place_order(&transaction, user_id, &request).await?;
+
+    Cart::clear(&transaction, cart_id).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Created().json(order))
+}
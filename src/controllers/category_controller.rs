@@ -0,0 +1,186 @@
+use actix_web::{web, HttpResponse};
+use deadpool_postgres::Pool;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    auth::{Admin, RequireRole},
+    controllers::product_controller::{present, product_filter, ListProductsQuery},
+    errors::AppError,
+    models::{
+        category_model::{is_valid_slug, slugify, Category, CategoryChanges, NewCategory},
+        product_model::Product,
+    },
+    pagination::Page,
+};
+
+/// Represents the request body to create a category.
+#[derive(Deserialize)]
+pub struct CreateCategoryRequest {
+    pub name: String,
+    pub slug: Option<String>,    // Derived from the name when omitted
+    pub parent_id: Option<Uuid>, // Omitted for a top level category
+    #[serde(default)]
+    pub position: i32,
+}
+
+/// Body of a category update. Omitted fields are left unchanged, and an explicit
+/// `"parent_id": null` moves the category to the top level.
+#[derive(Deserialize)]
+pub struct UpdateCategoryRequest {
+    pub name: Option<String>,
+    pub slug: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub parent_id: Option<Option<Uuid>>,
+    pub position: Option<i32>,
+}
+
+/// Represents the request body assigning a product to categories.
+#[derive(Deserialize)]
+pub struct SetProductCategoriesRequest {
+    pub category_ids: Vec<Uuid>, // Replaces the current assignment; empty to remove it
+}
+
+// Checks a slug, in the form produced by `slugify`.
+fn validate_slug(slug: &str) -> Result<(), AppError> {
+    if !is_valid_slug(slug) {
+        return Err(AppError::validation(
+            "slug must be up to 64 lower case letters and digits separated by dashes",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Handler returning every category as a tree, siblings ordered by position and name.
+pub async fn list_categories(pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+    let tree = Category::tree(&client).await?;
+
+    Ok(HttpResponse::Ok().json(tree))
+}
+
+/// Handler listing the products of a category and of all its subcategories.
+/// Accepts the same filters, sorting and pagination as `GET /products`.
+pub async fn list_category_products(
+    pool: web::Data<Pool>,
+    slug: web::Path<String>,
+    query: web::Query<ListProductsQuery>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    let category = Category::get_by_slug(&client, &slug)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Category not found".to_string()))?;
+
+    let filter = product_filter(query.into_inner(), Some(category.id))?;
+    let (products, total) = Product::list(&client, &filter).await?;
+
+    Ok(HttpResponse::Ok().json(Page::new(products, total, filter.limit, filter.offset)))
+}
+
+/// Handler to create a category. Restricted to admins.
+pub async fn create_category(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    body: web::Json<CreateCategoryRequest>,
+) -> Result<HttpResponse, AppError> {
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(AppError::validation("name must not be empty"));
+    }
+
+    let slug = body.slug.clone().unwrap_or_else(|| slugify(name));
+    validate_slug(&slug)?;
+
+    let client = pool.get().await?;
+
+    let category = Category::create(
+        &client,
+        &NewCategory {
+            parent_id: body.parent_id,
+            name,
+            slug: &slug,
+            position: body.position,
+        },
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().json(category))
+}
+
+/// Handler to rename, reorder or move a category. Restricted to admins.
+/// Moving a category moves its whole subtree.
+pub async fn update_category(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    category_id: web::Path<Uuid>,
+    body: web::Json<UpdateCategoryRequest>,
+) -> Result<HttpResponse, AppError> {
+    let body = body.into_inner();
+
+    let name = body.name.map(|name| name.trim().to_string());
+    if name.as_deref().is_some_and(str::is_empty) {
+        return Err(AppError::validation("name must not be empty"));
+    }
+    if let Some(slug) = &body.slug {
+        validate_slug(slug)?;
+    }
+
+    let changes = CategoryChanges {
+        parent_id: body.parent_id,
+        name,
+        slug: body.slug,
+        position: body.position,
+    };
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let category = Category::update(&transaction, *category_id, &changes)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Category not found".to_string()))?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(category))
+}
+
+/// Handler to delete a category without subcategories. Restricted to admins.
+/// Its products stay in the catalog.
+pub async fn delete_category(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    category_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    if !Category::delete(&client, *category_id).await? {
+        return Err(AppError::NotFound("Category not found".to_string()));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Handler replacing the categories a product is listed in. Restricted to admins.
+pub async fn set_product_categories(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    product_id: web::Path<Uuid>,
+    body: web::Json<SetProductCategoriesRequest>,
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    if !Category::set_for_product(&transaction, *product_id, &body.category_ids).await? {
+        return Err(AppError::NotFound("Product not found".to_string()));
+    }
+
+    transaction.commit().await?;
+
+    let product = Product::get_product(&client, *product_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Product not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(product))
+}
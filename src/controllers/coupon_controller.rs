@@ -0,0 +1,160 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Pool;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    auth::{Admin, RequireRole},
+    errors::AppError,
+    models::coupon_model::{normalize_code, Coupon, DiscountType, NewCoupon},
+    pagination::{page_bounds, Page},
+};
+
+/// Represents the request body to create a coupon.
+#[derive(Deserialize)]
+pub struct CreateCouponRequest {
+    pub code: String, // 3 to 32 letters, digits, "-" or "_"
+    pub discount_type: DiscountType,
+    pub value: Decimal, // Percentage (up to 100) or amount off
+    pub min_order_value: Option<Decimal>,
+    pub max_uses: Option<i32>,
+    pub max_uses_per_user: Option<i32>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub product_ids: Vec<Uuid>, // Restricts the coupon to these products
+    #[serde(default)]
+    pub category_ids: Vec<Uuid>, // and to the products of these categories
+}
+
+/// Query string accepted by the coupon listing.
+#[derive(Deserialize)]
+pub struct ListCouponsQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+// Checks the attributes of a new coupon, returning its normalized code.
+fn validate_coupon(body: &CreateCouponRequest) -> Result<String, AppError> {
+    let code = normalize_code(&body.code);
+    let valid_code = (3..=32).contains(&code.len())
+        && code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_code {
+        return Err(AppError::validation(
+            "code must be 3 to 32 letters, digits, '-' or '_'",
+        ));
+    }
+
+    if body.value <= Decimal::ZERO {
+        return Err(AppError::validation("value must be greater than zero"));
+    }
+    if body.discount_type == DiscountType::Percentage && body.value > Decimal::ONE_HUNDRED {
+        return Err(AppError::validation("A percentage cannot exceed 100"));
+    }
+    if body.value.scale() > 2 {
+        return Err(AppError::validation(
+            "value must have at most 2 decimal places",
+        ));
+    }
+
+    if body.min_order_value.is_some_and(|min| min < Decimal::ZERO) {
+        return Err(AppError::validation("min_order_value must not be negative"));
+    }
+    if body.max_uses.is_some_and(|max| max <= 0)
+        || body.max_uses_per_user.is_some_and(|max| max <= 0)
+    {
+        return Err(AppError::validation(
+            "Usage limits must be greater than zero",
+        ));
+    }
+
+    if let (Some(starts_at), Some(ends_at)) = (body.starts_at, body.ends_at) {
+        if starts_at >= ends_at {
+            return Err(AppError::validation("starts_at must be before ends_at"));
+        }
+    }
+
+    Ok(code)
+}
+
+/// Handler to create a coupon. Restricted to admins.
+pub async fn create_coupon(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    body: web::Json<CreateCouponRequest>,
+) -> Result<HttpResponse, AppError> {
+    let code = validate_coupon(&body)?;
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let coupon = Coupon::create(
+        &transaction,
+        &NewCoupon {
+            code: &code,
+            discount_type: body.discount_type,
+            value: body.value,
+            min_order_value: body.min_order_value,
+            max_uses: body.max_uses,
+            max_uses_per_user: body.max_uses_per_user,
+            starts_at: body.starts_at,
+            ends_at: body.ends_at,
+            product_ids: &body.product_ids,
+            category_ids: &body.category_ids,
+        },
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Created().json(coupon))
+}
+
+/// Handler listing coupons, newest first. Restricted to admins.
+pub async fn list_coupons(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    query: web::Query<ListCouponsQuery>,
+) -> Result<HttpResponse, AppError> {
+    let (limit, offset) = page_bounds(query.limit, query.offset)?;
+
+    let client = pool.get().await?;
+    let (coupons, total) = Coupon::list(&client, limit, offset).await?;
+
+    Ok(HttpResponse::Ok().json(Page::new(coupons, total, limit, offset)))
+}
+
+/// Handler to retrieve a coupon with its usage count. Restricted to admins.
+pub async fn get_coupon(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    coupon_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    let coupon = Coupon::get_by_id(&client, *coupon_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Coupon not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(coupon))
+}
+
+/// Handler to stop a coupon from being redeemed. Restricted to admins.
+/// Orders that already hold the coupon keep their discount.
+pub async fn deactivate_coupon(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    coupon_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    let coupon = Coupon::deactivate(&client, *coupon_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Coupon not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(coupon))
+}
@@ -1,6 +1,15 @@
-pub mod user_controller;
-pub mod product_controller;
+pub mod address_controller;
+pub mod cart_controller;
+pub mod category_controller;
+pub mod coupon_controller;
 pub mod order_controller;
 pub mod order_items_controller;
 pub mod payment_controller;
+pub mod product_controller;
+pub mod shipment_controller;
+pub mod shipping_controller;
+pub mod tax_rate_controller;
+pub mod user_controller;
+pub mod variant_controller;
 
+pub mod webhook_controller;
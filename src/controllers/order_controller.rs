@@ -1,109 +1,419 @@
-use std::sync::Arc;
-
-use actix_web::{web, HttpResponse};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{GenericClient, Pool};
 use serde::{Deserialize, Serialize};
-use tokio_postgres::Client;
+use serde_json::json;
 use uuid::Uuid;
 
-use crate::{auth::AuthenticatedUser, jwt::Claims, models::{order_items_model::OrderItem, order_model::Order, payment_model::Payment}};
+use crate::{
+    auth::{Admin, AuthenticatedUser, RequireRole},
+    controllers::payment_controller::{
+        ensure_no_captured_funds, void_authorized_payment, void_pending_payments,
+    },
+    errors::AppError,
+    idempotency::Idempotency,
+    models::{
+        address_model::Address,
+        coupon_model::Coupon,
+        order_items_model::OrderItem,
+        order_model::{Order, OrderFilter, OrderStatus},
+        payment_model::Payment,
+        shipment_model::Shipment,
+        shipping_model::ShippingMethod,
+    },
+    pagination::{page_bounds, Page},
+    payment_gateway::PaymentGateway,
+    tax,
+};
 
 /// Represents the request body to create a new order, including the items.
 #[derive(Serialize, Deserialize)]
 pub struct CreateOrderRequest {
     pub items: Vec<OrderItemRequest>, // List of items in the order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coupon_code: Option<String>, // Discount code to redeem, matched case insensitively
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_region: Option<String>, // e.g. "DE" or "US-CA", defaults to the address's region
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address_id: Option<Uuid>, // Address of the user's address book to ship to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shipping_method: Option<String>, // Code of the shipping method, required with an address
 }
 
 /// Represents an individual item in the order, including the product ID, quantity, and price.
 #[derive(Serialize, Deserialize)]
 pub struct OrderItemRequest {
     pub product_id: Uuid, // ID of the product being ordered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant_id: Option<Uuid>, // Required for products sold in variants
     pub quantity: i32,    // Quantity of the product
 }
 
-/// Handler to create an order along with its items and payment.
-pub async fn create_order(
-    client: web::Data<Arc<Client>>,      // Database client
-    auth_user: AuthenticatedUser,           // User ID for the order
-    body: web::Json<CreateOrderRequest>, // Request body containing order details
-) -> HttpResponse {
+/// Query string accepted by the order listing.
+#[derive(Deserialize)]
+pub struct ListOrdersQuery {
+    pub status: Option<OrderStatus>,
+    pub from: Option<DateTime<Utc>>, // RFC 3339 timestamp, inclusive
+    pub to: Option<DateTime<Utc>>,   // RFC 3339 timestamp, exclusive
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
 
-    let user_id = auth_user.0.sub; 
+/// An order together with its items, payment and shipments.
+#[derive(Serialize)]
+pub struct OrderDetails {
+    #[serde(flatten)]
+    pub order: Order,
+    pub items: Vec<OrderItem>,
+    pub payment: Option<Payment>,
+    pub shipments: Vec<Shipment>,
+}
 
-    let order = match Order::create_order(&client, user_id).await {
-        Ok(order) => order,
-        Err(err) => {
-            eprintln!("Error creating order: {:?}", err);
-            return HttpResponse::InternalServerError().finish();
-        }
-    };
+/// Represents the request body to move an order to another status.
+#[derive(Deserialize)]
+pub struct UpdateOrderStatusRequest {
+    pub status: OrderStatus, // Target status, must be allowed by the transition table
+}
 
-    // Processing the order items.
-    for item in &body.items {
-        let is_in_stock =
-            match OrderItem::check_stock(&client, item.product_id, item.quantity).await {
-                Ok(stock) => stock,
-                Err(err) => {
-                    eprintln!("Error checking stock: {:?}", err);
-                    return HttpResponse::InternalServerError().finish();
-                }
-            };
-
-        if !is_in_stock {
-            return HttpResponse::BadRequest().body("Insufficient stock for one or more items.");
+/// Creates an order with its items and a pending payment, taking the items out of stock and
+/// redeeming the coupon, if any. Shared by order creation and cart checkout; must run inside
+/// a transaction so a failure, e.g. a product running out of stock, rolls back every change.
+pub(crate) async fn place_order(
+    client: &impl GenericClient,
+    user_id: Uuid,
+    request: &CreateOrderRequest,
+) -> Result<Order, AppError> {
+    let shipping = match (request.address_id, &request.shipping_method) {
+        (Some(address_id), Some(code)) => {
+            let address = Address::get_for_user(client, user_id, address_id)
+                .await?
+                .ok_or_else(|| AppError::NotFound("Address not found".to_string()))?;
+            let method = ShippingMethod::get_active_by_code(client, code)
+                .await?
+                .ok_or_else(|| AppError::validation("Unknown shipping method"))?;
+            Some((address.snapshot(), method.snapshot()))
         }
+        (None, None) => None,
+        _ => {
+            return Err(AppError::validation(
+                "address_id and shipping_method must be given together",
+            ))
+        }
+    };
 
-        // Adding the item to the order.
-        match OrderItem::create_order_item(&client, order.id, item.product_id, item.quantity).await
-        {
-            Ok(_) => {}
-            Err(err) => {
-                eprintln!("Error adding item to order: {:?}", err);
-                return HttpResponse::InternalServerError().finish();
+    let tax_region = match &request.tax_region {
+        Some(region) => {
+            let region = region.trim().to_uppercase();
+            if !tax::is_valid_region(&region) {
+                return Err(AppError::validation(
+                    "tax_region must be a country or subdivision code such as DE or US-CA",
+                ));
             }
+            Some(region)
         }
+        None => shipping
+            .as_ref()
+            .map(|(address, _)| address.region.clone())
+            .or_else(tax::default_tax_region),
+    };
+
+    let order = Order::create_order(
+        client,
+        user_id,
+        tax_region.as_deref(),
+        shipping.as_ref().map(|(address, method)| (address, method)),
+    )
+    .await?;
+
+    // Lock products and variants in a consistent order so concurrent orders cannot deadlock.
+    let mut items: Vec<&OrderItemRequest> = request.items.iter().collect();
+    items.sort_by_key(|item| (item.product_id, item.variant_id));
+
+    // Adding the items to the order, failing if any of them is out of stock.
+    for item in items {
+        OrderItem::create_order_item(
+            client,
+            order.id,
+            item.product_id,
+            item.variant_id,
+            item.quantity,
+        )
+        .await?;
+    }
+
+    if let Some(code) = &request.coupon_code {
+        let items = OrderItem::get_order_items(client, order.id).await?;
+        Coupon::redeem(client, code, user_id, order.id, &items).await?;
     }
 
+    let order = Order::recalculate_totals(client, order.id).await?;
+
     let payment_method = ""; // Empty payment method initially
-    let _payment = match Payment::create_payment(&client, order.id, &payment_method).await {
-        Ok(payment) => payment,
-        Err(err) => {
-            eprintln!("Error creating payment: {:?}", err);
-            return HttpResponse::InternalServerError().finish();
-        }
-    };
+    Payment::create_payment(client, order.id, payment_method).await?;
+
+    Ok(order)
+}
+
+/// Handler to create an order along with its items and payment.
+///
+/// The whole flow runs in a single transaction: product rows are locked while their
+/// stock is checked and decremented, and any failure rolls back every change.
+/// Requests retried with the same `Idempotency-Key` return the order created the first time.
+pub async fn create_order(
+    req: HttpRequest,
+    pool: web::Data<Pool>,               // Database connection pool
+    auth_user: AuthenticatedUser,        // User ID for the order
+    body: web::Json<CreateOrderRequest>, // Request body containing order details
+) -> Result<HttpResponse, AppError> {
+    if body.items.is_empty() {
+        return Err(AppError::validation(
+            "An order must contain at least one item",
+        ));
+    }
+
+    if body.items.iter().any(|item| item.quantity <= 0) {
+        return Err(AppError::validation(
+            "Item quantities must be greater than zero",
+        ));
+    }
+
+    let user_id = auth_user.0.sub;
+    let idempotency = Idempotency::from_request(&req, user_id, &*body)?;
+
+    let mut client = pool.get().await?;
+
+    // Dropping the transaction without committing rolls it back.
+    let transaction = client.transaction().await?;
+
+    if let Some(response) = idempotency.replay(&transaction).await? {
+        return Ok(response);
+    }
+
+    let order = place_order(&transaction, user_id, &body).await?;
+
+    let response = idempotency
+        .respond(&transaction, StatusCode::CREATED, &order)
+        .await?;
+
+    transaction.commit().await?;
 
     // Returning the order with the created payment (pending).
-    HttpResponse::Created().json(order)
+    Ok(response)
 }
 
+/// Handler to list the orders of the authenticated user, newest first.
+/// Supports filtering by status and creation date, and offset pagination.
+pub async fn list_orders(
+    pool: web::Data<Pool>,        // Database connection pool
+    auth_user: AuthenticatedUser, // Authenticated user
+    query: web::Query<ListOrdersQuery>,
+) -> Result<HttpResponse, AppError> {
+    let query = query.into_inner();
+    let (limit, offset) = page_bounds(query.limit, query.offset)?;
+
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Err(AppError::validation("from must not be after to"));
+        }
+    }
+
+    let filter = OrderFilter {
+        user_id: auth_user.0.sub,
+        status: query.status,
+        from: query.from,
+        to: query.to,
+        limit,
+        offset,
+    };
+
+    let client = pool.get().await?;
+    let (orders, total) = Order::list_for_user(&client, &filter).await?;
+
+    Ok(HttpResponse::Ok().json(Page::new(orders, total, limit, offset)))
+}
 
-/// Handler to retrieve an order by its ID.
+/// Handler to retrieve an order by its ID, with its items, payment and shipments.
+/// Customers may only retrieve their own orders.
 pub async fn get_order(
-    client: web::Data<Arc<Client>>, // Database client
-    order_id: web::Path<Uuid>,      // Order ID to fetch
-) -> HttpResponse {
-    let order = Order::get_order(&client, *order_id).await;
-
-    match order {
-        // If order is found, return as JSON
-        Ok(Some(order)) => HttpResponse::Ok().json(order),
-        // If no order is found, return a 404 Not Found response
-        Ok(None) => HttpResponse::NotFound().finish(),
-        // If an error occurs during the database query, return a 500 Internal Server Error
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    pool: web::Data<Pool>,        // Database connection pool
+    auth_user: AuthenticatedUser, // Authenticated user
+    order_id: web::Path<Uuid>,    // Order ID to fetch
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    // If no order is found, return a 404 Not Found response
+    let order = Order::get_order(&client, *order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+
+    auth_user.ensure_self_or_admin(order.user_id)?;
+
+    let items = OrderItem::get_order_items(&client, order.id).await?;
+    let payment = Payment::get_payment(&client, order.id).await?;
+    let shipments = Shipment::list_for_order(&client, order.id).await?;
+
+    Ok(HttpResponse::Ok().json(OrderDetails {
+        order,
+        items,
+        payment,
+        shipments,
+    }))
+}
+
+/// Handler to move an order through fulfillment: `fulfilling`, `shipped` or `delivered`.
+/// Restricted to admins. Transitions not allowed from the current status are rejected with
+/// 409, and so is `cancelled`: `POST /orders/{order_id}/cancel` cancels an order along with
+/// its stock, coupon use and payments. Payment and refund statuses cannot be set here, they
+/// only follow the payment flows.
+pub async fn update_order_status(
+    admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,     // Database connection pool
+    order_id: web::Path<Uuid>, // Order ID to update
+    body: web::Json<UpdateOrderStatusRequest>,
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let order = Order::get_order_for_update(&transaction, *order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+
+    // Cancelling also restocks the items, gives the coupon back and voids the payments
+    if body.status == OrderStatus::Cancelled {
+        return Err(AppError::Conflict {
+            message: "Orders are cancelled through the cancel endpoint".to_string(),
+            details: Some(json!({ "cancel": format!("/orders/{}/cancel", order.id) })),
+        });
+    }
+
+    // Payment and refund statuses follow the payments, the refunds and the provider's events
+    if !matches!(
+        body.status,
+        OrderStatus::Fulfilling | OrderStatus::Shipped | OrderStatus::Delivered
+    ) {
+        return Err(AppError::validation(
+            "Only fulfilling, shipped and delivered can be set through this endpoint",
+        ));
+    }
+
+    let order = Order::transition(&transaction, &order, body.status, Some(admin.0.sub)).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(order))
+}
+
+/// Handler to cancel an order.
+/// Customers may cancel their own orders until they are paid; admins may cancel any order
+/// the transition table allows, which excludes orders that have shipped. Orders whose payment
+/// was captured are refused with 409: their payment must be refunded instead.
+///
+/// In the same transaction the stock of every item is restored, the use of its coupon is
+/// given back and pending payments are voided, so a cancelled order never holds inventory.
+/// Authorized payments are voided with the provider once the cancellation is committed; a
+/// void that fails is logged and can be retried with `POST /payments/{payment_id}/void`,
+/// while the cancelled order keeps the payment from being captured.
+pub async fn cancel_order(
+    pool: web::Data<Pool>,                  // Database connection pool
+    gateway: web::Data<dyn PaymentGateway>, // Payment provider, to void authorized payments
+    auth_user: AuthenticatedUser,           // Authenticated user
+    order_id: web::Path<Uuid>,              // Order ID to cancel
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let order = Order::get_order_for_update(&transaction, *order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+
+    auth_user.ensure_self_or_admin(order.user_id)?;
+
+    let unpaid = matches!(
+        order.status,
+        OrderStatus::Pending | OrderStatus::AwaitingPayment
+    );
+    if !unpaid && !auth_user.is_admin() {
+        return Err(AppError::Forbidden(
+            "Only an admin can cancel an order once it has been paid".to_string(),
+        ));
     }
+
+    ensure_no_captured_funds(&transaction, order.id).await?;
+
+    let order = Order::transition(
+        &transaction,
+        &order,
+        OrderStatus::Cancelled,
+        Some(auth_user.0.sub),
+    )
+    .await?;
+
+    OrderItem::restock_order(&transaction, order.id).await?;
+    Coupon::release(&transaction, order.id).await?;
+    let authorized = void_pending_payments(&transaction, order.id).await?;
+
+    transaction.commit().await?;
+
+    for payment in authorized {
+        if let Err(err) = void_authorized_payment(&mut client, gateway.get_ref(), &payment).await {
+            eprintln!(
+                "Failed to void payment {} of cancelled order {}: {:?}",
+                payment.id, order.id, err
+            );
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(order))
 }
 
-/// Handler to delete an order by its ID.
+/// Handler to list the status changes of an order, oldest first.
+/// Customers may only see the history of their own orders.
+pub async fn get_order_history(
+    pool: web::Data<Pool>,        // Database connection pool
+    auth_user: AuthenticatedUser, // Authenticated user
+    order_id: web::Path<Uuid>,    // Order ID whose history is requested
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    let order = Order::get_order(&client, *order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+
+    auth_user.ensure_self_or_admin(order.user_id)?;
+
+    let history = Order::status_history(&client, order.id).await?;
+
+    Ok(HttpResponse::Ok().json(history))
+}
+
+/// Handler to delete an order by its ID. Restricted to admins.
+/// Stock held by the order is returned unless it was already released by a cancellation
+/// or the goods have left the warehouse.
 pub async fn delete_order(
-    client: web::Data<Arc<Client>>, // Database client
-    order_id: web::Path<Uuid>,      // Order ID to delete
-) -> HttpResponse {
-    // Attempt to delete the order and handle any errors.
-    match Order::delete_order(&client, order_id.into_inner()).await {
-        // If the deletion is successful, return a 204 No Content response.
-        Ok(_) => HttpResponse::NoContent().finish(),
-        // If an error occurs, return a 500 Internal Server Error.
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,     // Database connection pool
+    order_id: web::Path<Uuid>, // Order ID to delete
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let order = Order::get_order_for_update(&transaction, *order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+
+    // Orders that could still be cancelled are the ones holding stock and coupon uses
+    if order.status.can_transition_to(OrderStatus::Cancelled) {
+        OrderItem::restock_order(&transaction, order.id).await?;
+        Coupon::release(&transaction, order.id).await?;
+    }
+
+    // Attempt to delete the order.
+    if !Order::delete_order(&transaction, order.id).await? {
+        return Err(AppError::NotFound("Order not found".to_string()));
     }
+
+    transaction.commit().await?;
+
+    // If the deletion is successful, return a 204 No Content response.
+    Ok(HttpResponse::NoContent().finish())
 }
@@ -1,70 +1,142 @@
-use std::sync::Arc;
-
-use actix_web::{web, HttpResponse, Error};
-use rust_decimal::Decimal;
-use tokio_postgres::Client;
-use uuid::Uuid;
+use actix_web::{web, HttpResponse};
+use deadpool_postgres::{Client, GenericClient, Pool};
 use serde::Deserialize;
+use uuid::Uuid;
 
-use crate::models::{order_items_model::OrderItem};
+use crate::{
+    auth::AuthenticatedUser,
+    errors::AppError,
+    models::{
+        order_items_model::OrderItem,
+        order_model::{Order, OrderStatus},
+    },
+};
 
 /// Represents the request body to create an order item, including the product ID, quantity, and price.
 #[derive(Deserialize)]
 pub struct CreateOrderItemRequest {
-    pub product_id: Uuid, // Product ID
-    pub quantity: i32,    // Product quantity
+    pub product_id: Uuid,         // Product ID
+    pub variant_id: Option<Uuid>, // Required for products sold in variants
+    pub quantity: i32,            // Product quantity
 }
 
+// Loads an order and ensures the authenticated user may modify or inspect it.
+async fn authorize_order(
+    client: &Client,
+    auth_user: &AuthenticatedUser,
+    order_id: Uuid,
+) -> Result<Order, AppError> {
+    let order = Order::get_order(client, order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+
+    auth_user.ensure_self_or_admin(order.user_id)?;
+    Ok(order)
+}
+
+// Locks an order whose items are about to change. Only the owner or an admin may change
+// the items, and only while the order is pending: later on the amounts are being paid.
+async fn lock_pending_order(
+    client: &impl GenericClient,
+    auth_user: &AuthenticatedUser,
+    order_id: Uuid,
+) -> Result<Order, AppError> {
+    let order = Order::get_order_for_update(client, order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+
+    auth_user.ensure_self_or_admin(order.user_id)?;
+
+    if order.status != OrderStatus::Pending {
+        return Err(AppError::conflict(format!(
+            "Items can only be changed while the order is pending, it is {}",
+            order.status.as_str()
+        )));
+    }
+
+    Ok(order)
+}
 
 /// Handler to create a new order item.
 pub async fn create_order_item(
-    client: web::Data<Arc<Client>>,
+    pool: web::Data<Pool>,
+    auth_user: AuthenticatedUser,
     order_id: web::Path<Uuid>,
     body: web::Json<CreateOrderItemRequest>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, AppError> {
+    if body.quantity <= 0 {
+        return Err(AppError::validation("Quantity must be greater than zero"));
+    }
+
+    let mut client = pool.get().await?;
+
+    // The item insert and the stock update are applied together or not at all
+    let transaction = client.transaction().await?;
+    lock_pending_order(&transaction, &auth_user, *order_id).await?;
+
     let order_item = OrderItem::create_order_item(
-        &client,
+        &transaction,
         *order_id,
         body.product_id,
+        body.variant_id,
         body.quantity,
     )
-    .await
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await?;
 
-    Ok(HttpResponse::Created().json(order_item))
-}
+    Order::recalculate_totals(&transaction, *order_id).await?;
 
+    transaction.commit().await?;
 
+    Ok(HttpResponse::Created().json(order_item))
+}
 
 /// Handler to retrieve all items for a specific order.
 pub async fn get_order_items(
-    client: web::Data<Arc<Client>>,  // Database client
-    order_id: web::Path<Uuid>,      // Order ID to fetch the items for
-) -> Result<HttpResponse, Error> {
+    pool: web::Data<Pool>,        // Database connection pool
+    auth_user: AuthenticatedUser, // Authenticated user
+    order_id: web::Path<Uuid>,    // Order ID to fetch the items for
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+    authorize_order(&client, &auth_user, *order_id).await?;
+
     // Fetch order items using the order ID
-    let items = OrderItem::get_order_items(&client, *order_id)
-        .await
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let items = OrderItem::get_order_items(&client, *order_id).await?;
 
     // Return the list of items for the order as a JSON response
     Ok(HttpResponse::Ok().json(items))
 }
 
 /// Handler to delete an order item.
+/// The item's quantity is returned to the product stock in the same transaction.
 pub async fn delete_order_item(
-    client: web::Data<Arc<Client>>, // Database client
-    item_id: web::Path<Uuid>,      // Order item ID to delete
-) -> Result<HttpResponse, Error> {
-    // Attempt to delete the order item
-    let success = OrderItem::delete_order_item(&client, *item_id)
-        .await
-        .map_err(actix_web::error::ErrorInternalServerError)?;
-
-    // Return appropriate response based on deletion success
-    if success {
-        Ok(HttpResponse::NoContent().finish()) // No content if deletion is successful
-    } else {
-        Ok(HttpResponse::NotFound().finish()) // Not found if the item does not exist
+    pool: web::Data<Pool>,        // Database connection pool
+    auth_user: AuthenticatedUser, // Authenticated user
+    item_id: web::Path<Uuid>,     // Order item ID to delete
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let item = OrderItem::get_order_item(&transaction, *item_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order item not found".to_string()))?;
+    lock_pending_order(&transaction, &auth_user, item.order_id).await?;
+
+    // Attempt to delete the order item, not found if a concurrent request removed it first
+    if !OrderItem::delete_order_item(&transaction, *item_id).await? {
+        return Err(AppError::NotFound("Order item not found".to_string()));
     }
-}
 
+    OrderItem::restore_stock(
+        &transaction,
+        item.product_id,
+        item.variant_id,
+        item.quantity,
+    )
+    .await?;
+    Order::recalculate_totals(&transaction, item.order_id).await?;
+
+    transaction.commit().await?;
+
+    // No content if deletion is successful
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,114 +1,819 @@
-use std::sync::Arc;
+use std::collections::HashSet;
 
-use crate::{auth::AuthenticatedUser, models::{order_model::Order, payment_model::Payment}};
-use actix_web::{web, HttpResponse, Error};
-use tokio_postgres::Client;
+use crate::{
+    auth::{Admin, AuthenticatedUser, RequireRole},
+    errors::AppError,
+    idempotency::Idempotency,
+    models::{
+        order_items_model::OrderItem,
+        order_model::{Order, OrderStatus},
+        payment_model::{Payment, PaymentStatus},
+        refund_model::{NewRefund, Refund, RefundItem, RefundStatus},
+    },
+    payment_gateway::{with_timeout, AuthorizationRequest, GatewayError, PaymentGateway},
+};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use deadpool_postgres::{Client, GenericClient, Pool};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use uuid::Uuid;
-use serde::Deserialize;
 
 // Represents the request body to create a payment, including the payment method.
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct CreatePaymentRequest {
     pub payment_method: String, // "credit_card", "paypal"
 }
 
-/// Represents the request body to update the payment status.
+/// Represents the request body to update the payment method.
 #[derive(Deserialize)]
 pub struct UpdatePaymentRequest {
-    pub payment_method: String, 
+    pub payment_method: String,
+}
+
+/// Represents the request body to refund a captured payment.
+/// Either an `amount` or the `items` being refunded must be given, not both.
+#[derive(Deserialize)]
+pub struct CreateRefundRequest {
+    pub amount: Option<Decimal>,
+    pub items: Option<Vec<RefundItemRequest>>,
+    #[serde(default)]
+    pub restock: bool, // Put the refunded items back in stock, only with `items`
+    pub reason: Option<String>,
+}
+
+/// Quantity of an order item to refund.
+#[derive(Deserialize)]
+pub struct RefundItemRequest {
+    pub order_item_id: Uuid,
+    pub quantity: i32,
+}
+
+// Locks a payment together with its order.
+// The order is locked first, like every other flow that touches both.
+async fn lock_payment(
+    client: &impl GenericClient,
+    payment_id: Uuid,
+) -> Result<(Order, Payment), AppError> {
+    let not_found = || AppError::NotFound("Payment not found".to_string());
+
+    let payment = Payment::get_payment_by_id(client, payment_id)
+        .await?
+        .ok_or_else(not_found)?;
+    let order = Order::get_order_for_update(client, payment.order_id)
+        .await?
+        .ok_or_else(not_found)?;
+    let payment = Payment::get_payment_for_update(client, payment_id)
+        .await?
+        .ok_or_else(not_found)?;
+
+    Ok((order, payment))
 }
 
+/// Fails with a conflict if a payment of an order holds captured funds. Cancelling such an
+/// order would keep the customer's money, so it must be refunded through
+/// `POST /payments/{payment_id}/refunds` instead.
+pub(crate) async fn ensure_no_captured_funds(
+    client: &impl GenericClient,
+    order_id: Uuid,
+) -> Result<(), AppError> {
+    let captured = Payment::list_for_order(client, order_id)
+        .await?
+        .into_iter()
+        .find(|payment| {
+            matches!(
+                payment.status,
+                PaymentStatus::Paid | PaymentStatus::PartiallyRefunded
+            )
+        });
+
+    if let Some(payment) = captured {
+        return Err(AppError::Conflict {
+            message: "The order has been paid, refund its payment instead of cancelling it"
+                .to_string(),
+            details: Some(json!({
+                "payment_id": payment.id,
+                "refund": format!("/payments/{}/refunds", payment.id),
+            })),
+        });
+    }
+
+    Ok(())
+}
+
+/// Voids the payments of an order that were never sent to the provider, e.g. when the order
+/// is cancelled, and returns the authorized ones. Fails with a conflict while a payment is
+/// authorizing or capturing. Those hold funds with the provider and must
+/// be voided with `void_authorized_payment` once the transaction is committed.
+pub(crate) async fn void_pending_payments(
+    client: &impl GenericClient,
+    order_id: Uuid,
+) -> Result<Vec<Payment>, AppError> {
+    let mut authorized = Vec::new();
+
+    for payment in Payment::list_for_order(client, order_id).await? {
+        match payment.status {
+            // The provider may be acting on it right now
+            PaymentStatus::Authorizing | PaymentStatus::Capturing => {
+                return Err(AppError::Conflict {
+                    message: "A payment of the order is being processed by the provider, \
+                              repeat its authorization or capture first"
+                        .to_string(),
+                    details: Some(json!({
+                        "payment_id": payment.id,
+                        "status": payment.status.as_str(),
+                    })),
+                });
+            }
+            PaymentStatus::Pending => {
+                Payment::set_status(client, payment.id, PaymentStatus::Voided).await?;
+            }
+            PaymentStatus::Authorized => authorized.push(payment),
+            _ => {}
+        }
+    }
+
+    Ok(authorized)
+}
+
+/// Voids an authorized payment with the provider, then records it in its own transaction.
+/// The provider is never called while a transaction is open, so a void it made cannot be
+/// rolled back on our side. Payments left authorized can be voided again with
+/// `POST /payments/{payment_id}/void`.
+pub(crate) async fn void_authorized_payment(
+    client: &mut Client,
+    gateway: &dyn PaymentGateway,
+    payment: &Payment,
+) -> Result<Payment, AppError> {
+    let reference = payment.provider_reference.as_deref().ok_or_else(|| {
+        AppError::Internal(format!(
+            "authorized payment {} has no provider reference",
+            payment.id
+        ))
+    })?;
+
+    with_timeout(gateway.void(reference)).await?;
+
+    let transaction = client.transaction().await?;
+    let (_, payment) = lock_payment(&transaction, payment.id).await?;
+
+    // A webhook may have reported the void in the meantime
+    let payment = if payment.status == PaymentStatus::Authorized {
+        Payment::set_status(&transaction, payment.id, PaymentStatus::Voided).await?
+    } else {
+        payment
+    };
+
+    transaction.commit().await?;
+
+    Ok(payment)
+}
+
+/// Handler to create a payment for an order, e.g. after the previous one failed or was voided.
+/// An order has at most one payment that is neither failed nor voided.
+/// Requests retried with the same `Idempotency-Key` return the payment created the first time.
 pub async fn create_payment(
-    client: web::Data<Arc<Client>>,           // Database client
-    auth_user: AuthenticatedUser,            // Authenticated user
-    order_id: web::Path<Uuid>,               // Associated order ID
-    body: web::Json<CreatePaymentRequest>,   // Request body with payment method
-) -> Result<HttpResponse, Error> {
-    // Validate if the order belongs to the authenticated user
+    req: HttpRequest,
+    pool: web::Data<Pool>,                 // Database connection pool
+    auth_user: AuthenticatedUser,          // Authenticated user
+    order_id: web::Path<Uuid>,             // Associated order ID
+    body: web::Json<CreatePaymentRequest>, // Request body with payment method
+) -> Result<HttpResponse, AppError> {
     let user_id = auth_user.0.sub;
-    let is_owner = Order::verify_order_owner(&client, *order_id, user_id).await;
+    let idempotency = Idempotency::from_request(&req, user_id, &*body)?;
+
+    let mut client = pool.get().await?;
+
+    // Validate if the order belongs to the authenticated user
+    if !Order::verify_order_owner(&client, *order_id, user_id).await? {
+        return Err(AppError::Forbidden(
+            "You do not have permission to access this order".to_string(),
+        ));
+    }
+
+    let transaction = client.transaction().await?;
 
-    if let Err(_) | Ok(false) = is_owner {
-        return Ok(HttpResponse::Forbidden().body("You do not have permission to access this order."));
+    if let Some(response) = idempotency.replay(&transaction).await? {
+        return Ok(response);
+    }
+
+    let order = Order::get_order_for_update(&transaction, *order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+    if !matches!(
+        order.status,
+        OrderStatus::Pending | OrderStatus::AwaitingPayment
+    ) {
+        return Err(AppError::conflict(format!(
+            "Cannot create a payment for an order that is {}",
+            order.status.as_str()
+        )));
     }
 
     // Create the payment
-    let payment = Payment::create_payment(
-        &client,
-        *order_id,
-        &body.payment_method,
-    )
-    .await
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    let payment = Payment::create_payment(&transaction, *order_id, &body.payment_method).await?;
 
-    Ok(HttpResponse::Created().json(payment))
-}
+    let response = idempotency
+        .respond(&transaction, StatusCode::CREATED, &payment)
+        .await?;
 
+    transaction.commit().await?;
 
+    Ok(response)
+}
 
 /// Handler to retrieve the payment for a specific order.
 pub async fn get_payment(
-    client: web::Data<Arc<Client>>,  // Database client
-    auth_user: AuthenticatedUser,   // Authenticated user
-    order_id: web::Path<Uuid>,      // Order ID to fetch the payment for
-) -> Result<HttpResponse, Error> {
+    pool: web::Data<Pool>,        // Database connection pool
+    auth_user: AuthenticatedUser, // Authenticated user
+    order_id: web::Path<Uuid>,    // Order ID to fetch the payment for
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
     // validate user
     let user_id = auth_user.0.sub;
-    let is_owner = Order::verify_order_owner(&client, *order_id, user_id).await;
-
-    if let Err(_) | Ok(false) = is_owner {
-        return Ok(HttpResponse::Forbidden().body("You do not have permission to access this order."));
+    if !Order::verify_order_owner(&client, *order_id, user_id).await? {
+        return Err(AppError::Forbidden(
+            "You do not have permission to access this order".to_string(),
+        ));
     }
 
     // get payment
     let payment = Payment::get_payment(&client, *order_id)
-        .await
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+        .await?
+        .ok_or_else(|| AppError::NotFound("Payment not found".to_string()))?;
 
-    if let Some(payment) = payment {
-        Ok(HttpResponse::Ok().json(payment))
-    } else {
-        Ok(HttpResponse::NotFound().finish())
-    }
+    Ok(HttpResponse::Ok().json(payment))
 }
 
-
-/// Handler to update the payment status.
+/// Handler to change the payment method of a payment that has not been authorized yet.
+/// The status of a payment is only changed through the provider.
 pub async fn update_payment(
-    client: web::Data<Arc<Client>>,        // Database client
-    auth_user: AuthenticatedUser,         // Authenticated user
-    payment_id: web::Path<Uuid>,          // Payment ID
+    pool: web::Data<Pool>,                 // Database connection pool
+    auth_user: AuthenticatedUser,          // Authenticated user
+    payment_id: web::Path<Uuid>,           // Payment ID
     body: web::Json<UpdatePaymentRequest>, // Request body
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
     // validate user
     let user_id = auth_user.0.sub;
+    if !Payment::verify_payment_owner(&client, &payment_id, user_id).await? {
+        return Err(AppError::Forbidden(
+            "You do not have permission to access this payment".to_string(),
+        ));
+    }
 
-    let is_owner = Payment::verify_payment_owner(&client, &payment_id, user_id).await;
+    // update payment method
+    if !Payment::update_payment_method(&client, *payment_id, &body.payment_method).await? {
+        return Err(AppError::conflict(
+            "The payment method can only be changed while the payment is pending",
+        ));
+    }
 
-    if let Err(_) | Ok(false) = is_owner {
-        return Ok(HttpResponse::Forbidden().body("You do not have permission to access this payment."));
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Records that the provider declined the call a payment is waiting on, unless another
+// attempt recorded an answer in the meantime. The customer may try again.
+async fn record_decline(
+    client: &mut Client,
+    payment_id: Uuid,
+    waiting_on: PaymentStatus,
+) -> Result<(), AppError> {
+    let transaction = client.transaction().await?;
+
+    let (_, payment) = lock_payment(&transaction, payment_id).await?;
+    if payment.status == waiting_on {
+        Payment::set_status(&transaction, payment.id, PaymentStatus::Failed).await?;
     }
 
-    // update payment method
-    let method_updated = Payment::update_payment_method(&client, *payment_id, &body.payment_method)
-        .await
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Handler to authorize a payment with the payment provider.
+/// The payment is priced from the order's grand total, and the order moves to
+/// awaiting_payment. A declined payment is marked as failed and may be authorized again.
+///
+/// The payment is committed as authorizing before the provider is called, and the provider's
+/// answer is recorded in a new transaction. If the answer is lost, e.g. on a timeout, the
+/// payment stays authorizing and the request can be repeated: the provider recognises the
+/// payment ID and does not reserve the funds twice.
+pub async fn authorize_payment(
+    pool: web::Data<Pool>,                  // Database connection pool
+    gateway: web::Data<dyn PaymentGateway>, // Configured payment provider
+    auth_user: AuthenticatedUser,           // Authenticated user
+    payment_id: web::Path<Uuid>,            // Payment ID
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let (order, payment) = lock_payment(&transaction, *payment_id).await?;
+    auth_user.ensure_self_or_admin(order.user_id)?;
+
+    if !matches!(
+        payment.status,
+        PaymentStatus::Pending | PaymentStatus::Failed | PaymentStatus::Authorizing
+    ) {
+        return Err(AppError::conflict(format!(
+            "Only pending or failed payments can be authorized, this one is {}",
+            payment.status.as_str()
+        )));
+    }
+    if !matches!(
+        order.status,
+        OrderStatus::Pending | OrderStatus::AwaitingPayment
+    ) {
+        return Err(AppError::conflict(format!(
+            "The order cannot be paid while it is {}",
+            order.status.as_str()
+        )));
+    }
+    if order.totals.grand_total <= Decimal::ZERO {
+        return Err(AppError::validation("The order has nothing to pay"));
+    }
+
+    // From here on the items of the order cannot change
+    let payment =
+        Payment::begin_authorization(&transaction, payment.id, order.totals.grand_total).await?;
+    let order = if order.status == OrderStatus::Pending {
+        Order::transition(
+            &transaction,
+            &order,
+            OrderStatus::AwaitingPayment,
+            Some(auth_user.0.sub),
+        )
+        .await?
+    } else {
+        order
+    };
+
+    transaction.commit().await?;
+
+    let request = AuthorizationRequest {
+        payment_id: payment.id,
+        amount: payment.amount,
+        currency: &order.totals.currency,
+        payment_method: &payment.payment_method,
+    };
+
+    let reference = match with_timeout(gateway.authorize(request)).await {
+        Ok(reference) => reference,
+        Err(err @ GatewayError::Declined(_)) => {
+            record_decline(&mut client, payment.id, PaymentStatus::Authorizing).await?;
+            return Err(err.into());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let transaction = client.transaction().await?;
+
+    // A concurrent attempt may have recorded the authorization already
+    let (_, payment) = lock_payment(&transaction, payment.id).await?;
+    let payment = if payment.status == PaymentStatus::Authorizing {
+        Payment::mark_authorized(
+            &transaction,
+            payment.id,
+            gateway.name(),
+            &reference,
+            payment.amount,
+        )
+        .await?
+    } else {
+        payment
+    };
+
+    transaction.commit().await?;
 
-    if method_updated {
-        Payment::update_payment_status(&client, *payment_id)
-            .await
-            .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(payment))
+}
+
+/// Handler to capture an authorized payment.
+/// This is the only way a payment becomes paid, and it moves the order to paid as well.
+///
+/// The payment is committed as capturing before the provider is called, and the provider's
+/// answer is recorded in a new transaction. If the answer is lost, the payment stays
+/// capturing until the request is repeated or the provider's `payment.captured` event
+/// arrives; capturing the same authorization again does not collect the funds twice.
+pub async fn capture_payment(
+    pool: web::Data<Pool>,                  // Database connection pool
+    gateway: web::Data<dyn PaymentGateway>, // Configured payment provider
+    auth_user: AuthenticatedUser,           // Authenticated user
+    payment_id: web::Path<Uuid>,            // Payment ID
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let (order, payment) = lock_payment(&transaction, *payment_id).await?;
+    auth_user.ensure_self_or_admin(order.user_id)?;
+
+    let reference = match (payment.status, payment.provider_reference.as_deref()) {
+        (PaymentStatus::Authorized | PaymentStatus::Capturing, Some(reference)) => {
+            reference.to_string()
+        }
+        _ => {
+            return Err(AppError::conflict(format!(
+                "Only authorized payments can be captured, this one is {}",
+                payment.status.as_str()
+            )))
+        }
+    };
+
+    // The order cannot change once it awaits payment, but never capture a stale amount
+    if payment.amount != order.totals.grand_total {
+        return Err(AppError::conflict(
+            "The payment amount no longer matches the order total",
+        ));
+    }
+
+    // Check the order can become paid before any money moves
+    if !order.status.can_transition_to(OrderStatus::Paid) {
+        return Err(AppError::conflict(format!(
+            "The order cannot be paid while it is {}",
+            order.status.as_str()
+        )));
+    }
+
+    let payment = if payment.status == PaymentStatus::Authorized {
+        Payment::set_status(&transaction, payment.id, PaymentStatus::Capturing).await?
+    } else {
+        payment
+    };
+
+    transaction.commit().await?;
+
+    match with_timeout(gateway.capture(&reference, payment.amount)).await {
+        Ok(()) => {}
+        Err(err @ GatewayError::Declined(_)) => {
+            record_decline(&mut client, payment.id, PaymentStatus::Capturing).await?;
+            return Err(err.into());
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    let transaction = client.transaction().await?;
 
-        Ok(HttpResponse::NoContent().finish())
+    // A concurrent attempt or the provider's event may have recorded the capture already.
+    // The order cannot be cancelled while its payment is capturing, so it can become paid.
+    let (order, payment) = lock_payment(&transaction, payment.id).await?;
+    let payment = if payment.status == PaymentStatus::Capturing {
+        let payment = Payment::set_status(&transaction, payment.id, PaymentStatus::Paid).await?;
+        Order::transition(
+            &transaction,
+            &order,
+            OrderStatus::Paid,
+            Some(auth_user.0.sub),
+        )
+        .await?;
+        payment
     } else {
-        Ok(HttpResponse::NotFound().finish())
+        payment
+    };
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(payment))
+}
+
+/// Handler to void an authorized payment with the provider. Restricted to admins.
+/// Used for payments of cancelled orders whose void failed, as cancelling does not wait for
+/// the provider.
+pub async fn void_payment(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,                  // Database connection pool
+    gateway: web::Data<dyn PaymentGateway>, // Configured payment provider
+    payment_id: web::Path<Uuid>,            // Payment ID
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+
+    let payment = Payment::get_payment_by_id(&client, *payment_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Payment not found".to_string()))?;
+
+    if payment.status != PaymentStatus::Authorized {
+        return Err(AppError::conflict(format!(
+            "Only authorized payments can be voided, this one is {}",
+            payment.status.as_str()
+        )));
+    }
+
+    let payment = void_authorized_payment(&mut client, gateway.get_ref(), &payment).await?;
+
+    Ok(HttpResponse::Ok().json(payment))
+}
+
+// Prices the items of a refund request, checking they belong to the order and have not
+// already been refunded. Units are refunded at their share of what the line was charged,
+// after its discount and with any tax added to it.
+async fn refund_items(
+    client: &impl GenericClient,
+    order: &Order,
+    requested: &[RefundItemRequest],
+) -> Result<Vec<(OrderItem, RefundItem)>, AppError> {
+    let mut order_items = OrderItem::get_order_items(client, order.id).await?;
+    let refunded = Refund::refunded_totals(client, order.id).await?;
+
+    let mut seen = HashSet::new();
+    let mut items = Vec::with_capacity(requested.len());
+
+    for request in requested {
+        if !seen.insert(request.order_item_id) {
+            return Err(AppError::validation(
+                "Each order item may only be listed once",
+            ));
+        }
+        if request.quantity <= 0 {
+            return Err(AppError::validation(
+                "Refunded quantities must be greater than zero",
+            ));
+        }
+
+        let position = order_items
+            .iter()
+            .position(|item| item.id == request.order_item_id)
+            .ok_or_else(|| {
+                AppError::validation(format!(
+                    "Order item {} does not belong to the order",
+                    request.order_item_id
+                ))
+            })?;
+        let order_item = order_items.swap_remove(position);
+
+        let (refunded_quantity, refunded_amount) = refunded
+            .get(&order_item.id)
+            .copied()
+            .unwrap_or((0, Decimal::ZERO));
+        let available = order_item.quantity - refunded_quantity;
+        if request.quantity > available {
+            return Err(AppError::Conflict {
+                message: "The quantity exceeds what can still be refunded for the item".to_string(),
+                details: Some(json!({
+                    "order_item_id": order_item.id,
+                    "requested": request.quantity,
+                    "refundable": available,
+                })),
+            });
+        }
+
+        let refund_item = RefundItem {
+            order_item_id: order_item.id,
+            quantity: request.quantity,
+            amount: order_item.refund_amount(
+                order.totals.prices_include_tax,
+                request.quantity,
+                refunded_quantity,
+                refunded_amount,
+            ),
+        };
+        items.push((order_item, refund_item));
+    }
+
+    Ok(items)
+}
+
+/// Handler to refund a captured payment, fully or partially. Restricted to admins.
+///
+/// The refund covers either an arbitrary amount or specific order items, optionally putting
+/// them back in stock. The sum of all refunds never exceeds the captured amount. The payment
+/// and the order become refunded, or partially_refunded while money is left.
+///
+/// The refund is committed as pending before the provider is called. If the provider does not
+/// answer, it stays pending, still counting against the payment, until `retry_refund` settles it.
+pub async fn create_refund(
+    admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,                  // Database connection pool
+    gateway: web::Data<dyn PaymentGateway>, // Configured payment provider
+    payment_id: web::Path<Uuid>,            // Payment ID
+    body: web::Json<CreateRefundRequest>,
+) -> Result<HttpResponse, AppError> {
+    let body = body.into_inner();
+
+    match (&body.amount, &body.items) {
+        (Some(_), None) if body.restock => {
+            return Err(AppError::validation(
+                "Only refunds of items can be restocked",
+            ));
+        }
+        (Some(amount), None) if *amount <= Decimal::ZERO || amount.scale() > 2 => {
+            return Err(AppError::validation(
+                "amount must be positive with at most two decimal places",
+            ));
+        }
+        (None, Some(items)) if items.is_empty() => {
+            return Err(AppError::validation(
+                "A refund must contain at least one item",
+            ));
+        }
+        (Some(_), None) | (None, Some(_)) => {}
+        _ => return Err(AppError::validation("Either amount or items must be given")),
     }
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let (order, payment) = lock_payment(&transaction, *payment_id).await?;
+
+    let refundable = payment.refundable_amount();
+    if refundable <= Decimal::ZERO {
+        return Err(AppError::conflict(format!(
+            "Only captured payments with money left can be refunded, this one is {}",
+            payment.status.as_str()
+        )));
+    }
+
+    let items = match &body.items {
+        Some(requested) => refund_items(&transaction, &order, requested).await?,
+        None => Vec::new(),
+    };
+    let amount = match body.amount {
+        Some(amount) => amount,
+        None => items.iter().map(|(_, item)| item.amount).sum(),
+    };
+
+    // Pending refunds may already have been paid out by the provider
+    let pending = Refund::pending_amount(&transaction, payment.id).await?;
+    if amount > refundable - pending {
+        return Err(AppError::Conflict {
+            message: "The refund exceeds the amount left on the payment".to_string(),
+            details: Some(json!({
+                "requested": amount,
+                "refundable": refundable - pending,
+                "pending": pending,
+            })),
+        });
+    }
+
+    let refund = Refund::create(
+        &transaction,
+        NewRefund {
+            id: Uuid::new_v4(),
+            payment_id: payment.id,
+            amount,
+            reason: body.reason.as_deref(),
+            restocked: body.restock,
+            created_by: Some(admin.0.sub),
+            items: items.into_iter().map(|(_, item)| item).collect(),
+        },
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    let refund = settle_refund(
+        &mut client,
+        gateway.get_ref(),
+        &payment,
+        refund,
+        Some(admin.0.sub),
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().json(refund))
 }
 
+/// Handler to retry a pending refund with the provider. Restricted to admins.
+///
+/// Refunds stay pending when the provider did not answer, or when its answer could not be
+/// recorded. The provider is called again with the same refund ID, so the money is never
+/// returned twice.
+pub async fn retry_refund(
+    admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,                  // Database connection pool
+    gateway: web::Data<dyn PaymentGateway>, // Configured payment provider
+    path: web::Path<(Uuid, Uuid)>,          // Payment ID and refund ID
+) -> Result<HttpResponse, AppError> {
+    let (payment_id, refund_id) = path.into_inner();
+    let mut client = pool.get().await?;
 
+    let payment = Payment::get_payment_by_id(&client, payment_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Payment not found".to_string()))?;
+    let refund = Refund::get(&client, payment_id, refund_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Refund not found".to_string()))?;
 
+    if refund.status != RefundStatus::Pending {
+        return Err(AppError::conflict(format!(
+            "Only pending refunds can be retried, this one is {}",
+            refund.status.as_str()
+        )));
+    }
+
+    let refund = settle_refund(
+        &mut client,
+        gateway.get_ref(),
+        &payment,
+        refund,
+        Some(admin.0.sub),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(refund))
+}
 
+/// Asks the provider to return the money of a committed pending refund, then records the
+/// outcome in its own transaction: the refund succeeds, its items are restocked if requested,
+/// and the payment and order become refunded or partially_refunded. A declined refund is
+/// marked as failed. Refunds the provider did not answer for stay pending and can be retried.
+pub(crate) async fn settle_refund(
+    client: &mut Client,
+    gateway: &dyn PaymentGateway,
+    payment: &Payment,
+    refund: Refund,
+    admin_id: Option<Uuid>,
+) -> Result<Refund, AppError> {
+    let reference = payment.provider_reference.as_deref().ok_or_else(|| {
+        AppError::Internal(format!(
+            "captured payment {} has no provider reference",
+            payment.id
+        ))
+    })?;
 
+    let provider_reference =
+        match with_timeout(gateway.refund(reference, refund.id, refund.amount)).await {
+            Ok(provider_reference) => provider_reference,
+            Err(err @ GatewayError::Declined(_)) => {
+                Refund::fail(&*client, refund.id).await?;
+                return Err(err.into());
+            }
+            Err(err) => return Err(err.into()),
+        };
 
+    let transaction = client.transaction().await?;
 
+    let (order, payment) = lock_payment(&transaction, payment.id).await?;
+    let refund = Refund::get_for_update(&transaction, payment.id, refund.id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Refund not found".to_string()))?;
 
+    // A concurrent retry may have recorded the refund already
+    if refund.status != RefundStatus::Pending {
+        return Ok(refund);
+    }
+
+    // Restock in product and variant order, like every other flow that locks products
+    if refund.restocked {
+        let order_items = OrderItem::get_order_items(&transaction, order.id).await?;
+        let mut restocked: Vec<(Uuid, Option<Uuid>, i32)> = refund
+            .items
+            .iter()
+            .filter_map(|refund_item| {
+                order_items
+                    .iter()
+                    .find(|order_item| order_item.id == refund_item.order_item_id)
+                    .map(|order_item| {
+                        (
+                            order_item.product_id,
+                            order_item.variant_id,
+                            refund_item.quantity,
+                        )
+                    })
+            })
+            .collect();
+        restocked.sort_by_key(|(product_id, variant_id, _)| (*product_id, *variant_id));
+
+        for (product_id, variant_id, quantity) in restocked {
+            OrderItem::restore_stock(&transaction, product_id, variant_id, quantity).await?;
+        }
+    }
+
+    let amount = refund.amount;
+    let refund = Refund::complete(&transaction, refund, &provider_reference).await?;
+    let payment = Payment::add_refund(&transaction, payment.id, amount).await?;
+
+    let order_status = if payment.status == PaymentStatus::Refunded {
+        OrderStatus::Refunded
+    } else {
+        OrderStatus::PartiallyRefunded
+    };
+    if order.status != order_status && order.status.can_transition_to(order_status) {
+        Order::transition(&transaction, &order, order_status, admin_id).await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(refund)
+}
+
+/// Handler to list the refunds of a payment.
+/// Customers may only see the refunds of their own payments.
+pub async fn get_refunds(
+    pool: web::Data<Pool>,        // Database connection pool
+    auth_user: AuthenticatedUser, // Authenticated user
+    payment_id: web::Path<Uuid>,  // Payment ID
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    let is_owner = Payment::verify_payment_owner(&client, &payment_id, auth_user.0.sub).await?;
+    if !is_owner && !auth_user.is_admin() {
+        return Err(AppError::Forbidden(
+            "You do not have permission to access this payment".to_string(),
+        ));
+    }
+
+    let refunds = Refund::list_for_payment(&client, *payment_id).await?;
+
+    Ok(HttpResponse::Ok().json(refunds))
+}
@@ -1,12 +1,19 @@
-use std::sync::Arc;
-
-use actix_web::{web, HttpResponse};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use deadpool_postgres::Pool;
 use rust_decimal::Decimal;
-use serde::Deserialize;
-use tokio_postgres::Client;
+use serde::{Deserialize, Deserializer};
+use serde_json::json;
 use uuid::Uuid;
 
-use crate::models::product_model::Product;
+use crate::{
+    auth::{Admin, RequireRole},
+    errors::AppError,
+    models::product_model::{
+        NewProduct, Product, ProductChanges, ProductFilter, ProductSort, ProductUpdate,
+    },
+    pagination::{page_bounds, Page, SortOrder},
+    tax::is_valid_tax_class,
+};
 
 #[derive(Deserialize)]
 pub struct CreateProductRequest {
@@ -14,56 +21,307 @@ pub struct CreateProductRequest {
     pub description: Option<String>,
     pub price: Decimal,
     pub stock: i32,
+    pub tax_class: Option<String>, // Defaults to "standard"
+    #[serde(default)]
+    pub weight_kg: Decimal, // Package of one unit, used for shipping costs
+    #[serde(default)]
+    pub length_cm: Decimal,
+    #[serde(default)]
+    pub width_cm: Decimal,
+    #[serde(default)]
+    pub height_cm: Decimal,
+}
+
+/// Body of a product update. Omitted fields are left unchanged, and an explicit
+/// `"description": null` clears the description.
+#[derive(Deserialize)]
+pub struct UpdateProductRequest {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub description: Option<Option<String>>,
+    pub price: Option<Decimal>,
+    pub stock: Option<i32>,
+    pub tax_class: Option<String>,
+    pub weight_kg: Option<Decimal>,
+    pub length_cm: Option<Decimal>,
+    pub width_cm: Option<Decimal>,
+    pub height_cm: Option<Decimal>,
 }
 
+// Tells an explicit `null` apart from a missing field: present fields are always wrapped in `Some`.
+pub(crate) fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+// Rejects tax class names that rates could not be keyed by.
+fn validate_tax_class(tax_class: &str) -> Result<(), AppError> {
+    if !is_valid_tax_class(tax_class) {
+        return Err(AppError::validation(
+            "tax_class must be 1 to 32 lower case letters, digits or underscores",
+        ));
+    }
+
+    Ok(())
+}
+
+// Rejects negative package weights and dimensions.
+fn validate_package(measures: &[Option<Decimal>]) -> Result<(), AppError> {
+    if measures.iter().flatten().any(Decimal::is_sign_negative) {
+        return Err(AppError::validation(
+            "Weight and dimensions must not be negative",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Query string accepted by the product listing.
+#[derive(Deserialize)]
+pub struct ListProductsQuery {
+    pub min_price: Option<Decimal>,
+    pub max_price: Option<Decimal>,
+    pub in_stock: Option<bool>,
+    pub name: Option<String>,
+    pub sort: Option<ProductSort>, // "price", "name" or "created_at" (default)
+    pub order: Option<SortOrder>,  // "asc" or "desc" (default)
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Query string accepted by the product search.
+#[derive(Deserialize)]
+pub struct SearchProductsQuery {
+    #[serde(default)]
+    pub q: String, // Words to look for in names and descriptions, the last ones possibly partial
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Handler function to create a product. Restricted to admins.
 pub async fn create_product(
-    client: web::Data<Arc<Client>>, // Database client for interacting with the database
+    _admin: RequireRole<Admin>, // Only admins may manage the catalog
+    pool: web::Data<Pool>,      // Connection pool for interacting with the database
     product_data: web::Json<CreateProductRequest>, // The data submitted in the product creation request
-) -> HttpResponse {
+) -> Result<HttpResponse, AppError> {
+    let tax_class = product_data.tax_class.as_deref().unwrap_or("standard");
+    validate_tax_class(tax_class)?;
+    validate_package(&[
+        Some(product_data.weight_kg),
+        Some(product_data.length_cm),
+        Some(product_data.width_cm),
+        Some(product_data.height_cm),
+    ])?;
+
+    let client = pool.get().await?;
 
     // Attempt to create the product in the database
     let product = Product::create_product(
         &client,
-        &product_data.name,
-        product_data.description.as_deref(), // Converte Option<String> para Option<&str>
-        product_data.price,
-        product_data.stock,
+        &NewProduct {
+            name: &product_data.name,
+            description: product_data.description.as_deref(), // Converte Option<String> para Option<&str>
+            price: product_data.price,
+            stock: product_data.stock,
+            tax_class,
+            weight_kg: product_data.weight_kg,
+            length_cm: product_data.length_cm,
+            width_cm: product_data.width_cm,
+            height_cm: product_data.height_cm,
+        },
     )
-    .await;
-
-    match product {
-        // If product creation is successful, return the product data as JSON in the response
-        Ok(product) => HttpResponse::Ok().json(product),
-        // If an error occurs during product creation, log the error and return a 500 internal server error
-        Err(e) => {
-            eprintln!("Error creating product: {:?}", e); // Log the error details
-            HttpResponse::InternalServerError().finish() // Send a generic internal server error response
-        }
-    }
+    .await?;
+
+    // Return the product data as JSON in the response
+    Ok(HttpResponse::Ok().json(product))
 }
 
-pub async fn get_product(client: web::Data<Arc<Client>>, product_id: web::Path<Uuid>) -> HttpResponse {
+pub async fn get_product(
+    pool: web::Data<Pool>,
+    product_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
     // Call the `get_product` method from the product model to fetch the product data
-    let product = Product::get_product(&client, product_id.into_inner()).await;
-
-    match product {
-        // If the product is found, return the product data as JSON
-        Ok(Some(product)) => HttpResponse::Ok().json(product),
-        // If no product is found with the given ID, return a 404 response
-        Ok(None) => HttpResponse::NotFound().finish(),
-        // If there's an error during the query, return an internal server error response
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    let product = Product::get_product(&client, product_id.into_inner())
+        .await?
+        .ok_or_else(|| AppError::NotFound("Product not found".to_string()))?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, product.etag()))
+        .json(product))
+}
+
+// Reads the version the client expects from the `If-Match` header.
+// Returns `None` for `*`, which matches whatever version is current.
+fn expected_version(req: &HttpRequest) -> Result<Option<i32>, AppError> {
+    let if_match = req
+        .headers()
+        .get(header::IF_MATCH)
+        .ok_or_else(|| {
+            AppError::PreconditionRequired(
+                "The If-Match header is required to update a product".to_string(),
+            )
+        })?
+        .to_str()
+        .map_err(|_| AppError::validation("Invalid If-Match header"))?
+        .trim();
+
+    if if_match == "*" {
+        return Ok(None);
     }
+
+    // Only strong ETags produced by `Product::etag` are accepted
+    if_match
+        .strip_prefix('"')
+        .and_then(|tag| tag.strip_suffix('"'))
+        .and_then(|version| version.parse().ok())
+        .map(Some)
+        .ok_or_else(|| AppError::validation("If-Match must be an ETag returned by the API"))
+}
+
+/// Handler function to update a product, used for both PUT and PATCH.
+/// Only the fields present in the body are changed. The request must carry the product's
+/// ETag in `If-Match`; if the product changed since it was read the update is rejected with 412.
+/// Restricted to admins.
+pub async fn update_product(
+    _admin: RequireRole<Admin>,
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    product_id: web::Path<Uuid>,
+    product_data: web::Json<UpdateProductRequest>,
+) -> Result<HttpResponse, AppError> {
+    let expected_version = expected_version(&req)?;
+    let product_data = product_data.into_inner();
+
+    if product_data
+        .name
+        .as_deref()
+        .is_some_and(|name| name.trim().is_empty())
+    {
+        return Err(AppError::validation("name must not be empty"));
+    }
+    if product_data
+        .price
+        .is_some_and(|price| price.is_sign_negative())
+    {
+        return Err(AppError::validation("price must not be negative"));
+    }
+    if product_data.stock.is_some_and(|stock| stock < 0) {
+        return Err(AppError::validation("stock must not be negative"));
+    }
+    if let Some(tax_class) = &product_data.tax_class {
+        validate_tax_class(tax_class)?;
+    }
+    validate_package(&[
+        product_data.weight_kg,
+        product_data.length_cm,
+        product_data.width_cm,
+        product_data.height_cm,
+    ])?;
+
+    let changes = ProductChanges {
+        name: product_data.name,
+        description: product_data.description,
+        price: product_data.price,
+        stock: product_data.stock,
+        tax_class: product_data.tax_class,
+        weight_kg: product_data.weight_kg,
+        length_cm: product_data.length_cm,
+        width_cm: product_data.width_cm,
+        height_cm: product_data.height_cm,
+    };
+
+    let client = pool.get().await?;
+
+    match Product::update_product(&client, product_id.into_inner(), &changes, expected_version)
+        .await?
+    {
+        ProductUpdate::Updated(product) => Ok(HttpResponse::Ok()
+            .insert_header((header::ETAG, product.etag()))
+            .json(product)),
+        ProductUpdate::NotFound => Err(AppError::NotFound("Product not found".to_string())),
+        ProductUpdate::VersionMismatch { current_version } => Err(AppError::PreconditionFailed {
+            message: "The product was modified by another request".to_string(),
+            details: Some(json!({ "current_version": current_version })),
+        }),
+    }
+}
+
+/// Builds the catalog filter from the query string of a product listing, restricted to a
+/// category and its subcategories if `category_id` is given.
+pub(crate) fn product_filter(
+    query: ListProductsQuery,
+    category_id: Option<Uuid>,
+) -> Result<ProductFilter, AppError> {
+    let (limit, offset) = page_bounds(query.limit, query.offset)?;
+
+    if let (Some(min_price), Some(max_price)) = (query.min_price, query.max_price) {
+        if min_price > max_price {
+            return Err(AppError::validation("min_price must not exceed max_price"));
+        }
+    }
+
+    Ok(ProductFilter {
+        min_price: query.min_price,
+        max_price: query.max_price,
+        in_stock: query.in_stock.unwrap_or(false),
+        category_id,
+        name: query.name,
+        sort: query.sort.unwrap_or_default(),
+        order: query.order.unwrap_or_default(),
+        limit,
+        offset,
+    })
+}
+
+/// Handler function to browse the catalog.
+/// Supports filtering by price range, availability and name, sorting and offset pagination.
+pub async fn list_products(
+    pool: web::Data<Pool>,
+    query: web::Query<ListProductsQuery>,
+) -> Result<HttpResponse, AppError> {
+    let filter = product_filter(query.into_inner(), None)?;
+
+    let client = pool.get().await?;
+    let (products, total) = Product::list(&client, &filter).await?;
+
+    Ok(HttpResponse::Ok().json(Page::new(products, total, filter.limit, filter.offset)))
+}
+
+/// Handler function to search the catalog by name and description.
+/// Results are ranked by relevance, highlight the matched words and are paginated like the
+/// product listing.
+pub async fn search_products(
+    pool: web::Data<Pool>,
+    query: web::Query<SearchProductsQuery>,
+) -> Result<HttpResponse, AppError> {
+    let (limit, offset) = page_bounds(query.limit, query.offset)?;
+
+    let client = pool.get().await?;
+    let (hits, total) = Product::search(&client, &query.q, limit, offset).await?;
+
+    Ok(HttpResponse::Ok().json(Page::new(hits, total, limit, offset)))
 }
 
 /// Handler function to delete a product by their ID.
 /// This function deletes the product record from the database based on the provided ID.
-pub async fn delete_product(product_id: web::Path<Uuid>, client: web::Data<Arc<Client>>) -> HttpResponse {
+/// Restricted to admins.
+pub async fn delete_product(
+    _admin: RequireRole<Admin>,
+    product_id: web::Path<Uuid>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
     // Call the `delete_product` method from the product model to delete the product
-    match Product::delete_product(&client, product_id.into_inner()).await {
-        // If the deletion is successful, return a 204 No Content response
-        Ok(_) => HttpResponse::NoContent().finish(),
-        // If an error occurs during deletion, return an internal server error
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    if !Product::delete_product(&client, product_id.into_inner()).await? {
+        return Err(AppError::NotFound("Product not found".to_string()));
     }
+
+    // If the deletion is successful, return a 204 No Content response
+    Ok(HttpResponse::NoContent().finish())
 }
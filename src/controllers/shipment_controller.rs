@@ -0,0 +1,314 @@
+use std::collections::HashSet;
+
+use actix_web::{web, HttpResponse};
+use deadpool_postgres::{GenericClient, Pool};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    auth::{Admin, AuthenticatedUser, RequireRole},
+    errors::AppError,
+    models::{
+        order_items_model::OrderItem,
+        order_model::{Order, OrderStatus},
+        refund_model::Refund,
+        shipment_model::{Shipment, ShipmentChanges, ShipmentItem, ShipmentStatus},
+    },
+};
+
+/// Represents the request body to create a shipment.
+#[derive(Deserialize)]
+pub struct CreateShipmentRequest {
+    pub carrier: Option<String>,
+    pub tracking_number: Option<String>,
+    pub items: Option<Vec<ShipmentItem>>, // Defaults to every unit not shipped yet
+    pub status: Option<ShipmentStatus>,   // Defaults to pending
+}
+
+/// Represents the request body to update a shipment.
+#[derive(Deserialize)]
+pub struct UpdateShipmentRequest {
+    pub carrier: Option<String>,
+    pub tracking_number: Option<String>,
+    pub status: Option<ShipmentStatus>, // Can only move forward
+}
+
+// Order states an order moves through as its shipments progress.
+const FULFILLMENT: [OrderStatus; 3] = [
+    OrderStatus::Fulfilling,
+    OrderStatus::Shipped,
+    OrderStatus::Delivered,
+];
+
+// Trims an optional text field, treating a blank value as absent.
+fn trimmed(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+// Returns how many units of each item of an order still need a shipment: the units ordered,
+// less those refunded and those already in a shipment.
+async fn unshipped_quantities(
+    client: &impl GenericClient,
+    order_id: Uuid,
+) -> Result<Vec<ShipmentItem>, AppError> {
+    let items = OrderItem::get_order_items(client, order_id).await?;
+    let refunded = Refund::refunded_quantities(client, order_id).await?;
+    let shipped = Shipment::quantities(client, order_id).await?;
+
+    Ok(items
+        .iter()
+        .map(|item| {
+            let refunded = refunded.get(&item.id).copied().unwrap_or_default();
+            let assigned = shipped
+                .get(&item.id)
+                .map_or(0, |quantities| quantities.assigned);
+            ShipmentItem {
+                order_item_id: item.id,
+                quantity: (item.quantity - refunded - assigned).max(0),
+            }
+        })
+        .collect())
+}
+
+/// Moves a locked order along `fulfilling → shipped → delivered` to match its shipments:
+/// shipped once every unit not refunded has left, delivered once every such unit arrived.
+/// Every step is recorded in the order's history, in order, even when a single change of
+/// the shipments skips several of them. Orders in any other part of the lifecycle are left
+/// alone.
+pub async fn sync_order_status(
+    client: &impl GenericClient,
+    order_id: Uuid,
+    changed_by: Uuid,
+) -> Result<(), AppError> {
+    let Some(mut order) = Order::get_order_for_update(client, order_id).await? else {
+        return Ok(());
+    };
+
+    let fulfillable = matches!(
+        order.status,
+        OrderStatus::Paid
+            | OrderStatus::PartiallyRefunded
+            | OrderStatus::Fulfilling
+            | OrderStatus::Shipped
+    );
+    if !fulfillable {
+        return Ok(());
+    }
+
+    let items = OrderItem::get_order_items(client, order_id).await?;
+    let refunded = Refund::refunded_quantities(client, order_id).await?;
+    let shipped = Shipment::quantities(client, order_id).await?;
+
+    let mut all_shipped = true;
+    let mut all_delivered = true;
+    for item in &items {
+        let due = item.quantity - refunded.get(&item.id).copied().unwrap_or_default();
+        let quantities = shipped.get(&item.id).copied().unwrap_or_default();
+        all_shipped &= quantities.shipped >= due;
+        all_delivered &= quantities.delivered >= due;
+    }
+
+    let target = if all_delivered {
+        OrderStatus::Delivered
+    } else if all_shipped {
+        OrderStatus::Shipped
+    } else {
+        OrderStatus::Fulfilling
+    };
+
+    let reached = FULFILLMENT
+        .iter()
+        .position(|status| *status == order.status)
+        .map_or(0, |index| index + 1);
+    let wanted = FULFILLMENT
+        .iter()
+        .position(|status| *status == target)
+        .map_or(0, |index| index + 1);
+
+    for next in FULFILLMENT.iter().take(wanted).skip(reached) {
+        order = Order::transition(client, &order, *next, Some(changed_by)).await?;
+    }
+
+    Ok(())
+}
+
+/// Handler to create a shipment of an order, for all or some of its units. Restricted to admins.
+///
+/// Only paid orders can be shipped, and each unit can only be in one shipment. The order
+/// moves to fulfilling, then to shipped or delivered once its shipments cover every unit
+/// that was not refunded.
+pub async fn create_shipment(
+    admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    order_id: web::Path<Uuid>,
+    body: web::Json<CreateShipmentRequest>,
+) -> Result<HttpResponse, AppError> {
+    let body = body.into_inner();
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let order = Order::get_order_for_update(&transaction, *order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+
+    let shippable = matches!(
+        order.status,
+        OrderStatus::Paid | OrderStatus::PartiallyRefunded | OrderStatus::Fulfilling
+    );
+    if !shippable {
+        return Err(AppError::conflict(format!(
+            "Cannot ship an order that is {}",
+            order.status.as_str()
+        )));
+    }
+
+    let unshipped = unshipped_quantities(&transaction, order.id).await?;
+
+    let items = match body.items {
+        Some(items) => {
+            if items.is_empty() {
+                return Err(AppError::validation(
+                    "A shipment must contain at least one item",
+                ));
+            }
+
+            let mut seen = HashSet::new();
+            for item in &items {
+                if item.quantity <= 0 {
+                    return Err(AppError::validation(
+                        "Item quantities must be greater than zero",
+                    ));
+                }
+                if !seen.insert(item.order_item_id) {
+                    return Err(AppError::validation("Each item can only be listed once"));
+                }
+
+                let available = unshipped
+                    .iter()
+                    .find(|unshipped| unshipped.order_item_id == item.order_item_id)
+                    .ok_or_else(|| {
+                        AppError::validation(format!(
+                            "Item {} does not belong to the order",
+                            item.order_item_id
+                        ))
+                    })?
+                    .quantity;
+                if item.quantity > available {
+                    return Err(AppError::Conflict {
+                        message: "The item has fewer units left to ship".to_string(),
+                        details: Some(json!({
+                            "order_item_id": item.order_item_id,
+                            "requested": item.quantity,
+                            "available": available,
+                        })),
+                    });
+                }
+            }
+
+            items
+        }
+        None => unshipped
+            .into_iter()
+            .filter(|item| item.quantity > 0)
+            .collect(),
+    };
+
+    if items.is_empty() {
+        return Err(AppError::conflict(
+            "Every item of the order is already in a shipment",
+        ));
+    }
+
+    let mut shipment = Shipment::create(
+        &transaction,
+        order.id,
+        trimmed(body.carrier.as_deref()).as_deref(),
+        trimmed(body.tracking_number.as_deref()).as_deref(),
+        &items,
+    )
+    .await?;
+
+    if let Some(status) = body
+        .status
+        .filter(|status| *status != ShipmentStatus::Pending)
+    {
+        let changes = ShipmentChanges {
+            status: Some(status),
+            ..Default::default()
+        };
+        shipment = Shipment::update(&transaction, shipment.id, &changes).await?;
+    }
+
+    sync_order_status(&transaction, order.id, admin.0.sub).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Created().json(shipment))
+}
+
+/// Handler to update the carrier, tracking number or status of a shipment.
+/// Restricted to admins. The status can only move forward; the order follows its shipments.
+pub async fn update_shipment(
+    admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    shipment_id: web::Path<Uuid>,
+    body: web::Json<UpdateShipmentRequest>,
+) -> Result<HttpResponse, AppError> {
+    let not_found = || AppError::NotFound("Shipment not found".to_string());
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    // Lock the order before the shipment, like every other fulfillment flow
+    let shipment = Shipment::get_by_id(&transaction, *shipment_id)
+        .await?
+        .ok_or_else(not_found)?;
+    Order::get_order_for_update(&transaction, shipment.order_id).await?;
+    let shipment = Shipment::get_for_update(&transaction, shipment.id)
+        .await?
+        .ok_or_else(not_found)?;
+
+    if body.status.is_some_and(|status| status < shipment.status) {
+        return Err(AppError::conflict(format!(
+            "The shipment is already {}",
+            shipment.status.as_str()
+        )));
+    }
+
+    let changes = ShipmentChanges {
+        carrier: trimmed(body.carrier.as_deref()),
+        tracking_number: trimmed(body.tracking_number.as_deref()),
+        status: body.status,
+    };
+    let shipment = Shipment::update(&transaction, shipment.id, &changes).await?;
+
+    sync_order_status(&transaction, shipment.order_id, admin.0.sub).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(shipment))
+}
+
+/// Handler listing the shipments of an order, with their tracking details.
+/// Customers may only list the shipments of their own orders.
+pub async fn list_shipments(
+    pool: web::Data<Pool>,
+    auth_user: AuthenticatedUser,
+    order_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    let order = Order::get_order(&client, *order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+    auth_user.ensure_self_or_admin(order.user_id)?;
+
+    let shipments = Shipment::list_for_order(&client, order.id).await?;
+
+    Ok(HttpResponse::Ok().json(shipments))
+}
@@ -0,0 +1,252 @@
+use actix_web::{web, HttpResponse};
+use deadpool_postgres::Pool;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    auth::{Admin, OptionalUser, RequireRole},
+    errors::AppError,
+    models::shipping_model::{NewShippingRate, ShippingMethod, ShippingRate, ShippingZone},
+    tax::is_valid_region,
+};
+
+/// Represents the request body to create a shipping method.
+#[derive(Deserialize)]
+pub struct CreateShippingMethodRequest {
+    pub code: String, // 1 to 32 lower case letters, digits or underscores
+    pub name: String,
+}
+
+/// Represents the request body to create a shipping zone.
+#[derive(Deserialize)]
+pub struct CreateShippingZoneRequest {
+    pub name: String,
+    pub regions: Vec<String>, // Countries ("DE") and subdivisions ("US-HI")
+}
+
+/// Represents the request body to add a weight bracket to a zone.
+#[derive(Deserialize)]
+pub struct CreateShippingRateRequest {
+    pub method_id: Uuid,
+    pub max_weight_kg: Option<Decimal>, // Omitted for a bracket covering any weight
+    pub price: Decimal,
+    pub free_over: Option<Decimal>, // Subtotal after discounts from which shipping is free
+}
+
+// Checks an amount of money: not negative and in cents.
+fn validate_amount(field: &str, amount: Decimal) -> Result<(), AppError> {
+    if amount.is_sign_negative() || amount.scale() > 2 {
+        return Err(AppError::validation(format!(
+            "{} must not be negative and have at most 2 decimal places",
+            field
+        )));
+    }
+
+    Ok(())
+}
+
+/// Handler listing the shipping methods customers can choose. Admins also get the inactive
+/// ones.
+pub async fn list_shipping_methods(
+    pool: web::Data<Pool>,
+    user: OptionalUser,
+) -> Result<HttpResponse, AppError> {
+    let include_inactive = user.0.as_ref().is_some_and(|user| user.is_admin());
+
+    let client = pool.get().await?;
+    let methods = ShippingMethod::list(&client, include_inactive).await?;
+
+    Ok(HttpResponse::Ok().json(methods))
+}
+
+/// Handler to create a shipping method. Restricted to admins.
+/// The method can only be chosen for destinations whose zone has a rate for it.
+pub async fn create_shipping_method(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    body: web::Json<CreateShippingMethodRequest>,
+) -> Result<HttpResponse, AppError> {
+    let valid_code = (1..=32).contains(&body.code.len())
+        && body
+            .code
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid_code {
+        return Err(AppError::validation(
+            "code must be 1 to 32 lower case letters, digits or underscores",
+        ));
+    }
+
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(AppError::validation("name must not be empty"));
+    }
+
+    let client = pool.get().await?;
+    let method = ShippingMethod::create(&client, &body.code, name).await?;
+
+    Ok(HttpResponse::Created().json(method))
+}
+
+/// Handler to stop a shipping method from being chosen. Restricted to admins.
+/// Orders that already use the method keep it.
+pub async fn deactivate_shipping_method(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    method_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    let method = ShippingMethod::deactivate(&client, *method_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Shipping method not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(method))
+}
+
+/// Handler listing every shipping zone with its regions. Restricted to admins.
+pub async fn list_shipping_zones(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+    let zones = ShippingZone::list(&client).await?;
+
+    Ok(HttpResponse::Ok().json(zones))
+}
+
+/// Handler to create a shipping zone. Restricted to admins.
+/// A region can only belong to one zone.
+pub async fn create_shipping_zone(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    body: web::Json<CreateShippingZoneRequest>,
+) -> Result<HttpResponse, AppError> {
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(AppError::validation("name must not be empty"));
+    }
+
+    let mut regions: Vec<String> = body
+        .regions
+        .iter()
+        .map(|region| region.trim().to_uppercase())
+        .collect();
+    if regions.is_empty() {
+        return Err(AppError::validation(
+            "A zone must contain at least one region",
+        ));
+    }
+    if let Some(invalid) = regions.iter().find(|region| !is_valid_region(region)) {
+        return Err(AppError::validation(format!(
+            "{} is not an ISO 3166 country or subdivision code",
+            invalid
+        )));
+    }
+    regions.sort();
+    regions.dedup();
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let zone = ShippingZone::create(&transaction, name, &regions).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Created().json(zone))
+}
+
+/// Handler to delete a shipping zone with its rates. Restricted to admins.
+/// Its regions are no longer served.
+pub async fn delete_shipping_zone(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    zone_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    if !ShippingZone::delete(&client, *zone_id).await? {
+        return Err(AppError::NotFound("Shipping zone not found".to_string()));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Handler listing the rates of a shipping zone. Restricted to admins.
+pub async fn list_shipping_rates(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    zone_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    if ShippingZone::get_by_id(&client, *zone_id).await?.is_none() {
+        return Err(AppError::NotFound("Shipping zone not found".to_string()));
+    }
+    let rates = ShippingRate::list_for_zone(&client, *zone_id).await?;
+
+    Ok(HttpResponse::Ok().json(rates))
+}
+
+/// Handler adding a weight bracket to a shipping zone. Restricted to admins.
+pub async fn create_shipping_rate(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    zone_id: web::Path<Uuid>,
+    body: web::Json<CreateShippingRateRequest>,
+) -> Result<HttpResponse, AppError> {
+    validate_amount("price", body.price)?;
+    if let Some(free_over) = body.free_over {
+        validate_amount("free_over", free_over)?;
+    }
+    if body
+        .max_weight_kg
+        .is_some_and(|max| max <= Decimal::ZERO || max.scale() > 3)
+    {
+        return Err(AppError::validation(
+            "max_weight_kg must be greater than zero with at most 3 decimal places",
+        ));
+    }
+
+    let client = pool.get().await?;
+
+    if ShippingZone::get_by_id(&client, *zone_id).await?.is_none() {
+        return Err(AppError::NotFound("Shipping zone not found".to_string()));
+    }
+    if ShippingMethod::get_by_id(&client, body.method_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::NotFound("Shipping method not found".to_string()));
+    }
+
+    let rate = ShippingRate::create(
+        &client,
+        *zone_id,
+        &NewShippingRate {
+            method_id: body.method_id,
+            max_weight_kg: body.max_weight_kg,
+            price: body.price,
+            free_over: body.free_over,
+        },
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().json(rate))
+}
+
+/// Handler to delete a shipping rate. Restricted to admins.
+pub async fn delete_shipping_rate(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    rate_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    if !ShippingRate::delete(&client, *rate_id).await? {
+        return Err(AppError::NotFound("Shipping rate not found".to_string()));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
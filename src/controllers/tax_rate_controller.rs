@@ -0,0 +1,87 @@
+use actix_web::{web, HttpResponse};
+use deadpool_postgres::Pool;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    auth::{Admin, RequireRole},
+    errors::AppError,
+    models::tax_rate_model::TaxRate,
+    tax::{is_valid_region, is_valid_tax_class},
+};
+
+/// Represents the request body to create a tax rate.
+#[derive(Deserialize)]
+pub struct CreateTaxRateRequest {
+    pub region: String, // "DE", or "US-CA" to override the country's rate in a subdivision
+    pub tax_class: String, // e.g. "standard"
+    pub rate: Decimal,  // Percentage, 0 to 100
+    pub name: String,   // e.g. "VAT"
+}
+
+/// Handler listing every tax rate. Restricted to admins.
+pub async fn list_tax_rates(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+    let rates = TaxRate::list(&client).await?;
+
+    Ok(HttpResponse::Ok().json(rates))
+}
+
+/// Handler to create a tax rate. Restricted to admins.
+/// The rate applies to orders placed or changed afterwards.
+pub async fn create_tax_rate(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    body: web::Json<CreateTaxRateRequest>,
+) -> Result<HttpResponse, AppError> {
+    let region = body.region.trim().to_uppercase();
+    if !is_valid_region(&region) {
+        return Err(AppError::validation(
+            "region must be an ISO 3166 country or subdivision code, e.g. \"DE\" or \"US-CA\"",
+        ));
+    }
+
+    if !is_valid_tax_class(&body.tax_class) {
+        return Err(AppError::validation(
+            "tax_class must be 1 to 32 lower case letters, digits or underscores",
+        ));
+    }
+
+    if body.rate < Decimal::ZERO || body.rate > Decimal::ONE_HUNDRED {
+        return Err(AppError::validation("rate must be between 0 and 100"));
+    }
+    if body.rate.scale() > 3 {
+        return Err(AppError::validation(
+            "rate must have at most 3 decimal places",
+        ));
+    }
+
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(AppError::validation("name must not be empty"));
+    }
+
+    let client = pool.get().await?;
+    let rate = TaxRate::create(&client, &region, &body.tax_class, body.rate, name).await?;
+
+    Ok(HttpResponse::Created().json(rate))
+}
+
+/// Handler to delete a tax rate. Restricted to admins.
+pub async fn delete_tax_rate(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    rate_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
+    if !TaxRate::delete(&client, *rate_id).await? {
+        return Err(AppError::NotFound("Tax rate not found".to_string()));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
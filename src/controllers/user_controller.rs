@@ -1,103 +1,268 @@
-use std::sync::Arc;
-
-use crate::{jwt::create_jwt, models::user_model::User};
-use actix_web::{web, HttpResponse};
-use serde::Deserialize;
-use tokio_postgres::Client;
+use crate::{
+    auth::{Admin, AuthenticatedUser, RequireRole},
+    controllers::cart_controller::{guest_cart_id, guest_cart_removal},
+    errors::AppError,
+    jwt::{create_jwt, refresh_token_ttl, ACCESS_TOKEN_TTL_SECS},
+    models::{
+        cart_model::Cart, refresh_token_model::RefreshToken, revoked_token_model::RevokedToken,
+        user_model::User,
+    },
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{TimeZone, Utc};
+use deadpool_postgres::{Client, GenericClient, Pool};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 /// Struct representing the request data for creating a user.
 #[derive(Deserialize)]
 pub struct CreateUserRequest {
-    pub name: String,    
-    pub email: String,    
-    pub password: String, 
+    pub name: String,
+    pub email: String,
+    pub password: String,
 }
 
 #[derive(Deserialize)]
 pub struct LoginRequest {
-    pub email: String,    
-    pub password: String, 
+    pub email: String,
+    pub password: String,
+}
+
+/// Request body carrying a refresh token, used to refresh and to log out.
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+/// Optional request body for logout; when present the refresh token family is revoked too.
+#[derive(Deserialize)]
+pub struct LogoutRequest {
+    pub refresh_token: Option<String>,
+}
+
+/// Response returned by login and refresh.
+#[derive(Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64, // Lifetime of the access token, in seconds
+}
+
+// Issues an access token and a refresh token belonging to the given family.
+async fn issue_tokens(
+    client: &impl GenericClient,
+    user: &User,
+    family_id: Uuid,
+) -> Result<TokenResponse, AppError> {
+    let access_token = create_jwt(user.id, user.role)?;
+    let (_, refresh_token) =
+        RefreshToken::issue(client, user.id, family_id, refresh_token_ttl()).await?;
+
+    Ok(TokenResponse {
+        access_token,
+        refresh_token,
+        token_type: "Bearer",
+        expires_in: ACCESS_TOKEN_TTL_SECS,
+    })
 }
 
 /// Handler function to create a user.
 /// This function processes the incoming HTTP request, hashes the password, and
 /// stores the user in the database.
 pub async fn create_user(
-    client: web::Data<Arc<Client>>,              // Database client for interacting with the database
-    user_data: web::Json<CreateUserRequest>,     // The data submitted in the user creation request
-) -> HttpResponse {
+    pool: web::Data<Pool>, // Connection pool for interacting with the database
+    user_data: web::Json<CreateUserRequest>, // The data submitted in the user creation request
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
     // Check if the email is already in use by querying the database
-    if User::email_exists(&client, &user_data.email).await.unwrap_or(false) {
-        return HttpResponse::BadRequest().body("Email already in use");  // Return an error response if email is in use
+    if User::email_exists(&client, &user_data.email).await? {
+        return Err(AppError::conflict("Email already in use"));
     }
 
     // Attempt to create the user in the database
+    // The password will be hashed within the `create_user` function
     let user = User::create_user(
         &client,
         &user_data.name,
         &user_data.email,
-        &user_data.password, // The password will be hashed within the `create_user` function
+        &user_data.password,
     )
-    .await;
-
-    match user {
-        // If user creation is successful, return the user data as JSON in the response
-        Ok(user) => HttpResponse::Ok().json(user),
-        // If an error occurs during user creation, log the error and return a 500 internal server error
-        Err(e) => {
-            eprintln!("Error creating user: {:?}", e);  // Log the error details
-            HttpResponse::InternalServerError().finish()  // Send a generic internal server error response
-        }
-    }
+    .await?;
+
+    // Return the user data as JSON in the response
+    Ok(HttpResponse::Ok().json(user))
 }
 
 /// Handler function to authenticate a user by login credentials.
-/// This function checks the provided credentials and generates a JWT token for the user if successful.
+/// This function checks the provided credentials and, if successful, issues an access token
+/// together with a refresh token starting a new token family.
+/// A guest cart sent along in its cookie is merged into the user's cart.
 pub async fn login_user(
-    client: web::Data<Arc<Client>>,              // Database client for interacting with the database
-    login_data: web::Json<LoginRequest>,         // The data submitted in the login request
-) -> HttpResponse {
-    match User::authenticate_user(&client, &login_data.email, &login_data.password).await {
-        // If authentication is successful, generate a JWT token for the authenticated user
-        Ok(Some(user)) => {
-            match create_jwt(user.id) {
-                Ok(token) => HttpResponse::Ok().json(token),  // Return the JWT token as JSON
-                Err(_) => HttpResponse::InternalServerError().body("Failed to generate token"),  // If token creation fails, return an error
+    req: HttpRequest,
+    pool: web::Data<Pool>, // Connection pool for interacting with the database
+    login_data: web::Json<LoginRequest>, // The data submitted in the login request
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+
+    // If no user is found with the provided credentials, return an Unauthorized response
+    let user = User::authenticate_user(&client, &login_data.email, &login_data.password)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid credentials".to_string()))?;
+
+    let mut response = HttpResponse::Ok();
+
+    // Items gathered before logging in are moved to the user's cart. A failed merge does not
+    // prevent the login, and keeps the guest cart and its cookie for another attempt.
+    if let Some(guest_cart_id) = guest_cart_id(&req) {
+        match merge_guest_cart(&mut client, guest_cart_id, user.id).await {
+            Ok(()) => {
+                response.cookie(guest_cart_removal());
             }
+            Err(err) => eprintln!("Failed to merge guest cart {}: {:?}", guest_cart_id, err),
         }
-        // If no user is found with the provided credentials, return an Unauthorized response
-        Ok(None) => HttpResponse::Unauthorized().body("Invalid credentials"),
-        // If an error occurs during authentication, return an internal server error
-        Err(_) => HttpResponse::InternalServerError().body("An error occurred during login"),
     }
+
+    // Generate the tokens for the authenticated user and return them as JSON
+    let tokens = issue_tokens(&client, &user, Uuid::new_v4()).await?;
+    Ok(response.json(tokens))
+}
+
+// Moves the items of a guest cart into the user's cart, in a single transaction.
+async fn merge_guest_cart(
+    client: &mut Client,
+    guest_cart_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), AppError> {
+    let transaction = client.transaction().await?;
+
+    let cart_id = Cart::id_for_user(&transaction, user_id).await?;
+    Cart::merge_guest(&transaction, guest_cart_id, cart_id).await?;
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Handler function to exchange a refresh token for a new pair of tokens.
+/// Refresh tokens are single use: each refresh rotates the token, and presenting a token
+/// that was already rotated revokes its whole family, since it may have been stolen.
+pub async fn refresh_token(
+    pool: web::Data<Pool>,
+    body: web::Json<RefreshRequest>,
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let token = RefreshToken::find_for_update(&transaction, &body.refresh_token)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid refresh token".to_string()))?;
+
+    if token.used_at.is_some() && token.revoked_at.is_none() {
+        // Reuse detected: the revocation must be committed even though the request fails
+        RefreshToken::revoke_family(&transaction, token.family_id).await?;
+        transaction.commit().await?;
+        eprintln!(
+            "Refresh token reuse detected for family {}",
+            token.family_id
+        );
+        return Err(AppError::Unauthorized(
+            "Refresh token has already been used".to_string(),
+        ));
+    }
+
+    if token.revoked_at.is_some() || token.used_at.is_some() {
+        return Err(AppError::Unauthorized(
+            "Refresh token has been revoked".to_string(),
+        ));
+    }
+
+    if token.is_expired() {
+        return Err(AppError::Unauthorized(
+            "Refresh token has expired".to_string(),
+        ));
+    }
+
+    // The role is read again so changes made since the last login take effect
+    let user = User::get_user(&transaction, token.user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid refresh token".to_string()))?;
+
+    RefreshToken::mark_used(&transaction, token.id).await?;
+    let tokens = issue_tokens(&transaction, &user, token.family_id).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+/// Handler function to log a user out.
+/// The access token used for the request is revoked, and so is the refresh token family
+/// when a refresh token is supplied.
+pub async fn logout_user(
+    pool: web::Data<Pool>,
+    auth_user: AuthenticatedUser,
+    body: Option<web::Json<LogoutRequest>>,
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let claims = &auth_user.0;
+    let expires_at = Utc
+        .timestamp_opt(claims.exp as i64, 0)
+        .single()
+        .unwrap_or_else(Utc::now);
+    RevokedToken::revoke(&transaction, claims.jti, expires_at).await?;
+
+    if let Some(refresh_token) = body.and_then(|body| body.into_inner().refresh_token) {
+        // Tokens belonging to other users are ignored rather than revealing they exist
+        if let Some(token) = RefreshToken::find_for_update(&transaction, &refresh_token).await? {
+            if token.user_id == claims.sub {
+                RefreshToken::revoke_family(&transaction, token.family_id).await?;
+            }
+        }
+    }
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().finish())
 }
 
 /// Handler function to fetch a user by their ID.
 /// This function queries the database to retrieve the user based on the given ID.
-pub async fn get_user(client: web::Data<Arc<Client>>, user_id: web::Path<Uuid>) -> HttpResponse {
+/// Users may only fetch themselves, unless they are admins.
+pub async fn get_user(
+    auth_user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    user_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    auth_user.ensure_self_or_admin(*user_id)?;
+
+    let client = pool.get().await?;
+
     // Call the `get_user` method from the User model to fetch the user data
-    let user = User::get_user(&client, user_id.into_inner()).await;
-
-    match user {
-        // If the user is found, return the user data as JSON
-        Ok(Some(user)) => HttpResponse::Ok().json(user),
-        // If no user is found with the given ID, return a 404 response
-        Ok(None) => HttpResponse::NotFound().finish(),
-        // If there's an error during the query, return an internal server error response
-        Err(_) => HttpResponse::InternalServerError().finish(),
-    }
+    let user = User::get_user(&client, user_id.into_inner())
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(user))
 }
 
 /// Handler function to delete a user by their ID.
 /// This function deletes the user record from the database based on the provided ID.
-pub async fn delete_user(user_id: web::Path<Uuid>, client: web::Data<Arc<Client>>) -> HttpResponse {
+/// Restricted to admins.
+pub async fn delete_user(
+    _admin: RequireRole<Admin>,
+    user_id: web::Path<Uuid>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+
     // Call the `delete_user` method from the User model to delete the user
-    match User::delete_user(&client, user_id.into_inner()).await {
-        // If the deletion is successful, return a 204 No Content response
-        Ok(_) => HttpResponse::NoContent().finish(),
-        // If an error occurs during deletion, return an internal server error
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    if !User::delete_user(&client, user_id.into_inner()).await? {
+        return Err(AppError::NotFound("User not found".to_string()));
     }
-}
 
+    // If the deletion is successful, return a 204 No Content response
+    Ok(HttpResponse::NoContent().finish())
+}
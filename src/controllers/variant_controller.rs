@@ -0,0 +1,275 @@
+use std::collections::HashSet;
+
+use actix_web::{web, HttpResponse};
+use deadpool_postgres::Pool;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    auth::{Admin, RequireRole},
+    controllers::product_controller::present,
+    errors::AppError,
+    models::variant_model::{NewOptionType, NewVariant, OptionType, Variant, VariantChanges},
+};
+
+/// Represents the request body to add an option type to a product.
+#[derive(Deserialize)]
+pub struct CreateOptionTypeRequest {
+    pub name: String,        // e.g. "Size"
+    pub values: Vec<String>, // e.g. ["S", "M", "L"], positioned in this order
+    #[serde(default)]
+    pub position: i32,
+}
+
+/// Represents the request body to add a value to an option type.
+#[derive(Deserialize)]
+pub struct AddOptionValueRequest {
+    pub value: String,
+}
+
+/// Represents the request body to create a variant.
+#[derive(Deserialize)]
+pub struct CreateVariantRequest {
+    pub sku: String,
+    pub price: Option<Decimal>, // Defaults to the product's price
+    pub stock: i32,
+    #[serde(default)]
+    pub option_value_ids: Vec<Uuid>, // One value of each option type of the product
+}
+
+/// Body of a variant update. Omitted fields are left unchanged, and an explicit
+/// `"price": null` sells the variant at the product's price.
+#[derive(Deserialize)]
+pub struct UpdateVariantRequest {
+    pub sku: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub price: Option<Option<Decimal>>,
+    pub stock: Option<i32>,
+}
+
+// Trims a name or value, rejecting blank ones.
+fn required(value: &str, field: &str) -> Result<String, AppError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(AppError::validation(format!("{} must not be empty", field)));
+    }
+
+    Ok(value.to_string())
+}
+
+// Checks a SKU: 1 to 64 letters, digits, dots, dashes or underscores.
+fn validate_sku(sku: &str) -> Result<(), AppError> {
+    let valid = (1..=64).contains(&sku.len())
+        && sku
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    if !valid {
+        return Err(AppError::validation(
+            "sku must be 1 to 64 letters, digits, dots, dashes or underscores",
+        ));
+    }
+
+    Ok(())
+}
+
+// Rejects negative prices and stock.
+fn validate_price_and_stock(price: Option<Decimal>, stock: Option<i32>) -> Result<(), AppError> {
+    if price.is_some_and(|price| price.is_sign_negative()) {
+        return Err(AppError::validation("price must not be negative"));
+    }
+    if stock.is_some_and(|stock| stock < 0) {
+        return Err(AppError::validation("stock must not be negative"));
+    }
+
+    Ok(())
+}
+
+/// Handler listing the option types of a product, with their values.
+pub async fn list_option_types(
+    pool: web::Data<Pool>,
+    product_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+    let option_types = OptionType::list_for_product(&client, *product_id).await?;
+
+    Ok(HttpResponse::Ok().json(option_types))
+}
+
+/// Handler to add an option type with its values to a product. Restricted to admins.
+/// Option types must be defined before the variants of the product.
+pub async fn create_option_type(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    product_id: web::Path<Uuid>,
+    body: web::Json<CreateOptionTypeRequest>,
+) -> Result<HttpResponse, AppError> {
+    let name = required(&body.name, "name")?;
+
+    let values = body
+        .values
+        .iter()
+        .map(|value| required(value, "Option values"))
+        .collect::<Result<Vec<_>, _>>()?;
+    if values.is_empty() {
+        return Err(AppError::validation(
+            "An option type needs at least one value",
+        ));
+    }
+    let mut seen = HashSet::new();
+    if !values.iter().all(|value| seen.insert(value)) {
+        return Err(AppError::validation("Each value can only be listed once"));
+    }
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let option_type = OptionType::create(
+        &transaction,
+        *product_id,
+        &NewOptionType {
+            name: &name,
+            position: body.position,
+            values: &values,
+        },
+    )
+    .await?
+    .ok_or_else(|| AppError::NotFound("Product not found".to_string()))?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Created().json(option_type))
+}
+
+/// Handler to add a value to an option type of a product. Restricted to admins.
+pub async fn add_option_value(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    path: web::Path<(Uuid, Uuid)>,
+    body: web::Json<AddOptionValueRequest>,
+) -> Result<HttpResponse, AppError> {
+    let (product_id, option_type_id) = path.into_inner();
+    let value = required(&body.value, "value")?;
+
+    let client = pool.get().await?;
+
+    let option_type = OptionType::add_value(&client, product_id, option_type_id, &value)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Option type not found".to_string()))?;
+
+    Ok(HttpResponse::Created().json(option_type))
+}
+
+/// Handler to delete an option type of a product. Restricted to admins.
+/// Option types used by variants cannot be deleted.
+pub async fn delete_option_type(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, AppError> {
+    let (product_id, option_type_id) = path.into_inner();
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    if !OptionType::delete(&transaction, product_id, option_type_id).await? {
+        return Err(AppError::NotFound("Option type not found".to_string()));
+    }
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Handler listing the variants of a product, with their options, price and stock.
+pub async fn list_variants(
+    pool: web::Data<Pool>,
+    product_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let client = pool.get().await?;
+    let variants = Variant::list_for_product(&client, *product_id).await?;
+
+    Ok(HttpResponse::Ok().json(variants))
+}
+
+/// Handler to create a variant of a product. Restricted to admins.
+/// Once a product has variants, it can only be ordered through one of them.
+pub async fn create_variant(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    product_id: web::Path<Uuid>,
+    body: web::Json<CreateVariantRequest>,
+) -> Result<HttpResponse, AppError> {
+    let sku = body.sku.trim();
+    validate_sku(sku)?;
+    validate_price_and_stock(body.price, Some(body.stock))?;
+
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let variant = Variant::create(
+        &transaction,
+        *product_id,
+        &NewVariant {
+            sku,
+            price: body.price,
+            stock: body.stock,
+            option_value_ids: &body.option_value_ids,
+        },
+    )
+    .await?
+    .ok_or_else(|| AppError::NotFound("Product not found".to_string()))?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Created().json(variant))
+}
+
+/// Handler to change the SKU, price or stock of a variant. Restricted to admins.
+pub async fn update_variant(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    path: web::Path<(Uuid, Uuid)>,
+    body: web::Json<UpdateVariantRequest>,
+) -> Result<HttpResponse, AppError> {
+    let (product_id, variant_id) = path.into_inner();
+    let body = body.into_inner();
+
+    let sku = body.sku.map(|sku| sku.trim().to_string());
+    if let Some(sku) = &sku {
+        validate_sku(sku)?;
+    }
+    validate_price_and_stock(body.price.flatten(), body.stock)?;
+
+    let changes = VariantChanges {
+        sku,
+        price: body.price,
+        stock: body.stock,
+    };
+
+    let client = pool.get().await?;
+
+    let variant = Variant::update(&client, product_id, variant_id, &changes)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Variant not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(variant))
+}
+
+/// Handler to delete a variant of a product. Restricted to admins.
+/// Variants that were ordered cannot be deleted.
+pub async fn delete_variant(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, AppError> {
+    let (product_id, variant_id) = path.into_inner();
+
+    let client = pool.get().await?;
+
+    if !Variant::delete(&client, product_id, variant_id).await? {
+        return Err(AppError::NotFound("Variant not found".to_string()));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,378 @@
+use std::env;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use deadpool_postgres::{Client, GenericClient, Pool};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    auth::{Admin, RequireRole},
+    controllers::payment_controller::settle_refund,
+    crypto::verify_hmac_sha256,
+    errors::AppError,
+    models::{
+        order_model::{Order, OrderStatus},
+        payment_event_model::PaymentEvent,
+        payment_model::{Payment, PaymentStatus},
+        refund_model::{NewRefund, Refund},
+    },
+    pagination::{page_bounds, Page},
+    payment_gateway::PaymentGateway,
+};
+
+/// Header carrying the Unix time at which the provider signed the event.
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+
+/// Header carrying the hex encoded HMAC-SHA256 of `"{timestamp}.{body}"`.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// Event body accepted from payment providers.
+#[derive(Deserialize)]
+pub struct WebhookEvent {
+    pub id: String, // Provider's event ID, used to ignore redeliveries
+    #[serde(rename = "type")]
+    pub event_type: String, // e.g. "payment.captured"
+    pub data: WebhookEventData,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookEventData {
+    pub reference: String,       // Provider's reference of the payment
+    pub amount: Option<Decimal>, // Amount the event applies to, checked against the payment
+}
+
+/// Query string accepted by the event listing.
+#[derive(Deserialize)]
+pub struct ListEventsQuery {
+    pub unprocessed: Option<bool>, // Only events that have not been applied
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Acknowledgement sent back to the provider.
+#[derive(Serialize)]
+pub struct WebhookResponse {
+    pub status: &'static str, // "processed", "ignored" or "duplicate"
+}
+
+// Returns the secret shared with a provider, configured with PAYMENT_WEBHOOK_SECRET_<PROVIDER>.
+fn webhook_secret(provider: &str) -> Option<String> {
+    let name = format!("PAYMENT_WEBHOOK_SECRET_{}", provider.to_uppercase());
+    env::var(name).ok().filter(|secret| !secret.is_empty())
+}
+
+// Returns how far the signed timestamp may be from now, configured with
+// WEBHOOK_TOLERANCE_SECS (default 300). Older events are rejected to prevent replays.
+fn timestamp_tolerance() -> i64 {
+    env::var("WEBHOOK_TOLERANCE_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(300)
+}
+
+// Checks the timestamp and signature headers of a webhook request against its raw body.
+fn verify_signature(req: &HttpRequest, secret: &str, body: &[u8]) -> Result<(), AppError> {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .ok_or_else(|| AppError::Unauthorized(format!("Missing {} header", name)))
+    };
+
+    let timestamp = header(TIMESTAMP_HEADER)?;
+    let signature = header(SIGNATURE_HEADER)?;
+
+    let signed_at: i64 = timestamp
+        .parse()
+        .map_err(|_| AppError::Unauthorized("Invalid webhook timestamp".to_string()))?;
+    if (chrono::Utc::now().timestamp() - signed_at).abs() > timestamp_tolerance() {
+        return Err(AppError::Unauthorized(
+            "Webhook timestamp is outside the tolerance window".to_string(),
+        ));
+    }
+
+    let mut signed_payload = format!("{}.", timestamp).into_bytes();
+    signed_payload.extend_from_slice(body);
+
+    if !verify_hmac_sha256(secret.as_bytes(), &signed_payload, signature) {
+        return Err(AppError::Unauthorized(
+            "Invalid webhook signature".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+// What applying an event did.
+enum Applied {
+    Ignored,
+    Processed,
+    // Processed, and recorded a pending refund to send to the provider once committed
+    Refunding(Box<(Payment, Refund)>),
+}
+
+// Maps an event type to the payment status it reports, None for events we do not handle.
+fn reported_status(event_type: &str) -> Option<PaymentStatus> {
+    match event_type {
+        "payment.authorized" => Some(PaymentStatus::Authorized),
+        "payment.captured" => Some(PaymentStatus::Paid),
+        "payment.failed" => Some(PaymentStatus::Failed),
+        "payment.voided" => Some(PaymentStatus::Voided),
+        "payment.refunded" => Some(PaymentStatus::Refunded),
+        _ => None,
+    }
+}
+
+// Records a refund made on the provider's side, e.g. from its dashboard, as a succeeded refund
+// of whatever was left on the payment. Our own pending refunds are left out: they are added to
+// the payment when their answer is recorded.
+async fn refund_on_provider_side(
+    client: &impl GenericClient,
+    payment: &Payment,
+    event_id: &str,
+) -> Result<Payment, AppError> {
+    let amount = payment.refundable_amount() - Refund::pending_amount(client, payment.id).await?;
+    if amount <= Decimal::ZERO {
+        return Payment::get_payment_by_id(client, payment.id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Payment not found".to_string()));
+    }
+
+    let refund = Refund::create(
+        client,
+        NewRefund {
+            id: Uuid::new_v4(),
+            payment_id: payment.id,
+            amount,
+            reason: Some("Refunded by the payment provider"),
+            restocked: false,
+            created_by: None,
+            items: Vec::new(),
+        },
+    )
+    .await?;
+    Refund::complete(client, refund, event_id).await?;
+
+    Payment::add_refund(client, payment.id, amount).await
+}
+
+// Applies an event to its payment and order. Applying an event twice has no further effect.
+// Money captured for an order that can no longer be paid, e.g. one that was cancelled, is
+// given back: the payment becomes paid and a refund of its amount is started.
+async fn apply_event(
+    client: &impl GenericClient,
+    provider: &str,
+    event: &WebhookEvent,
+) -> Result<Applied, AppError> {
+    let Some(status) = reported_status(&event.event_type) else {
+        return Ok(Applied::Ignored);
+    };
+
+    let not_found = || AppError::NotFound("Payment not found".to_string());
+
+    // Lock the order before the payment, like every other payment flow
+    let payment = Payment::find_by_provider_reference(client, provider, &event.data.reference)
+        .await?
+        .ok_or_else(not_found)?;
+    let order = Order::get_order_for_update(client, payment.order_id)
+        .await?
+        .ok_or_else(not_found)?;
+    let payment = Payment::get_payment_for_update(client, payment.id)
+        .await?
+        .ok_or_else(not_found)?;
+
+    if payment.status == status {
+        return Ok(Applied::Processed);
+    }
+
+    if !payment.status.can_transition_to(status) {
+        return Err(AppError::conflict(format!(
+            "Cannot change the payment status from {} to {}",
+            payment.status.as_str(),
+            status.as_str()
+        )));
+    }
+
+    if let Some(amount) = event.data.amount {
+        if amount != payment.amount {
+            return Err(AppError::conflict(
+                "The event amount does not match the payment",
+            ));
+        }
+    }
+
+    let payment = if status == PaymentStatus::Refunded {
+        refund_on_provider_side(client, &payment, &event.id).await?
+    } else {
+        Payment::set_status(client, payment.id, status).await?
+    };
+
+    if payment.status == PaymentStatus::Paid && !order.status.can_transition_to(OrderStatus::Paid) {
+        let refund = Refund::create(
+            client,
+            NewRefund {
+                id: Uuid::new_v4(),
+                payment_id: payment.id,
+                amount: payment.amount,
+                reason: Some(&format!(
+                    "Captured while the order was {}",
+                    order.status.as_str()
+                )),
+                restocked: false,
+                created_by: None,
+                items: Vec::new(),
+            },
+        )
+        .await?;
+
+        return Ok(Applied::Refunding(Box::new((payment, refund))));
+    }
+
+    let order_status = match payment.status {
+        PaymentStatus::Authorized if order.status == OrderStatus::Pending => {
+            Some(OrderStatus::AwaitingPayment)
+        }
+        PaymentStatus::Paid => Some(OrderStatus::Paid),
+        PaymentStatus::PartiallyRefunded if order.status != OrderStatus::PartiallyRefunded => {
+            Some(OrderStatus::PartiallyRefunded)
+        }
+        PaymentStatus::Refunded => Some(OrderStatus::Refunded),
+        _ => None,
+    };
+
+    if let Some(next) = order_status {
+        if order.status.can_transition_to(next) {
+            Order::transition(client, &order, next, None).await?;
+        } else {
+            eprintln!(
+                "Payment {} is {} but order {} cannot move from {} to {}",
+                payment.id,
+                status.as_str(),
+                order.id,
+                order.status.as_str(),
+                next.as_str()
+            );
+        }
+    }
+
+    Ok(Applied::Processed)
+}
+
+// Applies a stored event in its own transaction and marks it as processed.
+// Unless `replay` is set, events that were already processed are left alone.
+// On failure the error is stored on the event so it can be inspected and replayed.
+// A refund started by the event is sent to the provider once the transaction is committed;
+// if that fails, the refund stays pending and can be retried like any other.
+async fn process_event(
+    client: &mut Client,
+    gateway: &dyn PaymentGateway,
+    event_id: Uuid,
+    replay: bool,
+) -> Result<&'static str, AppError> {
+    let transaction = client.transaction().await?;
+
+    let stored = PaymentEvent::get_for_update(&transaction, event_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Event not found".to_string()))?;
+
+    if stored.processed_at.is_some() && !replay {
+        return Ok("duplicate");
+    }
+
+    let result = match serde_json::from_value::<WebhookEvent>(stored.payload.clone()) {
+        Ok(event) => apply_event(&transaction, &stored.provider, &event).await,
+        Err(err) => Err(AppError::validation(format!("Invalid event: {}", err))),
+    };
+
+    let applied = match result {
+        Ok(applied) => applied,
+        Err(err) => {
+            drop(transaction);
+            PaymentEvent::mark_failed(&*client, stored.id, &err.to_string()).await?;
+            return Err(err);
+        }
+    };
+
+    PaymentEvent::mark_processed(&transaction, stored.id).await?;
+    transaction.commit().await?;
+
+    match applied {
+        Applied::Ignored => Ok("ignored"),
+        Applied::Processed => Ok("processed"),
+        Applied::Refunding(refunding) => {
+            let (payment, refund) = *refunding;
+            let refund_id = refund.id;
+            if let Err(err) = settle_refund(client, gateway, &payment, refund, None).await {
+                eprintln!(
+                    "Refund {} of payment {} is still pending: {:?}",
+                    refund_id, payment.id, err
+                );
+            }
+            Ok("processed")
+        }
+    }
+}
+
+/// Handler receiving payment events from a provider.
+///
+/// The request must be signed with the provider's secret, and its timestamp must be recent.
+/// Every event is stored before it is applied; redeliveries of an event that was already
+/// applied are acknowledged without changing anything.
+pub async fn receive_webhook(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    gateway: web::Data<dyn PaymentGateway>,
+    provider: web::Path<String>,
+    body: web::Bytes, // Raw body, the signature covers it byte for byte
+) -> Result<HttpResponse, AppError> {
+    let provider = provider.into_inner();
+    let secret = webhook_secret(&provider)
+        .ok_or_else(|| AppError::NotFound("Unknown payment provider".to_string()))?;
+
+    verify_signature(&req, &secret, &body)?;
+
+    let payload: Value = serde_json::from_slice(&body)
+        .map_err(|err| AppError::validation(format!("Invalid event: {}", err)))?;
+    let event: WebhookEvent = serde_json::from_value(payload.clone())
+        .map_err(|err| AppError::validation(format!("Invalid event: {}", err)))?;
+
+    let mut client = pool.get().await?;
+
+    let (stored, _) =
+        PaymentEvent::record(&client, &provider, &event.id, &event.event_type, &payload).await?;
+    let status = process_event(&mut client, gateway.get_ref(), stored.id, false).await?;
+
+    Ok(HttpResponse::Ok().json(WebhookResponse { status }))
+}
+
+/// Handler listing received payment events, newest first. Restricted to admins.
+pub async fn list_events(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    query: web::Query<ListEventsQuery>,
+) -> Result<HttpResponse, AppError> {
+    let (limit, offset) = page_bounds(query.limit, query.offset)?;
+
+    let client = pool.get().await?;
+    let (events, total) =
+        PaymentEvent::list(&client, query.unprocessed.unwrap_or(false), limit, offset).await?;
+
+    Ok(HttpResponse::Ok().json(Page::new(events, total, limit, offset)))
+}
+
+/// Handler applying a stored event again, e.g. after fixing what made it fail.
+/// Restricted to admins.
+pub async fn replay_event(
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    gateway: web::Data<dyn PaymentGateway>,
+    event_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let mut client = pool.get().await?;
+    let status = process_event(&mut client, gateway.get_ref(), *event_id, true).await?;
+
+    Ok(HttpResponse::Ok().json(WebhookResponse { status }))
+}
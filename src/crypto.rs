@@ -0,0 +1,36 @@
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Generates an opaque, URL safe random token with 256 bits of entropy.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Returns the hex encoded SHA-256 digest of the input.
+/// Used to store secrets such as refresh tokens without keeping them in plain text.
+pub fn sha256_hex(input: &str) -> String {
+    hex::encode(Sha256::digest(input.as_bytes()))
+}
+
+/// Returns the hex encoded HMAC-SHA256 of `message` keyed with `secret`.
+pub fn hmac_sha256_hex(secret: &[u8], message: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Checks a hex encoded HMAC-SHA256 signature of `message` in constant time.
+pub fn verify_hmac_sha256(secret: &[u8], message: &[u8], signature_hex: &str) -> bool {
+    let Ok(signature) = hex::decode(signature_hex) else {
+        return false;
+    };
+
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.verify_slice(&signature).is_ok()
+}
@@ -1,32 +1,63 @@
-use tokio_postgres::{NoTls, Client};
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod, Runtime, Timeouts};
 use dotenv::dotenv;
 use std::env;
 use std::error::Error as StdError;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio_postgres::NoTls;
 
-// Function to establish a connection to the database
-pub async fn establish_connection() -> Result<Client, Box<dyn StdError>> {
+// Reads a numeric setting from the environment, falling back to a default when unset
+fn env_or<T: FromStr>(key: &str, default: T) -> Result<T, Box<dyn StdError>> {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| format!("{} must be a number, got {:?}", key, value).into()),
+        Err(_) => Ok(default),
+    }
+}
+
+// Function to build the database connection pool
+//
+// The pool is configured through the following environment variables:
+// - DATABASE_URL: connection string (required)
+// - DB_POOL_MAX_SIZE: maximum number of open connections (default 16)
+// - DB_POOL_TIMEOUT_SECS: how long a request waits for a free connection (default 5)
+// - DB_CONNECT_TIMEOUT_SECS: how long opening or health checking a connection may take (default 5)
+pub fn create_pool() -> Result<Pool, Box<dyn StdError>> {
     // Loads environment variables from the .env file
     dotenv().ok();
 
     // Retrieves the database URL from environment variables
-    let database_url = env::var("DATABASE_URL")
-        .map_err(|_| "DATABASE_URL not configured in .env")?;
-
-    // Attempts to connect to the database
-    let (client, connection) = tokio_postgres::connect(&database_url, NoTls)
-        .await
-        .map_err(|e| format!("Error connecting to the database: {}", e))?;
-
-    // Spawns a new async task to handle the connection
-    tokio::spawn(async move {
-        if let Err(e) = connection.await {
-            // Logs an error if the database connection fails
-            eprintln!("Error with database connection: {}", e);
-        }
-    });
-
-    // Returns the database client to interact with the database
-    Ok(client)
-}
+    let database_url =
+        env::var("DATABASE_URL").map_err(|_| "DATABASE_URL not configured in .env")?;
+
+    let pg_config = tokio_postgres::Config::from_str(&database_url)
+        .map_err(|e| format!("Invalid DATABASE_URL: {}", e))?;
 
+    let max_size = env_or("DB_POOL_MAX_SIZE", 16)?;
+    let wait_timeout = Duration::from_secs(env_or("DB_POOL_TIMEOUT_SECS", 5)?);
+    let connect_timeout = Duration::from_secs(env_or("DB_CONNECT_TIMEOUT_SECS", 5)?);
 
+    // Every connection is health checked with a round trip before being handed out, so
+    // connections broken by a database restart are discarded and transparently reopened
+    let manager = Manager::from_config(
+        pg_config,
+        NoTls,
+        ManagerConfig {
+            recycling_method: RecyclingMethod::Verified,
+        },
+    );
+
+    let pool = Pool::builder(manager)
+        .max_size(max_size)
+        .timeouts(Timeouts {
+            wait: Some(wait_timeout),
+            create: Some(connect_timeout),
+            recycle: Some(connect_timeout),
+        })
+        .runtime(Runtime::Tokio1)
+        .build()
+        .map_err(|e| format!("Error creating the database pool: {}", e))?;
+
+    Ok(pool)
+}
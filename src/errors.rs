@@ -0,0 +1,186 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+use tokio_postgres::error::SqlState;
+
+use crate::payment_gateway::GatewayError;
+
+/// Crate-wide error type returned by models and handlers.
+///
+/// Every variant is rendered as a JSON body of the form `{code, message, details}`
+/// with the matching HTTP status code.
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{message}")]
+    Conflict {
+        message: String,
+        details: Option<Value>,
+    },
+
+    #[error("{message}")]
+    Validation {
+        message: String,
+        details: Option<Value>,
+    },
+
+    #[error("{message}")]
+    PreconditionFailed {
+        message: String,
+        details: Option<Value>,
+    },
+
+    #[error("{0}")]
+    PreconditionRequired(String),
+
+    #[error("{0}")]
+    Unprocessable(String),
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("database error: {0}")]
+    Database(tokio_postgres::Error),
+
+    #[error("database unavailable: {0}")]
+    Pool(#[from] deadpool_postgres::PoolError),
+
+    #[error("{0}")]
+    Gateway(#[from] GatewayError),
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+/// JSON body sent to the client for every error response.
+#[derive(Serialize)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+    pub details: Option<Value>,
+}
+
+impl AppError {
+    /// Shortcut for a validation error without details.
+    pub fn validation(message: impl Into<String>) -> Self {
+        AppError::Validation {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Shortcut for a conflict error without details.
+    pub fn conflict(message: impl Into<String>) -> Self {
+        AppError::Conflict {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Stable, machine readable identifier of the error kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict { .. } => "conflict",
+            AppError::Validation { .. } => "validation_error",
+            AppError::PreconditionFailed { .. } => "precondition_failed",
+            AppError::PreconditionRequired(_) => "precondition_required",
+            AppError::Unprocessable(_) => "unprocessable_entity",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Database(_) => "database_error",
+            AppError::Pool(_) => "service_unavailable",
+            AppError::Gateway(GatewayError::Declined(_)) => "payment_declined",
+            AppError::Gateway(GatewayError::Timeout) => "gateway_timeout",
+            AppError::Gateway(GatewayError::Provider(_)) => "payment_provider_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl From<tokio_postgres::Error> for AppError {
+    // Constraint violations are caused by the request, not by the server, so they are
+    // reported as conflicts or validation errors instead of opaque database errors.
+    fn from(err: tokio_postgres::Error) -> Self {
+        match err.code() {
+            Some(code) if *code == SqlState::UNIQUE_VIOLATION => {
+                AppError::conflict("The resource already exists")
+            }
+            Some(code) if *code == SqlState::FOREIGN_KEY_VIOLATION => {
+                AppError::conflict("The resource is referenced by or references another resource")
+            }
+            Some(code) if *code == SqlState::CHECK_VIOLATION => {
+                AppError::validation("The request violates a data constraint")
+            }
+            _ => AppError::Database(err),
+        }
+    }
+}
+
+impl From<bcrypt::BcryptError> for AppError {
+    fn from(err: bcrypt::BcryptError) -> Self {
+        AppError::Internal(format!("password hashing failed: {}", err))
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for AppError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        AppError::Internal(format!("token handling failed: {}", err))
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::Validation { .. } => StatusCode::BAD_REQUEST,
+            AppError::PreconditionFailed { .. } => StatusCode::PRECONDITION_FAILED,
+            AppError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            AppError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Pool(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Gateway(GatewayError::Declined(_)) => StatusCode::PAYMENT_REQUIRED,
+            AppError::Gateway(GatewayError::Timeout) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::Gateway(GatewayError::Provider(_)) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+
+        // Server side failures are logged in full but never leak internals to the client
+        let message = if status.is_server_error() {
+            eprintln!("Request failed: {:?}", self);
+            match self {
+                AppError::Pool(_) => "The service is temporarily unavailable".to_string(),
+                AppError::Gateway(GatewayError::Timeout) => self.to_string(),
+                AppError::Gateway(_) => "The payment provider failed".to_string(),
+                _ => "An internal error occurred".to_string(),
+            }
+        } else {
+            self.to_string()
+        };
+
+        let details = match self {
+            AppError::Conflict { details, .. }
+            | AppError::Validation { details, .. }
+            | AppError::PreconditionFailed { details, .. } => details.clone(),
+            _ => None,
+        };
+
+        HttpResponse::build(status).json(ErrorBody {
+            code: self.code(),
+            message,
+            details,
+        })
+    }
+}
@@ -0,0 +1,114 @@
+use actix_web::{http::StatusCode, HttpRequest, HttpResponse};
+use deadpool_postgres::GenericClient;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    crypto::sha256_hex,
+    errors::AppError,
+    models::idempotency_key_model::{IdempotencyKey, KeyClaim},
+};
+
+/// Header clients set to make a request safe to retry.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Header set on responses replayed from a previous request with the same key.
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// Longest key accepted.
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Idempotency context of a request. Requests without an `Idempotency-Key` header run as usual.
+pub struct Idempotency {
+    user_id: Uuid,
+    key: Option<String>,
+    request_hash: String, // SHA-256 of the method, path and JSON body
+}
+
+impl Idempotency {
+    /// Reads the `Idempotency-Key` header of a request made by `user_id` with the given body.
+    pub fn from_request(
+        req: &HttpRequest,
+        user_id: Uuid,
+        body: &impl Serialize,
+    ) -> Result<Self, AppError> {
+        let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+            Some(value) => {
+                let key = value
+                    .to_str()
+                    .map(str::trim)
+                    .map_err(|_| AppError::validation("Invalid Idempotency-Key header"))?;
+                if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+                    return Err(AppError::validation(format!(
+                        "Idempotency-Key must be between 1 and {} characters",
+                        MAX_KEY_LENGTH
+                    )));
+                }
+                Some(key.to_string())
+            }
+            None => None,
+        };
+
+        let body = serde_json::to_string(body)
+            .map_err(|err| AppError::Internal(format!("request serialization failed: {}", err)))?;
+        let request_hash = sha256_hex(&format!("{} {}\n{}", req.method(), req.path(), body));
+
+        Ok(Idempotency {
+            user_id,
+            key,
+            request_hash,
+        })
+    }
+
+    /// Claims the key in the request's transaction.
+    /// Returns the stored response if the request was already made with this key, and
+    /// rejects the request with 422 if the key was used for a different request.
+    pub async fn replay(
+        &self,
+        client: &impl GenericClient,
+    ) -> Result<Option<HttpResponse>, AppError> {
+        let Some(key) = &self.key else {
+            return Ok(None);
+        };
+
+        match IdempotencyKey::claim(client, self.user_id, key, &self.request_hash).await? {
+            KeyClaim::New => Ok(None),
+            KeyClaim::Completed { status_code, body } => {
+                let status = u16::try_from(status_code)
+                    .ok()
+                    .and_then(|code| StatusCode::from_u16(code).ok())
+                    .ok_or_else(|| {
+                        AppError::Internal(format!("invalid stored status {}", status_code))
+                    })?;
+
+                Ok(Some(
+                    HttpResponse::build(status)
+                        .insert_header((REPLAYED_HEADER, "true"))
+                        .json(body),
+                ))
+            }
+            KeyClaim::Mismatch => Err(AppError::Unprocessable(
+                "The Idempotency-Key was already used for a different request".to_string(),
+            )),
+        }
+    }
+
+    /// Stores the response in the request's transaction, if the request has a key, and
+    /// returns it to be sent to the client once the transaction is committed.
+    pub async fn respond(
+        &self,
+        client: &impl GenericClient,
+        status: StatusCode,
+        body: &impl Serialize,
+    ) -> Result<HttpResponse, AppError> {
+        let body = serde_json::to_value(body)
+            .map_err(|err| AppError::Internal(format!("response serialization failed: {}", err)))?;
+
+        if let Some(key) = &self.key {
+            IdempotencyKey::complete(client, self.user_id, key, status.as_u16() as i16, &body)
+                .await?;
+        }
+
+        Ok(HttpResponse::build(status).json(body))
+    }
+}
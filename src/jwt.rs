@@ -1,36 +1,39 @@
-use std::{env, sync::Arc};
+use std::env;
 
-use actix_web::error;
-use actix_web::{
-    dev::{Service, ServiceRequest, ServiceResponse},
-    Error,
-};
+use deadpool_postgres::GenericClient;
 use jsonwebtoken::{encode, EncodingKey, Header};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use actix_service::Transform;
+use crate::errors::AppError;
+use crate::models::revoked_token_model::RevokedToken;
+use crate::models::user_model::{User, UserRole};
 
-use futures::future::{ok, Either, Ready};
-
-use std::task::{Context, Poll};
+/// Lifetime of an access token, in seconds.
+pub const ACCESS_TOKEN_TTL_SECS: i64 = 3600;
 
 #[derive(Serialize, Deserialize)]
 pub struct Claims {
     pub sub: Uuid,  // User ID
     pub exp: usize, // Expiration timestamp
+    #[serde(default)]
+    pub role: UserRole, // Role of the user, replaced by their current role on validation
+    #[serde(default)]
+    pub jti: Uuid, // Unique token ID, used to revoke the token
 }
 
-/// Creates a JWT for the given user ID.
-pub fn create_jwt(user_id: Uuid) -> Result<String, Box<dyn std::error::Error>> {
+/// Creates a JWT for the given user ID and role.
+pub fn create_jwt(user_id: Uuid, role: UserRole) -> Result<String, AppError> {
     let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::seconds(3600))
+        .checked_add_signed(chrono::Duration::seconds(ACCESS_TOKEN_TTL_SECS))
         .expect("Invalid expiration time")
         .timestamp() as usize;
 
     let claims = Claims {
         sub: user_id,
         exp: expiration,
+        role,
+        jti: Uuid::new_v4(),
     };
 
     let secret = env::var("JWT_SECRET").expect("JWT_SECRET not set");
@@ -40,71 +43,42 @@ pub fn create_jwt(user_id: Uuid) -> Result<String, Box<dyn std::error::Error>> {
     Ok(token)
 }
 
-/// Middleware for JWT authentication
-pub struct JwtMiddleware<S> {
-    service: S,
-}
-
-impl<S, Req> Transform<S, ServiceRequest> for JwtMiddleware<Req>
-where
-    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
-    S::Future: 'static,
-{
-    type Response = ServiceResponse;
-    type Error = Error;
-    type InitError = ();
-    type Transform = JwtMiddleware<S>;
-    type Future = Ready<Result<Self::Transform, Self::InitError>>;
-
-    fn new_transform(&self, service: S) -> Self::Future {
-        ok(JwtMiddleware { service })
-    }
-}
-
-impl<S> Service<ServiceRequest> for JwtMiddleware<S>
-where
-    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
-    S::Future: 'static,
-{
-    type Response = ServiceResponse;
-    type Error = Error;
-    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
-
-    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.service.poll_ready(cx)
-    }
+/// Returns how long refresh tokens stay valid, configured with REFRESH_TOKEN_TTL_DAYS (default 30).
+pub fn refresh_token_ttl() -> chrono::Duration {
+    let days = env::var("REFRESH_TOKEN_TTL_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(30);
 
-    fn call(&self, req: ServiceRequest) -> Self::Future {
-        // Extract token from the Authorization header
-        if let Some(auth_header) = req.headers().get("Authorization") {
-            if let Ok(token) = auth_header.to_str() {
-                match validate_token(token) {
-                    Ok(_) => {
-                        // Token is valid, proceed to the next service
-                        return Either::Left(self.service.call(req));
-                    }
-                    Err(_) => {
-                        return Either::Right(ok(
-                            req.error_response(error::ErrorUnauthorized("Invalid token"))
-                        ))
-                    }
-                }
-            }
-        }
-
-        Either::Right(ok(req.error_response(error::ErrorUnauthorized(
-            "Authorization token missing",
-        ))))
-    }
+    chrono::Duration::days(days)
 }
 
-// JWT token validation function
-pub fn validate_token(token: &str) -> Result<Claims, Box<dyn std::error::Error>> {
-    println!("Validating token: {}", token);
+/// Verifies the signature and expiration of a JWT and returns its claims.
+/// This does not check the revocation list; use `validate_token` for that.
+pub fn decode_token(token: &str) -> Result<Claims, AppError> {
     let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET not set");
     let decoding_key = jsonwebtoken::DecodingKey::from_secret(secret.as_ref());
     let validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
 
-    let token_data = jsonwebtoken::decode::<Claims>(token, &decoding_key, &validation)?;
+    let token_data = jsonwebtoken::decode::<Claims>(token, &decoding_key, &validation)
+        .map_err(|e| AppError::Unauthorized(format!("Invalid token: {}", e)))?;
     Ok(token_data.claims)
 }
+
+// JWT token validation function
+// Rejects tokens that are malformed, expired, or whose `jti` has been revoked.
+// The role is read from `users` so that promotions and demotions apply to live tokens,
+// and tokens of deleted users are rejected.
+pub async fn validate_token(client: &impl GenericClient, token: &str) -> Result<Claims, AppError> {
+    let mut claims = decode_token(token)?;
+
+    if RevokedToken::is_revoked(client, claims.jti).await? {
+        return Err(AppError::Unauthorized("Token has been revoked".to_string()));
+    }
+
+    claims.role = User::current_role(client, claims.sub)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("User no longer exists".to_string()))?;
+
+    Ok(claims)
+}
@@ -1,8 +1,16 @@
+pub mod app_state;
+pub mod auth;
+pub mod controllers;
+pub mod crypto;
 pub mod db;
-pub mod models;
+pub mod errors;
+pub mod idempotency;
 pub mod jwt;
-pub mod server;
+pub mod migrations;
+pub mod models;
+pub mod pagination;
+pub mod payment_gateway;
 pub mod routes;
-pub mod controllers;
-pub mod auth;
-pub mod app_state;
\ No newline at end of file
+pub mod server;
+pub mod shipping;
+pub mod tax;
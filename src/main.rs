@@ -1,26 +1,91 @@
-mod jwt;
-mod controllers;
-mod db;
-mod models;
-mod routes;
-mod server;
-mod auth;
+use std::env;
 
-use std::sync::Arc;
-
-use db::establish_connection;
+use rustify_store::{
+    db::create_pool, migrations, models::cart_model::Cart, payment_gateway, server,
+};
+use tokio_postgres::Client;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("debug"));
-    // Establishes the connection to the database
-    let client = establish_connection()
-        .await
-        .expect("Failed to connect to database");
+    // Builds the database connection pool
+    let pool = create_pool().expect("Failed to configure database pool");
+
+    // Checks out a connection up front so a bad configuration fails at startup
+    let mut client = pool.get().await.expect("Failed to connect to database");
+
+    // `rustify_store migrate up|down|status` manages the schema and exits without serving
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("migrate") {
+        return run_migrate_command(&mut client, args.get(2).map(String::as_str)).await;
+    }
+
+    // Brings the schema up to date before serving, unless disabled with AUTO_MIGRATE=false
+    if env::var("AUTO_MIGRATE")
+        .map(|v| v != "false")
+        .unwrap_or(true)
+    {
+        let applied = migrations::run_pending(&mut client)
+            .await
+            .expect("Failed to apply database migrations");
+        for migration in applied {
+            println!("Applied migration {}", migration.name);
+        }
+    }
+
+    // Removes the guest carts that expired while the server was down; afterwards they are
+    // removed whenever a visitor starts a new cart
+    match Cart::remove_expired_guests(&client).await {
+        Ok(0) => {}
+        Ok(removed) => println!("Removed {} expired guest carts", removed),
+        Err(e) => eprintln!("Failed to remove expired guest carts: {}", e),
+    }
+
+    // Returns the connection to the pool before serving
+    drop(client);
+
+    let gateway = payment_gateway::from_env().expect("Failed to configure payment gateway");
+
+    // Starts the server, passing the connection pool and payment gateway as application data
+    server::run_server(pool, gateway).await
+}
+
+// Handles the `migrate` subcommand
+async fn run_migrate_command(client: &mut Client, action: Option<&str>) -> std::io::Result<()> {
+    let to_io_error = |e: tokio_postgres::Error| std::io::Error::other(e.to_string());
 
-    // Wraps the database client in an Arc (atomic reference counted) for shared access across threads
-    let client = Arc::new(client);
+    match action {
+        Some("up") => {
+            let applied = migrations::run_pending(client).await.map_err(to_io_error)?;
+            if applied.is_empty() {
+                println!("Database schema is up to date");
+            }
+            for migration in applied {
+                println!("Applied migration {}", migration.name);
+            }
+        }
+        Some("down") => match migrations::rollback_last(client)
+            .await
+            .map_err(to_io_error)?
+        {
+            Some(migration) => println!("Reverted migration {}", migration.name),
+            None => println!("No migrations to revert"),
+        },
+        Some("status") => {
+            for status in migrations::status(client).await.map_err(to_io_error)? {
+                match status.applied_at {
+                    Some(applied_at) => {
+                        println!("[x] {} (applied {})", status.name, applied_at.to_rfc3339())
+                    }
+                    None => println!("[ ] {} (pending)", status.name),
+                }
+            }
+        }
+        _ => {
+            eprintln!("Usage: rustify_store migrate <up|down|status>");
+            std::process::exit(2);
+        }
+    }
 
-    // Starts the server, passing the database client as application data
-    server::run_server(client).await
+    Ok(())
 }
@@ -0,0 +1,183 @@
+use chrono::{DateTime, Utc};
+use tokio_postgres::{Client, Error};
+
+/// A versioned schema migration whose SQL is embedded in the binary at compile time.
+pub struct Migration {
+    pub version: i64,       // Monotonically increasing version number.
+    pub name: &'static str, // Human readable name, taken from the file name.
+    pub up: &'static str,   // SQL applied when migrating up.
+    pub down: &'static str, // SQL applied when rolling the migration back.
+}
+
+/// Reports whether a migration has been applied to the database, and when.
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: &'static str,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+// Embeds the `.up.sql` and `.down.sql` files of a migration from the `migrations/` directory.
+macro_rules! migration {
+    ($version:expr, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            up: include_str!(concat!("../migrations/", $name, ".up.sql")),
+            down: include_str!(concat!("../migrations/", $name, ".down.sql")),
+        }
+    };
+}
+
+/// Every migration known to the application, in the order they must be applied.
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_initial_schema"),
+    migration!(2, "0002_user_roles"),
+    migration!(3, "0003_refresh_tokens"),
+    migration!(4, "0004_product_versions"),
+    migration!(5, "0005_order_status"),
+    migration!(6, "0006_order_totals"),
+    migration!(7, "0007_payment_gateway"),
+    migration!(8, "0008_payment_events"),
+    migration!(9, "0009_refunds"),
+    migration!(10, "0010_idempotency_keys"),
+    migration!(11, "0011_carts"),
+    migration!(12, "0012_guest_carts"),
+    migration!(13, "0013_coupons"),
+    migration!(14, "0014_taxes"),
+    migration!(15, "0015_shipping"),
+    migration!(16, "0016_shipments"),
+    migration!(17, "0017_categories"),
+    migration!(18, "0018_variants"),
+    migration!(19, "0019_product_search"),
+    migration!(20, "0020_order_status_history_order"),
+    migration!(21, "0021_refund_status"),
+    migration!(22, "0022_payment_intents"),
+];
+
+// Arbitrary key for the advisory lock that keeps concurrent instances from migrating at once.
+const MIGRATION_LOCK_KEY: i64 = 7_297_036_125;
+
+/// Creates the table used to track which migrations have been applied.
+async fn ensure_tracking_table(client: &Client) -> Result<(), Error> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version BIGINT PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+        )
+        .await
+}
+
+/// Returns the versions already applied to the database, in ascending order.
+async fn applied_versions(client: &Client) -> Result<Vec<i64>, Error> {
+    let rows = client
+        .query(
+            "SELECT version FROM schema_migrations ORDER BY version",
+            &[],
+        )
+        .await?;
+
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Applies every pending migration, each one inside its own transaction.
+/// Returns the migrations that were applied by this call.
+pub async fn run_pending(client: &mut Client) -> Result<Vec<&'static Migration>, Error> {
+    ensure_tracking_table(client).await?;
+    client
+        .execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK_KEY])
+        .await?;
+
+    let result = apply_pending(client).await;
+
+    client
+        .execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK_KEY])
+        .await?;
+
+    result
+}
+
+async fn apply_pending(client: &mut Client) -> Result<Vec<&'static Migration>, Error> {
+    let applied = applied_versions(client).await?;
+    let mut newly_applied = Vec::new();
+
+    for migration in MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+        let transaction = client.transaction().await?;
+        transaction.batch_execute(migration.up).await?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)",
+                &[&migration.version, &migration.name],
+            )
+            .await?;
+        transaction.commit().await?;
+
+        newly_applied.push(migration);
+    }
+
+    Ok(newly_applied)
+}
+
+/// Rolls back the most recently applied migration, if any.
+/// Returns the migration that was reverted.
+pub async fn rollback_last(client: &mut Client) -> Result<Option<&'static Migration>, Error> {
+    ensure_tracking_table(client).await?;
+    client
+        .execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK_KEY])
+        .await?;
+
+    let result = revert_last(client).await;
+
+    client
+        .execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK_KEY])
+        .await?;
+
+    result
+}
+
+async fn revert_last(client: &mut Client) -> Result<Option<&'static Migration>, Error> {
+    let applied = applied_versions(client).await?;
+
+    // Only migrations embedded in this binary can be reverted.
+    let Some(migration) = applied
+        .last()
+        .and_then(|version| MIGRATIONS.iter().find(|m| m.version == *version))
+    else {
+        return Ok(None);
+    };
+
+    let transaction = client.transaction().await?;
+    transaction.batch_execute(migration.down).await?;
+    transaction
+        .execute(
+            "DELETE FROM schema_migrations WHERE version = $1",
+            &[&migration.version],
+        )
+        .await?;
+    transaction.commit().await?;
+
+    Ok(Some(migration))
+}
+
+/// Lists every known migration together with the time it was applied, if it was.
+pub async fn status(client: &Client) -> Result<Vec<MigrationStatus>, Error> {
+    ensure_tracking_table(client).await?;
+
+    let rows = client
+        .query("SELECT version, applied_at FROM schema_migrations", &[])
+        .await?;
+
+    Ok(MIGRATIONS
+        .iter()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            name: migration.name,
+            applied_at: rows
+                .iter()
+                .find(|row| row.get::<_, i64>(0) == migration.version)
+                .map(|row| row.get(1)),
+        })
+        .collect())
+}
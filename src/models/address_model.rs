@@ -0,0 +1,265 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// An address of a user's address book.
+#[derive(Serialize, Debug)]
+pub struct Address {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String, // Recipient
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub postal_code: String,
+    pub region: String, // ISO 3166 country ("DE") or subdivision ("US-CA")
+    pub phone: Option<String>,
+    pub is_default: bool, // Address offered first; a user has at most one
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Copy of an address stored on an order, unaffected by later changes to the address book.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AddressSnapshot {
+    pub name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub postal_code: String,
+    pub region: String,
+    pub phone: Option<String>,
+}
+
+/// Attributes of an address to create.
+pub struct NewAddress<'a> {
+    pub name: &'a str,
+    pub line1: &'a str,
+    pub line2: Option<&'a str>,
+    pub city: &'a str,
+    pub postal_code: &'a str,
+    pub region: &'a str,
+    pub phone: Option<&'a str>,
+    pub is_default: bool,
+}
+
+/// Changes applied by a partial address update; `None` leaves a field untouched.
+#[derive(Default)]
+pub struct AddressChanges {
+    pub name: Option<String>,
+    pub line1: Option<String>,
+    pub line2: Option<Option<String>>, // `Some(None)` clears the line
+    pub city: Option<String>,
+    pub postal_code: Option<String>,
+    pub region: Option<String>,
+    pub phone: Option<Option<String>>, // `Some(None)` clears the phone number
+    pub is_default: Option<bool>,
+}
+
+// Columns selected for every address query, in the order expected by `Address::from_row`.
+const ADDRESS_COLUMNS: &str = "id, user_id, name, line1, line2, city, postal_code, region, \
+                               phone, is_default, created_at, updated_at";
+
+impl Address {
+    fn from_row(row: &Row) -> Address {
+        Address {
+            id: row.get(0),
+            user_id: row.get(1),
+            name: row.get(2),
+            line1: row.get(3),
+            line2: row.get(4),
+            city: row.get(5),
+            postal_code: row.get(6),
+            region: row.get(7),
+            phone: row.get(8),
+            is_default: row.get(9),
+            created_at: row.get(10),
+            updated_at: row.get(11),
+        }
+    }
+
+    /// Returns the copy of the address to store on an order.
+    pub fn snapshot(&self) -> AddressSnapshot {
+        AddressSnapshot {
+            name: self.name.clone(),
+            line1: self.line1.clone(),
+            line2: self.line2.clone(),
+            city: self.city.clone(),
+            postal_code: self.postal_code.clone(),
+            region: self.region.clone(),
+            phone: self.phone.clone(),
+        }
+    }
+
+    /// Adds an address to a user's address book. The first address of a user becomes the
+    /// default one; making an address the default takes the flag off the previous default.
+    /// Must run in a transaction.
+    pub async fn create(
+        client: &impl GenericClient,
+        user_id: Uuid,
+        address: &NewAddress<'_>,
+    ) -> Result<Address, AppError> {
+        // Serializes concurrent changes to the default address of the user
+        client
+            .execute("SELECT 1 FROM users WHERE id = $1 FOR UPDATE", &[&user_id])
+            .await?;
+
+        let has_addresses: bool = client
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM addresses WHERE user_id = $1)",
+                &[&user_id],
+            )
+            .await?
+            .get(0);
+        let is_default = address.is_default || !has_addresses;
+
+        if is_default {
+            Self::clear_default(client, user_id).await?;
+        }
+
+        let query = format!(
+            "INSERT INTO addresses (id, user_id, name, line1, line2, city, postal_code, region,
+                                    phone, is_default)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+             RETURNING {}",
+            ADDRESS_COLUMNS
+        );
+        let row = client
+            .query_one(
+                &query,
+                &[
+                    &Uuid::new_v4(),
+                    &user_id,
+                    &address.name,
+                    &address.line1,
+                    &address.line2,
+                    &address.city,
+                    &address.postal_code,
+                    &address.region,
+                    &address.phone,
+                    &is_default,
+                ],
+            )
+            .await?;
+
+        Ok(Self::from_row(&row))
+    }
+
+    /// Lists the addresses of a user, the default one first.
+    pub async fn list_for_user(
+        client: &impl GenericClient,
+        user_id: Uuid,
+    ) -> Result<Vec<Address>, AppError> {
+        let query = format!(
+            "SELECT {} FROM addresses WHERE user_id = $1
+             ORDER BY is_default DESC, created_at, id",
+            ADDRESS_COLUMNS
+        );
+        let rows = client.query(&query, &[&user_id]).await?;
+
+        Ok(rows.iter().map(Self::from_row).collect())
+    }
+
+    /// Retrieves an address of a user. Returns None if the user has no such address.
+    pub async fn get_for_user(
+        client: &impl GenericClient,
+        user_id: Uuid,
+        address_id: Uuid,
+    ) -> Result<Option<Address>, AppError> {
+        let query = format!(
+            "SELECT {} FROM addresses WHERE id = $1 AND user_id = $2",
+            ADDRESS_COLUMNS
+        );
+        let row = client.query_opt(&query, &[&address_id, &user_id]).await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Applies a partial update to an address of a user. Returns None if the user has no
+    /// such address. Must run in a transaction.
+    pub async fn update(
+        client: &impl GenericClient,
+        user_id: Uuid,
+        address_id: Uuid,
+        changes: &AddressChanges,
+    ) -> Result<Option<Address>, AppError> {
+        client
+            .execute("SELECT 1 FROM users WHERE id = $1 FOR UPDATE", &[&user_id])
+            .await?;
+
+        if changes.is_default == Some(true) {
+            Self::clear_default(client, user_id).await?;
+        }
+
+        let query = format!(
+            "UPDATE addresses SET
+                name = COALESCE($3, name),
+                line1 = COALESCE($4, line1),
+                line2 = CASE WHEN $5 THEN $6 ELSE line2 END,
+                city = COALESCE($7, city),
+                postal_code = COALESCE($8, postal_code),
+                region = COALESCE($9, region),
+                phone = CASE WHEN $10 THEN $11 ELSE phone END,
+                is_default = COALESCE($12, is_default),
+                updated_at = now()
+             WHERE id = $1 AND user_id = $2
+             RETURNING {}",
+            ADDRESS_COLUMNS
+        );
+        let row = client
+            .query_opt(
+                &query,
+                &[
+                    &address_id,
+                    &user_id,
+                    &changes.name,
+                    &changes.line1,
+                    &changes.line2.is_some(),
+                    &changes.line2.clone().flatten(),
+                    &changes.city,
+                    &changes.postal_code,
+                    &changes.region,
+                    &changes.phone.is_some(),
+                    &changes.phone.clone().flatten(),
+                    &changes.is_default,
+                ],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Deletes an address of a user. Orders keep their copy of it.
+    /// Returns false if the user has no such address.
+    pub async fn delete(
+        client: &impl GenericClient,
+        user_id: Uuid,
+        address_id: Uuid,
+    ) -> Result<bool, AppError> {
+        let deleted = client
+            .execute(
+                "DELETE FROM addresses WHERE id = $1 AND user_id = $2",
+                &[&address_id, &user_id],
+            )
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    // Takes the default flag off the current default address of a user.
+    async fn clear_default(client: &impl GenericClient, user_id: Uuid) -> Result<(), AppError> {
+        client
+            .execute(
+                "UPDATE addresses SET is_default = FALSE, updated_at = now()
+                 WHERE user_id = $1 AND is_default",
+                &[&user_id],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::order_model::store_currency;
+
+/// Why an item of the cart cannot be ordered in its current quantity.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StockWarning {
+    OutOfStock,        // Nothing left in stock
+    InsufficientStock, // Less in stock than the quantity in the cart
+}
+
+/// A product in a cart, priced at the product's current price.
+#[derive(Serialize, Debug)]
+pub struct CartItem {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub variant_id: Option<Uuid>,
+    pub sku: Option<String>, // SKU of the variant, if any
+    pub name: String,
+    pub quantity: i32,
+    pub unit_price: Decimal, // Current price of the product or variant
+    pub previous_price: Option<Decimal>, // Price when the cart was last viewed, only if it changed
+    pub line_total: Decimal,
+    pub available_stock: i32,
+    pub stock_warning: Option<StockWarning>,
+}
+
+/// A cart with its items and subtotal.
+#[derive(Serialize, Debug)]
+pub struct Cart {
+    pub id: Option<Uuid>, // None for visitors who have not added anything yet
+    pub items: Vec<CartItem>,
+    pub subtotal: Decimal,
+    pub currency: String,
+}
+
+/// Number of days a guest cart is kept after its last change.
+pub const GUEST_CART_TTL_DAYS: i32 = 30;
+
+// Columns selected for every item query, in the order expected by `CartItem::from_row`.
+// Variants override the price and stock of their product.
+const CART_ITEM_COLUMNS: &str = "cart_items.id, cart_items.product_id, products.name, \
+     cart_items.quantity, COALESCE(product_variants.price, products.price), \
+     cart_items.unit_price, COALESCE(product_variants.stock, products.stock), \
+     cart_items.variant_id, product_variants.sku";
+
+// Joins the product and variant of every cart item.
+const CART_ITEM_JOINS: &str = "INNER JOIN products ON products.id = cart_items.product_id \
+     LEFT JOIN product_variants ON product_variants.id = cart_items.variant_id";
+
+impl CartItem {
+    fn from_row(row: &Row) -> CartItem {
+        let quantity: i32 = row.get(3);
+        let unit_price: Decimal = row.get(4);
+        let snapshot: Decimal = row.get(5);
+        let available_stock: i32 = row.get(6);
+
+        let stock_warning = if available_stock <= 0 {
+            Some(StockWarning::OutOfStock)
+        } else if available_stock < quantity {
+            Some(StockWarning::InsufficientStock)
+        } else {
+            None
+        };
+
+        CartItem {
+            id: row.get(0),
+            product_id: row.get(1),
+            variant_id: row.get(7),
+            sku: row.get(8),
+            name: row.get(2),
+            quantity,
+            unit_price,
+            previous_price: (snapshot != unit_price).then_some(snapshot),
+            line_total: unit_price * Decimal::from(quantity),
+            available_stock,
+            stock_warning,
+        }
+    }
+}
+
+impl Cart {
+    /// Returns the ID of the user's cart, creating an empty cart on first use.
+    /// Inside a transaction the cart stays locked until the end of it, so changes to a cart
+    /// and its checkout are applied one at a time.
+    pub async fn id_for_user(client: &impl GenericClient, user_id: Uuid) -> Result<Uuid, AppError> {
+        let row = client
+            .query_one(
+                "INSERT INTO carts (id, user_id) VALUES ($1, $2)
+                 ON CONFLICT (user_id) DO UPDATE SET user_id = EXCLUDED.user_id
+                 RETURNING id",
+                &[&Uuid::new_v4(), &user_id],
+            )
+            .await?;
+
+        Ok(row.get(0))
+    }
+
+    /// Returns the ID of a visitor's cart, `guest_cart_id` being the cart named by their cookie,
+    /// or `None` if they have no cart yet or theirs no longer exists. Like `id_for_user`, the
+    /// cart stays locked until the end of the transaction.
+    pub async fn find_guest(
+        client: &impl GenericClient,
+        guest_cart_id: Option<Uuid>,
+    ) -> Result<Option<Uuid>, AppError> {
+        let Some(cart_id) = guest_cart_id else {
+            return Ok(None);
+        };
+
+        let row = client
+            .query_opt(
+                "UPDATE carts SET updated_at = now()
+                 WHERE id = $1 AND user_id IS NULL
+                 RETURNING id",
+                &[&cart_id],
+            )
+            .await?;
+
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    /// Creates an empty cart for a visitor and returns its ID.
+    /// Expired guest carts are removed on the way.
+    pub async fn create_guest(client: &impl GenericClient) -> Result<Uuid, AppError> {
+        Self::remove_expired_guests(client).await?;
+
+        let row = client
+            .query_one(
+                "INSERT INTO carts (id) VALUES ($1) RETURNING id",
+                &[&Uuid::new_v4()],
+            )
+            .await?;
+
+        Ok(row.get(0))
+    }
+
+    /// Deletes the guest carts not used for `GUEST_CART_TTL_DAYS`, with their items, and
+    /// returns how many were deleted.
+    pub async fn remove_expired_guests(client: &impl GenericClient) -> Result<u64, AppError> {
+        let deleted = client
+            .execute(
+                "DELETE FROM carts
+                 WHERE user_id IS NULL AND updated_at < now() - make_interval(days => $1)",
+                &[&GUEST_CART_TTL_DAYS],
+            )
+            .await?;
+
+        Ok(deleted)
+    }
+
+    /// Moves the items of a guest cart into a user's cart and deletes the guest cart.
+    /// Quantities of products (and variants) in both carts are summed; merged quantities are
+    /// capped at the stock, but an item is kept with a quantity of one even when it is out of
+    /// stock.
+    pub async fn merge_guest(
+        client: &impl GenericClient,
+        guest_cart_id: Uuid,
+        cart_id: Uuid,
+    ) -> Result<(), AppError> {
+        let query = format!(
+            "SELECT cart_items.product_id, cart_items.variant_id, cart_items.quantity,
+                    cart_items.unit_price, COALESCE(product_variants.stock, products.stock)
+             FROM carts
+             INNER JOIN cart_items ON cart_items.cart_id = carts.id
+             {}
+             WHERE carts.id = $1 AND carts.user_id IS NULL
+             ORDER BY cart_items.product_id, cart_items.variant_id NULLS FIRST",
+            CART_ITEM_JOINS
+        );
+        let rows = client.query(&query, &[&guest_cart_id]).await?;
+
+        for row in &rows {
+            let product_id: Uuid = row.get(0);
+            let variant_id: Option<Uuid> = row.get(1);
+            let quantity: i32 = row.get(2);
+            let unit_price: Decimal = row.get(3);
+            let stock: i32 = row.get(4);
+
+            client
+                .execute(
+                    "INSERT INTO cart_items (id, cart_id, product_id, variant_id, quantity,
+                                             unit_price)
+                     VALUES ($1, $2, $3, $4, GREATEST(LEAST($5::INT, $7::INT), 1), $6)
+                     ON CONFLICT (cart_id, product_id, variant_id) DO UPDATE
+                     SET quantity = GREATEST(LEAST(cart_items.quantity + $5::INT, $7::INT), 1),
+                         updated_at = now()",
+                    &[
+                        &Uuid::new_v4(),
+                        &cart_id,
+                        &product_id,
+                        &variant_id,
+                        &quantity,
+                        &unit_price,
+                        &stock,
+                    ],
+                )
+                .await?;
+        }
+
+        client
+            .execute(
+                "DELETE FROM carts WHERE id = $1 AND user_id IS NULL",
+                &[&guest_cart_id],
+            )
+            .await?;
+
+        Self::touch(client, cart_id).await
+    }
+
+    /// Retrieves the items of a cart, oldest first, priced at the current product prices.
+    /// Items whose price changed since the last view report the old price in `previous_price`.
+    pub async fn items(
+        client: &impl GenericClient,
+        cart_id: Uuid,
+    ) -> Result<Vec<CartItem>, AppError> {
+        let query = format!(
+            "SELECT {}
+             FROM cart_items
+             {}
+             WHERE cart_items.cart_id = $1
+             ORDER BY cart_items.created_at, cart_items.id",
+            CART_ITEM_COLUMNS, CART_ITEM_JOINS
+        );
+        let rows = client.query(&query, &[&cart_id]).await?;
+
+        Ok(rows.iter().map(CartItem::from_row).collect())
+    }
+
+    /// Loads a cart for display, then stores the current prices as the new snapshots so
+    /// price changes are only reported once.
+    pub async fn view(client: &impl GenericClient, cart_id: Uuid) -> Result<Cart, AppError> {
+        let items = Self::items(client, cart_id).await?;
+
+        if items.iter().any(|item| item.previous_price.is_some()) {
+            Self::refresh_prices(client, cart_id).await?;
+        }
+
+        // Start from 0.00 so an empty cart renders with the same scale as prices
+        let subtotal = items
+            .iter()
+            .fold(Decimal::new(0, 2), |sum, item| sum + item.line_total);
+
+        Ok(Cart {
+            id: Some(cart_id),
+            items,
+            subtotal,
+            currency: store_currency(),
+        })
+    }
+
+    /// Returns the cart shown to visitors who have no cart yet, without storing one.
+    pub fn empty() -> Cart {
+        Cart {
+            id: None,
+            items: Vec::new(),
+            subtotal: Decimal::new(0, 2),
+            currency: store_currency(),
+        }
+    }
+
+    /// Updates the price snapshots of a cart to the current product and variant prices.
+    pub async fn refresh_prices(
+        client: &impl GenericClient,
+        cart_id: Uuid,
+    ) -> Result<(), AppError> {
+        let query = format!(
+            "UPDATE cart_items
+             SET unit_price = prices.price, updated_at = now()
+             FROM (
+                 SELECT cart_items.id,
+                        COALESCE(product_variants.price, products.price) AS price
+                 FROM cart_items
+                 {}
+                 WHERE cart_items.cart_id = $1
+             ) AS prices
+             WHERE prices.id = cart_items.id
+               AND cart_items.unit_price <> prices.price",
+            CART_ITEM_JOINS
+        );
+        client.execute(&query, &[&cart_id]).await?;
+
+        Ok(())
+    }
+
+    /// Adds a product to a cart, or increases its quantity if it is already in the cart.
+    /// Products sold in variants are added through one of their variants.
+    pub async fn add_item(
+        client: &impl GenericClient,
+        cart_id: Uuid,
+        product_id: Uuid,
+        variant_id: Option<Uuid>,
+        quantity: i32,
+    ) -> Result<(), AppError> {
+        let has_variants: bool = client
+            .query_opt(
+                "SELECT EXISTS (SELECT 1 FROM product_variants WHERE product_id = products.id)
+                 FROM products WHERE id = $1",
+                &[&product_id],
+            )
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Product {} not found", product_id)))?
+            .get(0);
+        if has_variants && variant_id.is_none() {
+            return Err(AppError::validation(format!(
+                "Product {} is sold in variants, a variant_id is required",
+                product_id
+            )));
+        }
+
+        let inserted = client
+            .execute(
+                "INSERT INTO cart_items (id, cart_id, product_id, variant_id, quantity, unit_price)
+                 SELECT $1, $2, products.id, product_variants.id, $5,
+                        COALESCE(product_variants.price, products.price)
+                 FROM products
+                 LEFT JOIN product_variants
+                     ON product_variants.id = $4 AND product_variants.product_id = products.id
+                 WHERE products.id = $3 AND ($4::UUID IS NULL OR product_variants.id IS NOT NULL)
+                 ON CONFLICT (cart_id, product_id, variant_id) DO UPDATE
+                 SET quantity = cart_items.quantity + EXCLUDED.quantity, updated_at = now()",
+                &[
+                    &Uuid::new_v4(),
+                    &cart_id,
+                    &product_id,
+                    &variant_id,
+                    &quantity,
+                ],
+            )
+            .await?;
+
+        if inserted == 0 {
+            return Err(AppError::NotFound(format!(
+                "Variant {} of product {} not found",
+                variant_id.unwrap_or_default(),
+                product_id
+            )));
+        }
+
+        Self::touch(client, cart_id).await
+    }
+
+    /// Sets the quantity of an item of a cart. Returns false if the cart has no such item.
+    pub async fn set_item_quantity(
+        client: &impl GenericClient,
+        cart_id: Uuid,
+        item_id: Uuid,
+        quantity: i32,
+    ) -> Result<bool, AppError> {
+        let updated = client
+            .execute(
+                "UPDATE cart_items SET quantity = $1, updated_at = now()
+                 WHERE id = $2 AND cart_id = $3",
+                &[&quantity, &item_id, &cart_id],
+            )
+            .await?;
+
+        if updated > 0 {
+            Self::touch(client, cart_id).await?;
+        }
+
+        Ok(updated > 0)
+    }
+
+    /// Removes an item from a cart. Returns false if the cart has no such item.
+    pub async fn remove_item(
+        client: &impl GenericClient,
+        cart_id: Uuid,
+        item_id: Uuid,
+    ) -> Result<bool, AppError> {
+        let deleted = client
+            .execute(
+                "DELETE FROM cart_items WHERE id = $1 AND cart_id = $2",
+                &[&item_id, &cart_id],
+            )
+            .await?;
+
+        if deleted > 0 {
+            Self::touch(client, cart_id).await?;
+        }
+
+        Ok(deleted > 0)
+    }
+
+    /// Removes every item from a cart, e.g. once it was checked out.
+    pub async fn clear(client: &impl GenericClient, cart_id: Uuid) -> Result<(), AppError> {
+        client
+            .execute("DELETE FROM cart_items WHERE cart_id = $1", &[&cart_id])
+            .await?;
+
+        Self::touch(client, cart_id).await
+    }
+
+    // Records that the cart changed.
+    async fn touch(client: &impl GenericClient, cart_id: Uuid) -> Result<(), AppError> {
+        client
+            .execute(
+                "UPDATE carts SET updated_at = now() WHERE id = $1",
+                &[&cart_id],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
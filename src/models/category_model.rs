@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use serde::Serialize;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// A category of the catalog. Categories form a tree through `parent_id`.
+#[derive(Serialize, Debug, Clone)]
+pub struct Category {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>, // None for top level categories
+    pub name: String,
+    pub slug: String,  // Unique, used in URLs, e.g. "running-shoes"
+    pub position: i32, // Order among siblings, then by name
+    pub created_at: DateTime<Utc>,
+}
+
+/// A category with its subcategories, as returned by the category tree.
+#[derive(Serialize, Debug)]
+pub struct CategoryNode {
+    #[serde(flatten)]
+    pub category: Category,
+    pub children: Vec<CategoryNode>,
+}
+
+/// Attributes of a category to create.
+pub struct NewCategory<'a> {
+    pub parent_id: Option<Uuid>,
+    pub name: &'a str,
+    pub slug: &'a str,
+    pub position: i32,
+}
+
+/// Changes applied by a partial category update; `None` leaves a field untouched.
+#[derive(Default)]
+pub struct CategoryChanges {
+    pub parent_id: Option<Option<Uuid>>, // `Some(None)` moves the category to the top level
+    pub name: Option<String>,
+    pub slug: Option<String>,
+    pub position: Option<i32>,
+}
+
+// Columns selected for every category query, in the order expected by `Category::from_row`.
+const CATEGORY_COLUMNS: &str = "id, parent_id, name, slug, position, created_at";
+
+/// Returns a query selecting the IDs of the categories matching `roots`, a condition on
+/// `categories`, and of all their descendants. Meant to be embedded in other queries.
+pub fn subtree_sql(roots: &str) -> String {
+    format!(
+        "WITH RECURSIVE subtree AS (
+             SELECT id FROM categories WHERE {}
+             UNION
+             SELECT categories.id FROM categories
+             INNER JOIN subtree ON categories.parent_id = subtree.id
+         )
+         SELECT id FROM subtree",
+        roots
+    )
+}
+
+/// Turns a name into a slug: lower case letters and digits separated by single dashes.
+pub fn slugify(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Returns true if `slug` is made of lower case letters and digits separated by single
+/// dashes, and fits in 64 characters.
+pub fn is_valid_slug(slug: &str) -> bool {
+    (1..=64).contains(&slug.len())
+        && slug.split('-').all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        })
+}
+
+// Builds the subtrees below `parent_id` from the categories grouped by parent.
+fn build_tree(
+    by_parent: &mut HashMap<Option<Uuid>, Vec<Category>>,
+    parent_id: Option<Uuid>,
+) -> Vec<CategoryNode> {
+    let categories = by_parent.remove(&parent_id).unwrap_or_default();
+
+    categories
+        .into_iter()
+        .map(|category| {
+            let children = build_tree(by_parent, Some(category.id));
+            CategoryNode { category, children }
+        })
+        .collect()
+}
+
+impl Category {
+    fn from_row(row: &Row) -> Category {
+        Category {
+            id: row.get(0),
+            parent_id: row.get(1),
+            name: row.get(2),
+            slug: row.get(3),
+            position: row.get(4),
+            created_at: row.get(5),
+        }
+    }
+
+    /// Creates a category. Fails with a conflict if the slug is taken or the parent does
+    /// not exist.
+    pub async fn create(
+        client: &impl GenericClient,
+        category: &NewCategory<'_>,
+    ) -> Result<Category, AppError> {
+        let query = format!(
+            "INSERT INTO categories (id, parent_id, name, slug, position)
+             VALUES ($1, $2, $3, $4, $5)
+             RETURNING {}",
+            CATEGORY_COLUMNS
+        );
+        let row = client
+            .query_one(
+                &query,
+                &[
+                    &Uuid::new_v4(),
+                    &category.parent_id,
+                    &category.name,
+                    &category.slug,
+                    &category.position,
+                ],
+            )
+            .await?;
+
+        Ok(Self::from_row(&row))
+    }
+
+    /// Retrieves a category by its slug.
+    pub async fn get_by_slug(
+        client: &impl GenericClient,
+        slug: &str,
+    ) -> Result<Option<Category>, AppError> {
+        let query = format!(
+            "SELECT {} FROM categories WHERE slug = $1",
+            CATEGORY_COLUMNS
+        );
+        let row = client.query_opt(&query, &[&slug]).await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Returns every category as a tree, siblings ordered by position and name.
+    pub async fn tree(client: &impl GenericClient) -> Result<Vec<CategoryNode>, AppError> {
+        let query = format!(
+            "SELECT {} FROM categories ORDER BY position, name, id",
+            CATEGORY_COLUMNS
+        );
+        let rows = client.query(&query, &[]).await?;
+
+        let mut by_parent: HashMap<Option<Uuid>, Vec<Category>> = HashMap::new();
+        for row in &rows {
+            let category = Self::from_row(row);
+            by_parent
+                .entry(category.parent_id)
+                .or_default()
+                .push(category);
+        }
+
+        Ok(build_tree(&mut by_parent, None))
+    }
+
+    /// Applies a partial update to a category. Returns None if it does not exist.
+    ///
+    /// A category cannot be moved below itself or one of its descendants. Moves are applied
+    /// one at a time, so concurrent moves cannot build a cycle; must run in a transaction.
+    pub async fn update(
+        client: &impl GenericClient,
+        category_id: Uuid,
+        changes: &CategoryChanges,
+    ) -> Result<Option<Category>, AppError> {
+        if let Some(Some(parent_id)) = changes.parent_id {
+            client
+                .batch_execute("LOCK TABLE categories IN SHARE ROW EXCLUSIVE MODE")
+                .await?;
+
+            let query = format!(
+                "SELECT EXISTS ({} INTERSECT SELECT $2::UUID)",
+                subtree_sql("id = $1")
+            );
+            let cycle: bool = client
+                .query_one(&query, &[&category_id, &parent_id])
+                .await?
+                .get(0);
+            if cycle {
+                return Err(AppError::validation(
+                    "A category cannot be moved below itself or one of its subcategories",
+                ));
+            }
+        }
+
+        let query = format!(
+            "UPDATE categories SET
+                parent_id = CASE WHEN $2 THEN $3 ELSE parent_id END,
+                name = COALESCE($4, name),
+                slug = COALESCE($5, slug),
+                position = COALESCE($6, position),
+                updated_at = now()
+             WHERE id = $1
+             RETURNING {}",
+            CATEGORY_COLUMNS
+        );
+        let row = client
+            .query_opt(
+                &query,
+                &[
+                    &category_id,
+                    &changes.parent_id.is_some(),
+                    &changes.parent_id.flatten(),
+                    &changes.name,
+                    &changes.slug,
+                    &changes.position,
+                ],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Deletes a category, taking its products out of it. Returns false if it does not exist.
+    /// Categories with subcategories cannot be deleted.
+    pub async fn delete(client: &impl GenericClient, category_id: Uuid) -> Result<bool, AppError> {
+        let has_children: bool = client
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM categories WHERE parent_id = $1)",
+                &[&category_id],
+            )
+            .await?
+            .get(0);
+        if has_children {
+            return Err(AppError::conflict(
+                "Move or delete the subcategories of the category first",
+            ));
+        }
+
+        let deleted = client
+            .execute("DELETE FROM categories WHERE id = $1", &[&category_id])
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Replaces the categories a product is assigned to. Returns false if the product does not
+    /// exist, and fails with 404 if one of the categories does not. Must run in a transaction.
+    pub async fn set_for_product(
+        client: &impl GenericClient,
+        product_id: Uuid,
+        category_ids: &[Uuid],
+    ) -> Result<bool, AppError> {
+        let product = client
+            .query_opt(
+                "SELECT id FROM products WHERE id = $1 FOR UPDATE",
+                &[&product_id],
+            )
+            .await?;
+        if product.is_none() {
+            return Ok(false);
+        }
+
+        let existing: Vec<Uuid> = client
+            .query(
+                "SELECT id FROM categories WHERE id = ANY($1)",
+                &[&category_ids],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        if let Some(missing) = category_ids.iter().find(|id| !existing.contains(id)) {
+            return Err(AppError::NotFound(format!(
+                "Category {} not found",
+                missing
+            )));
+        }
+
+        client
+            .execute(
+                "DELETE FROM product_categories WHERE product_id = $1",
+                &[&product_id],
+            )
+            .await?;
+
+        for category_id in category_ids {
+            client
+                .execute(
+                    "INSERT INTO product_categories (product_id, category_id) VALUES ($1, $2)
+                     ON CONFLICT DO NOTHING",
+                    &[&product_id, category_id],
+                )
+                .await?;
+        }
+
+        Ok(true)
+    }
+}
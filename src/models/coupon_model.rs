@@ -0,0 +1,379 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::order_items_model::OrderItem;
+
+/// How a coupon reduces the price of an order.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscountType {
+    Percentage, // `value` percent off the eligible items
+    Fixed,      // `value` off the eligible items, in the store currency
+}
+
+impl DiscountType {
+    /// Returns the value stored in the `coupons.discount_type` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscountType::Percentage => "percentage",
+            DiscountType::Fixed => "fixed",
+        }
+    }
+}
+
+impl FromStr for DiscountType {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "percentage" => Ok(DiscountType::Percentage),
+            "fixed" => Ok(DiscountType::Fixed),
+            other => Err(AppError::Internal(format!(
+                "unknown discount type: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// A discount code customers can apply when placing an order.
+#[derive(Serialize, Debug)]
+pub struct Coupon {
+    pub id: Uuid,
+    pub code: String, // Upper case, matched case insensitively
+    pub discount_type: DiscountType,
+    pub value: Decimal,
+    pub min_order_value: Option<Decimal>, // Subtotal the order must reach
+    pub max_uses: Option<i32>,            // Redemptions allowed across all customers
+    pub max_uses_per_user: Option<i32>,
+    pub uses: i32, // Orders currently holding the coupon
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub active: bool,
+    pub product_ids: Vec<Uuid>,  // Products the coupon is restricted to
+    pub category_ids: Vec<Uuid>, // Categories the coupon is restricted to, with their subcategories
+    #[serde(skip)]
+    pub category_product_ids: Vec<Uuid>, // Products of those categories
+    pub created_at: DateTime<Utc>,
+}
+
+/// Attributes of a coupon to create.
+pub struct NewCoupon<'a> {
+    pub code: &'a str,
+    pub discount_type: DiscountType,
+    pub value: Decimal,
+    pub min_order_value: Option<Decimal>,
+    pub max_uses: Option<i32>,
+    pub max_uses_per_user: Option<i32>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub product_ids: &'a [Uuid],
+    pub category_ids: &'a [Uuid],
+}
+
+// Columns selected for every coupon query, in the order expected by `Coupon::from_row`.
+const COUPON_COLUMNS: &str = "id, code, discount_type, value, min_order_value, max_uses, \
+     max_uses_per_user, uses, starts_at, ends_at, active, created_at, \
+     ARRAY(SELECT product_id FROM coupon_products \
+           WHERE coupon_id = coupons.id ORDER BY product_id) AS product_ids, \
+     ARRAY(SELECT category_id FROM coupon_categories \
+           WHERE coupon_id = coupons.id ORDER BY category_id) AS category_ids, \
+     ARRAY(WITH RECURSIVE subtree AS ( \
+               SELECT category_id AS id FROM coupon_categories WHERE coupon_id = coupons.id \
+               UNION \
+               SELECT categories.id FROM categories \
+               INNER JOIN subtree ON categories.parent_id = subtree.id \
+           ) \
+           SELECT DISTINCT product_id FROM product_categories \
+           WHERE category_id IN (SELECT id FROM subtree)) AS category_product_ids";
+
+// Returns the sum of price * quantity of the items.
+fn items_subtotal(items: &[OrderItem]) -> Decimal {
+    items.iter().map(OrderItem::line_total).sum()
+}
+
+/// Normalizes a code entered by a customer or an admin.
+pub fn normalize_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+impl Coupon {
+    fn from_row(row: &Row) -> Result<Coupon, AppError> {
+        Ok(Coupon {
+            id: row.get(0),
+            code: row.get(1),
+            discount_type: row.get::<_, &str>(2).parse()?,
+            value: row.get(3),
+            min_order_value: row.get(4),
+            max_uses: row.get(5),
+            max_uses_per_user: row.get(6),
+            uses: row.get(7),
+            starts_at: row.get(8),
+            ends_at: row.get(9),
+            active: row.get(10),
+            created_at: row.get(11),
+            product_ids: row.get(12),
+            category_ids: row.get(13),
+            category_product_ids: row.get(14),
+        })
+    }
+
+    /// Returns true if the coupon discounts the given product: any product for coupons
+    /// without restrictions, otherwise the listed products and those of the listed categories.
+    pub fn applies_to(&self, product_id: Uuid) -> bool {
+        if self.product_ids.is_empty() && self.category_ids.is_empty() {
+            return true;
+        }
+
+        self.product_ids.contains(&product_id) || self.category_product_ids.contains(&product_id)
+    }
+
+    /// Returns the discount the coupon grants on an order with the given items.
+    ///
+    /// Only items the coupon applies to count, and nothing is granted
+    /// below the minimum order value. The discount never exceeds the eligible amount.
+    pub fn discount_for(&self, items: &[OrderItem]) -> Decimal {
+        if self
+            .min_order_value
+            .is_some_and(|min| items_subtotal(items) < min)
+        {
+            return Decimal::ZERO;
+        }
+
+        let eligible: Decimal = items
+            .iter()
+            .filter(|item| self.applies_to(item.product_id))
+            .map(OrderItem::line_total)
+            .sum();
+
+        let discount = match self.discount_type {
+            DiscountType::Percentage => (eligible * self.value / Decimal::ONE_HUNDRED).round_dp(2),
+            DiscountType::Fixed => self.value,
+        };
+
+        discount.min(eligible)
+    }
+
+    /// Creates a coupon together with its product and category restrictions.
+    pub async fn create(
+        client: &impl GenericClient,
+        coupon: &NewCoupon<'_>,
+    ) -> Result<Coupon, AppError> {
+        let id = Uuid::new_v4();
+
+        client
+            .execute(
+                "INSERT INTO coupons (id, code, discount_type, value, min_order_value, max_uses,
+                                      max_uses_per_user, starts_at, ends_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+                &[
+                    &id,
+                    &coupon.code,
+                    &coupon.discount_type.as_str(),
+                    &coupon.value,
+                    &coupon.min_order_value,
+                    &coupon.max_uses,
+                    &coupon.max_uses_per_user,
+                    &coupon.starts_at,
+                    &coupon.ends_at,
+                ],
+            )
+            .await?;
+
+        for product_id in coupon.product_ids {
+            client
+                .execute(
+                    "INSERT INTO coupon_products (coupon_id, product_id) VALUES ($1, $2)
+                     ON CONFLICT DO NOTHING",
+                    &[&id, product_id],
+                )
+                .await?;
+        }
+
+        for category_id in coupon.category_ids {
+            client
+                .execute(
+                    "INSERT INTO coupon_categories (coupon_id, category_id) VALUES ($1, $2)
+                     ON CONFLICT DO NOTHING",
+                    &[&id, category_id],
+                )
+                .await?;
+        }
+
+        Self::get_by_id(client, id)
+            .await?
+            .ok_or_else(|| AppError::Internal(format!("coupon {} vanished", id)))
+    }
+
+    /// Retrieves a coupon by its ID.
+    pub async fn get_by_id(
+        client: &impl GenericClient,
+        coupon_id: Uuid,
+    ) -> Result<Option<Coupon>, AppError> {
+        let query = format!("SELECT {} FROM coupons WHERE id = $1", COUPON_COLUMNS);
+        let row = client.query_opt(&query, &[&coupon_id]).await?;
+
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Lists coupons, newest first, with the total number of coupons.
+    pub async fn list(
+        client: &impl GenericClient,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<Coupon>, i64), AppError> {
+        let query = format!(
+            "SELECT {} FROM coupons ORDER BY created_at DESC, id LIMIT $1 OFFSET $2",
+            COUPON_COLUMNS
+        );
+        let rows = client.query(&query, &[&limit, &offset]).await?;
+        let coupons = rows
+            .iter()
+            .map(Self::from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let total: i64 = client
+            .query_one("SELECT COUNT(*) FROM coupons", &[])
+            .await?
+            .get(0);
+
+        Ok((coupons, total))
+    }
+
+    /// Stops a coupon from being redeemed. Orders already holding it keep their discount.
+    /// Returns None if the coupon does not exist.
+    pub async fn deactivate(
+        client: &impl GenericClient,
+        coupon_id: Uuid,
+    ) -> Result<Option<Coupon>, AppError> {
+        let updated = client
+            .execute(
+                "UPDATE coupons SET active = FALSE WHERE id = $1",
+                &[&coupon_id],
+            )
+            .await?;
+
+        if updated == 0 {
+            return Ok(None);
+        }
+
+        Self::get_by_id(client, coupon_id).await
+    }
+
+    /// Redeems a coupon for a new order of `user_id` holding `items`.
+    ///
+    /// The coupon row stays locked while its limits are checked and its use is counted, so
+    /// concurrent orders cannot exceed them. Must run in the order's transaction, so that a
+    /// failure later on gives the use back.
+    pub async fn redeem(
+        client: &impl GenericClient,
+        code: &str,
+        user_id: Uuid,
+        order_id: Uuid,
+        items: &[OrderItem],
+    ) -> Result<(), AppError> {
+        let query = format!(
+            "SELECT {} FROM coupons WHERE code = $1 FOR UPDATE",
+            COUPON_COLUMNS
+        );
+        let coupon = client
+            .query_opt(&query, &[&normalize_code(code)])
+            .await?
+            .as_ref()
+            .map(Self::from_row)
+            .transpose()?
+            .filter(|coupon| coupon.active)
+            .ok_or_else(|| AppError::validation("Unknown coupon code"))?;
+
+        let now = Utc::now();
+        if coupon.starts_at.is_some_and(|starts_at| now < starts_at) {
+            return Err(AppError::validation("The coupon is not valid yet"));
+        }
+        if coupon.ends_at.is_some_and(|ends_at| now >= ends_at) {
+            return Err(AppError::validation("The coupon has expired"));
+        }
+
+        if coupon
+            .max_uses
+            .is_some_and(|max_uses| coupon.uses >= max_uses)
+        {
+            return Err(AppError::conflict("The coupon has been used up"));
+        }
+
+        if let Some(max_uses_per_user) = coupon.max_uses_per_user {
+            let used: i64 = client
+                .query_one(
+                    "SELECT COUNT(*) FROM coupon_redemptions WHERE coupon_id = $1 AND user_id = $2",
+                    &[&coupon.id, &user_id],
+                )
+                .await?
+                .get(0);
+
+            if used >= i64::from(max_uses_per_user) {
+                return Err(AppError::conflict(
+                    "You have already used this coupon as many times as allowed",
+                ));
+            }
+        }
+
+        if let Some(min) = coupon.min_order_value {
+            if items_subtotal(items) < min {
+                return Err(AppError::validation(format!(
+                    "The coupon requires an order of at least {}",
+                    min
+                )));
+            }
+        }
+
+        if coupon.discount_for(items).is_zero() {
+            return Err(AppError::validation(
+                "The coupon does not apply to any item of the order",
+            ));
+        }
+
+        client
+            .execute(
+                "UPDATE coupons SET uses = uses + 1 WHERE id = $1",
+                &[&coupon.id],
+            )
+            .await?;
+        client
+            .execute(
+                "INSERT INTO coupon_redemptions (order_id, coupon_id, user_id) VALUES ($1, $2, $3)",
+                &[&order_id, &coupon.id, &user_id],
+            )
+            .await?;
+        client
+            .execute(
+                "UPDATE orders SET coupon_id = $1 WHERE id = $2",
+                &[&coupon.id, &order_id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Gives back the use of the coupon held by an order, e.g. when it is cancelled.
+    /// The order keeps its discount for the record.
+    pub async fn release(client: &impl GenericClient, order_id: Uuid) -> Result<(), AppError> {
+        client
+            .execute(
+                "WITH released AS (
+                     DELETE FROM coupon_redemptions WHERE order_id = $1 RETURNING coupon_id
+                 )
+                 UPDATE coupons SET uses = uses - 1
+                 FROM released WHERE coupons.id = released.coupon_id",
+                &[&order_id],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
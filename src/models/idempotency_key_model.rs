@@ -0,0 +1,96 @@
+use deadpool_postgres::GenericClient;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// How long a key is remembered, after which it may be used for a new request.
+const KEY_TTL_HOURS: i32 = 24;
+
+/// Outcome of claiming an idempotency key.
+#[derive(Debug)]
+pub enum KeyClaim {
+    New,                                         // First use of the key, the request must run
+    Completed { status_code: i16, body: Value }, // Response stored by the first request
+    Mismatch,                                    // Key already used for a different request
+}
+
+/// Responses stored per user and `Idempotency-Key`, so retried requests are not applied twice.
+pub struct IdempotencyKey;
+
+impl IdempotencyKey {
+    /// Claims a key for a request, identified by the hash of its method, path and body.
+    ///
+    /// Must run inside the transaction performing the request: a concurrent request with the
+    /// same key waits until that transaction ends, then sees the stored response, or claims
+    /// the key itself if the transaction was rolled back.
+    pub async fn claim(
+        client: &impl GenericClient,
+        user_id: Uuid,
+        key: &str,
+        request_hash: &str,
+    ) -> Result<KeyClaim, AppError> {
+        client
+            .execute(
+                "DELETE FROM idempotency_keys
+                 WHERE user_id = $1 AND key = $2
+                   AND created_at < now() - make_interval(hours => $3)",
+                &[&user_id, &key, &KEY_TTL_HOURS],
+            )
+            .await?;
+
+        let inserted = client
+            .execute(
+                "INSERT INTO idempotency_keys (user_id, key, request_hash)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT (user_id, key) DO NOTHING",
+                &[&user_id, &key, &request_hash],
+            )
+            .await?;
+
+        if inserted > 0 {
+            return Ok(KeyClaim::New);
+        }
+
+        let row = client
+            .query_one(
+                "SELECT request_hash, status_code, response_body
+                 FROM idempotency_keys WHERE user_id = $1 AND key = $2",
+                &[&user_id, &key],
+            )
+            .await?;
+
+        if row.get::<_, &str>(0) != request_hash {
+            return Ok(KeyClaim::Mismatch);
+        }
+
+        // Keys are claimed and completed in the same transaction, so committed keys always
+        // carry a response.
+        match (row.get(1), row.get(2)) {
+            (Some(status_code), Some(body)) => Ok(KeyClaim::Completed { status_code, body }),
+            _ => Err(AppError::Internal(format!(
+                "idempotency key {} has no response",
+                key
+            ))),
+        }
+    }
+
+    /// Stores the response of the request that claimed the key.
+    pub async fn complete(
+        client: &impl GenericClient,
+        user_id: Uuid,
+        key: &str,
+        status_code: i16,
+        body: &Value,
+    ) -> Result<(), AppError> {
+        client
+            .execute(
+                "UPDATE idempotency_keys SET status_code = $1, response_body = $2
+                 WHERE user_id = $3 AND key = $4",
+                &[&status_code, body, &user_id, &key],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,5 +1,18 @@
-pub mod user_model;
-pub mod product_model;
-pub mod order_model;
+pub mod address_model;
+pub mod cart_model;
+pub mod category_model;
+pub mod coupon_model;
+pub mod idempotency_key_model;
 pub mod order_items_model;
-pub mod payment_model;
\ No newline at end of file
+pub mod order_model;
+pub mod payment_event_model;
+pub mod payment_model;
+pub mod product_model;
+pub mod refresh_token_model;
+pub mod refund_model;
+pub mod revoked_token_model;
+pub mod shipment_model;
+pub mod shipping_model;
+pub mod tax_rate_model;
+pub mod user_model;
+pub mod variant_model;
@@ -1,122 +1,404 @@
+use deadpool_postgres::GenericClient;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
-use tokio_postgres::Client;
+use serde_json::json;
+use tokio_postgres::Row;
 use uuid::Uuid;
 
+use crate::errors::AppError;
+use crate::shipping;
+use crate::tax;
+
 /// Represents an item in an order.
 #[derive(Serialize, Deserialize)]
 pub struct OrderItem {
     pub id: Uuid,
     pub order_id: Uuid,
     pub product_id: Uuid,
+    pub variant_id: Option<Uuid>, // Variant ordered, for products sold in variants
+    pub sku: Option<String>,      // SKU of the variant when it was ordered
     pub quantity: i32,
     pub price: Decimal,
+    pub tax_class: String, // Tax class of the product when it was ordered
+    pub discount_amount: Decimal, // Share of the order discount taken off this line
+    pub tax_rate: Decimal, // Percentage applied to the line
+    pub tax_amount: Decimal, // Tax on the discounted line, included in or added to its price
 }
 
+// Columns selected for every item query, in the order expected by `OrderItem::from_row`.
+const ORDER_ITEM_COLUMNS: &str =
+    "id, order_id, product_id, quantity, price, tax_class, discount_amount, tax_rate, tax_amount, \
+     variant_id, sku";
+
 impl OrderItem {
-    /// Checks if the requested quantity of a product is available in stock.
-    /// Returns true if there is enough stock, otherwise false.
+    fn from_row(row: &Row) -> OrderItem {
+        OrderItem {
+            id: row.get(0),
+            order_id: row.get(1),
+            product_id: row.get(2),
+            quantity: row.get(3),
+            price: row.get(4),
+            tax_class: row.get(5),
+            discount_amount: row.get(6),
+            tax_rate: row.get(7),
+            tax_amount: row.get(8),
+            variant_id: row.get(9),
+            sku: row.get(10),
+        }
+    }
+
+    /// Returns price * quantity, before discounts and taxes.
+    pub fn line_total(&self) -> Decimal {
+        self.price * Decimal::from(self.quantity)
+    }
+
+    /// Returns what the customer was charged for the line: its total after the discount, plus
+    /// its tax when the tax was added to the prices.
+    pub fn charged_amount(&self, prices_include_tax: bool) -> Decimal {
+        let amount = self.line_total() - self.discount_amount;
+
+        if prices_include_tax {
+            amount
+        } else {
+            amount + self.tax_amount
+        }
+    }
+
+    /// Returns the refund for `quantity` more units of the line, given the units and amount
+    /// already refunded for it. The refunds follow the units' share of the charged amount, so
+    /// refunding every unit returns exactly what was charged and the last unit takes the
+    /// rounding remainder.
+    pub fn refund_amount(
+        &self,
+        prices_include_tax: bool,
+        quantity: i32,
+        refunded_quantity: i32,
+        refunded_amount: Decimal,
+    ) -> Decimal {
+        let share = Decimal::from(refunded_quantity + quantity) / Decimal::from(self.quantity);
+        let refunded_so_far = tax::round_money(self.charged_amount(prices_include_tax) * share);
+
+        (refunded_so_far - refunded_amount).max(Decimal::ZERO)
+    }
+
+    /// Checks if the requested quantity of a product, or of its variant if one is given, is
+    /// available in stock. Returns true if there is enough stock, otherwise false.
+    ///
+    /// When called inside a transaction the product or variant row stays locked until the
+    /// transaction ends, so concurrent orders cannot consume the same stock.
     pub async fn check_stock(
-        client: &Client,
+        client: &impl GenericClient,
         product_id: Uuid,
+        variant_id: Option<Uuid>,
         requested_quantity: i32,
-    ) -> Result<bool, Box<dyn Error>> {
-        // Query to retrieve the stock for the given product.
-        let row = client
-            .query_one("SELECT stock FROM products WHERE id = $1", &[&product_id])
-            .await?;
-
-        let stock: i32 = row.get(0);
+    ) -> Result<bool, AppError> {
+        let stock = Self::get_stock(client, product_id, variant_id).await?;
 
         // Returns true if the stock is greater than or equal to the requested quantity.
         Ok(stock >= requested_quantity)
     }
 
+    // Retrieves (and locks) the current stock of a product, or of its variant if one is given.
+    // Products sold in variants can only be ordered through one of them.
+    async fn get_stock(
+        client: &impl GenericClient,
+        product_id: Uuid,
+        variant_id: Option<Uuid>,
+    ) -> Result<i32, AppError> {
+        if let Some(variant_id) = variant_id {
+            let row = client
+                .query_opt(
+                    "SELECT stock FROM product_variants WHERE id = $1 AND product_id = $2
+                     FOR UPDATE",
+                    &[&variant_id, &product_id],
+                )
+                .await?
+                .ok_or_else(|| {
+                    AppError::NotFound(format!(
+                        "Variant {} of product {} not found",
+                        variant_id, product_id
+                    ))
+                })?;
+
+            return Ok(row.get(0));
+        }
+
+        let row = client
+            .query_opt(
+                "SELECT stock, EXISTS (SELECT 1 FROM product_variants WHERE product_id = products.id)
+                 FROM products WHERE id = $1 FOR UPDATE",
+                &[&product_id],
+            )
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Product {} not found", product_id)))?;
+
+        if row.get::<_, bool>(1) {
+            return Err(AppError::validation(format!(
+                "Product {} is sold in variants, a variant_id is required",
+                product_id
+            )));
+        }
+
+        Ok(row.get(0))
+    }
+
+    // Builds the error returned when a product cannot cover the requested quantity.
+    fn insufficient_stock(
+        product_id: Uuid,
+        variant_id: Option<Uuid>,
+        requested: i32,
+        available: i32,
+    ) -> AppError {
+        AppError::Conflict {
+            message: "Insufficient stock for the product".to_string(),
+            details: Some(json!({
+                "product_id": product_id,
+                "variant_id": variant_id,
+                "requested": requested,
+                "available": available,
+            })),
+        }
+    }
+
     /// Creates an order item by first checking stock availability.
-    /// If stock is sufficient, it inserts the order item and updates the product stock.
+    /// If stock is sufficient, it inserts the order item and updates the stock of the product,
+    /// or of its variant if one is given. Variants sell at their own price when they have one.
+    ///
+    /// This should run inside a transaction so the insert and the stock update are
+    /// applied together.
     pub async fn create_order_item(
-        client: &Client,
+        client: &impl GenericClient,
         order_id: Uuid,
         product_id: Uuid,
+        variant_id: Option<Uuid>,
         quantity: i32,
-    ) -> Result<OrderItem, Box<dyn Error>> {
-        let price = Self::get_product_price(client, product_id).await?;
+    ) -> Result<OrderItem, AppError> {
+        let (price, tax_class, sku) =
+            Self::get_product_pricing(client, product_id, variant_id).await?;
 
-        let is_in_stock = Self::check_stock(client, product_id, quantity).await?;
+        let stock = Self::get_stock(client, product_id, variant_id).await?;
 
-        if !is_in_stock {
-            return Err("Insufficient stock for the product".into());
+        if stock < quantity {
+            return Err(Self::insufficient_stock(
+                product_id, variant_id, quantity, stock,
+            ));
         }
 
         let id = Uuid::new_v4();
+        let query = format!(
+            "INSERT INTO order_items (id, order_id, product_id, quantity, price, tax_class,
+                                      variant_id, sku)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             RETURNING {}",
+            ORDER_ITEM_COLUMNS
+        );
         let row = client
             .query_one(
-                "INSERT INTO order_items (id, order_id, product_id, quantity, price) 
-                 VALUES ($1, $2, $3, $4, $5) 
-                 RETURNING id, order_id, product_id, quantity, price",
-                &[&id, &order_id, &product_id, &quantity, &price],
+                &query,
+                &[
+                    &id,
+                    &order_id,
+                    &product_id,
+                    &quantity,
+                    &price,
+                    &tax_class,
+                    &variant_id,
+                    &sku,
+                ],
             )
             .await?;
 
-        client
-            .execute(
-                "UPDATE products SET stock = stock - $1 WHERE id = $2",
-                &[&quantity, &product_id],
+        let updated = match variant_id {
+            Some(variant_id) => {
+                client
+                    .execute(
+                        "UPDATE product_variants SET stock = stock - $1, updated_at = now()
+                         WHERE id = $2 AND stock >= $1",
+                        &[&quantity, &variant_id],
+                    )
+                    .await?
+            }
+            None => {
+                client
+                    .execute(
+                        "UPDATE products SET stock = stock - $1 WHERE id = $2 AND stock >= $1",
+                        &[&quantity, &product_id],
+                    )
+                    .await?
+            }
+        };
+
+        if updated == 0 {
+            return Err(Self::insufficient_stock(
+                product_id, variant_id, quantity, stock,
+            ));
+        }
+
+        Ok(Self::from_row(&row))
+    }
+
+    /// Returns `quantity` units of a product, or of its variant if one is given, to stock,
+    /// e.g. when an item is removed or its order is cancelled. This is the single place where
+    /// stock is given back.
+    pub async fn restore_stock(
+        client: &impl GenericClient,
+        product_id: Uuid,
+        variant_id: Option<Uuid>,
+        quantity: i32,
+    ) -> Result<(), AppError> {
+        match variant_id {
+            Some(variant_id) => {
+                client
+                    .execute(
+                        "UPDATE product_variants SET stock = stock + $1, updated_at = now()
+                         WHERE id = $2",
+                        &[&quantity, &variant_id],
+                    )
+                    .await?;
+            }
+            None => {
+                client
+                    .execute(
+                        "UPDATE products SET stock = stock + $1 WHERE id = $2",
+                        &[&quantity, &product_id],
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the stock of every item of an order.
+    /// Products and variants are updated in ID order, the same order used when stock is taken,
+    /// so restocking cannot deadlock with concurrent orders.
+    pub async fn restock_order(
+        client: &impl GenericClient,
+        order_id: Uuid,
+    ) -> Result<(), AppError> {
+        let rows = client
+            .query(
+                "SELECT product_id, variant_id, SUM(quantity)::INTEGER
+                 FROM order_items WHERE order_id = $1
+                 GROUP BY product_id, variant_id
+                 ORDER BY product_id, variant_id NULLS FIRST",
+                &[&order_id],
             )
             .await?;
 
-        Ok(OrderItem {
-            id: row.get(0),
-            order_id: row.get(1),
-            product_id: row.get(2),
-            quantity: row.get(3),
-            price: row.get(4),
-        })
+        for row in rows {
+            Self::restore_stock(client, row.get(0), row.get(1), row.get(2)).await?;
+        }
+
+        Ok(())
     }
 
-    pub async fn get_product_price(
-        client: &Client,
+    // Retrieves the current price and tax class of a product, and the SKU of its variant if
+    // one is given. A variant's own price overrides the product's.
+    async fn get_product_pricing(
+        client: &impl GenericClient,
         product_id: Uuid,
-    ) -> Result<Decimal, Box<dyn Error>> {
+        variant_id: Option<Uuid>,
+    ) -> Result<(Decimal, String, Option<String>), AppError> {
         let row = client
-            .query_one("SELECT price FROM products WHERE id = $1", &[&product_id])
-            .await
-            .map_err(|e| Box::new(e) as Box<dyn Error>)?; // Ensure the error is boxed
+            .query_opt(
+                "SELECT COALESCE(product_variants.price, products.price), products.tax_class,
+                        product_variants.sku
+                 FROM products
+                 LEFT JOIN product_variants
+                     ON product_variants.id = $2 AND product_variants.product_id = products.id
+                 WHERE products.id = $1",
+                &[&product_id, &variant_id],
+            )
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Product {} not found", product_id)))?;
 
-        Ok(row.get(0)) // Return the price as a Decimal
+        Ok((row.get(0), row.get(1), row.get(2)))
     }
 
     /// Retrieves all items associated with a specific order.
     pub async fn get_order_items(
-        client: &Client,
+        client: &impl GenericClient,
+        order_id: Uuid,
+    ) -> Result<Vec<OrderItem>, AppError> {
+        // Query to retrieve all order items for the given order, in a stable order.
+        let query = format!(
+            "SELECT {} FROM order_items WHERE order_id = $1 ORDER BY id",
+            ORDER_ITEM_COLUMNS
+        );
+        let rows = client.query(&query, &[&order_id]).await?;
+
+        // Map the result rows into a vector of OrderItem objects.
+        Ok(rows.iter().map(Self::from_row).collect())
+    }
+
+    /// Retrieves a single order item by its ID.
+    pub async fn get_order_item(
+        client: &impl GenericClient,
+        item_id: Uuid,
+    ) -> Result<Option<OrderItem>, AppError> {
+        let query = format!(
+            "SELECT {} FROM order_items WHERE id = $1",
+            ORDER_ITEM_COLUMNS
+        );
+        let row = client.query_opt(&query, &[&item_id]).await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Returns the weight a carrier charges for the items of an order, from the current
+    /// package of every product.
+    pub async fn billable_weight(
+        client: &impl GenericClient,
         order_id: Uuid,
-    ) -> Result<Vec<OrderItem>, Box<dyn Error>> {
-        // Query to retrieve all order items for the given order.
+    ) -> Result<Decimal, AppError> {
         let rows = client
             .query(
-                "SELECT id, order_id, product_id, quantity, price 
-                 FROM order_items WHERE order_id = $1",
+                "SELECT order_items.quantity, products.weight_kg, products.length_cm,
+                        products.width_cm, products.height_cm
+                 FROM order_items
+                 INNER JOIN products ON products.id = order_items.product_id
+                 WHERE order_items.order_id = $1",
                 &[&order_id],
             )
             .await?;
 
-        // Map the result rows into a vector of OrderItem objects.
         Ok(rows
             .iter()
-            .map(|row| OrderItem {
-                id: row.get(0),
-                order_id: row.get(1),
-                product_id: row.get(2),
-                quantity: row.get(3),
-                price: row.get(4),
+            .map(|row| {
+                let quantity: i32 = row.get(0);
+                shipping::billable_weight(row.get(1), row.get(2), row.get(3), row.get(4))
+                    * Decimal::from(quantity)
             })
-            .collect())
+            .sum())
+    }
+
+    /// Records the discount and tax computed for an item when its order's totals change.
+    pub async fn set_breakdown(
+        client: &impl GenericClient,
+        item_id: Uuid,
+        discount_amount: Decimal,
+        tax_rate: Decimal,
+        tax_amount: Decimal,
+    ) -> Result<(), AppError> {
+        client
+            .execute(
+                "UPDATE order_items SET discount_amount = $1, tax_rate = $2, tax_amount = $3
+                 WHERE id = $4",
+                &[&discount_amount, &tax_rate, &tax_amount, &item_id],
+            )
+            .await?;
+
+        Ok(())
     }
 
     /// Deletes an order item by its ID.
     /// Returns true if the item was successfully deleted, false otherwise.
-    pub async fn delete_order_item(client: &Client, item_id: Uuid) -> Result<bool, Box<dyn Error>> {
+    /// The caller is responsible for returning the item's quantity with `restore_stock`.
+    pub async fn delete_order_item(
+        client: &impl GenericClient,
+        item_id: Uuid,
+    ) -> Result<bool, AppError> {
         // Execute the deletion query.
         let result = client
             .execute("DELETE FROM order_items WHERE id = $1", &[&item_id])
@@ -126,3 +408,70 @@ impl OrderItem {
         Ok(result > 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    // Three units at 10.00 with 1.00 of the order discount and 19% tax on the rest.
+    fn discounted_item() -> OrderItem {
+        OrderItem {
+            id: Uuid::nil(),
+            order_id: Uuid::nil(),
+            product_id: Uuid::nil(),
+            variant_id: None,
+            sku: None,
+            quantity: 3,
+            price: dec!(10.00),
+            tax_class: "standard".to_string(),
+            discount_amount: dec!(1.00),
+            tax_rate: dec!(19),
+            tax_amount: dec!(5.51),
+        }
+    }
+
+    #[test]
+    fn charged_amount_adds_exclusive_tax_only() {
+        let item = discounted_item();
+
+        assert_eq!(item.charged_amount(false), dec!(34.51));
+        assert_eq!(item.charged_amount(true), dec!(29.00));
+    }
+
+    #[test]
+    fn refunding_item_by_item_returns_the_charged_amount() {
+        let item = discounted_item();
+
+        for prices_include_tax in [false, true] {
+            let mut refunded_quantity = 0;
+            let mut refunded_amount = Decimal::ZERO;
+            let mut refunds = Vec::new();
+
+            while refunded_quantity < item.quantity {
+                let refund =
+                    item.refund_amount(prices_include_tax, 1, refunded_quantity, refunded_amount);
+                refunds.push(refund);
+                refunded_quantity += 1;
+                refunded_amount += refund;
+            }
+
+            assert_eq!(refunded_amount, item.charged_amount(prices_include_tax));
+            if !prices_include_tax {
+                assert_eq!(refunds, vec![dec!(11.50), dec!(11.51), dec!(11.50)]);
+            }
+        }
+    }
+
+    #[test]
+    fn the_last_units_take_the_rounding_remainder() {
+        let item = discounted_item();
+
+        let first = item.refund_amount(false, 2, 0, Decimal::ZERO);
+        let last = item.refund_amount(false, 1, 2, first);
+
+        assert_eq!(first, dec!(23.01));
+        assert_eq!(last, dec!(11.50));
+        assert_eq!(item.refund_amount(false, 3, 0, Decimal::ZERO), dec!(34.51));
+    }
+}
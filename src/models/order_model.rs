@@ -1,34 +1,258 @@
-use std::error::Error;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
 
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use tokio_postgres::Client;
+use serde_json::json;
+use tokio_postgres::{
+    types::{Json, ToSql},
+    Client, Row,
+};
 use uuid::Uuid;
 
+use crate::errors::AppError;
+use crate::models::{
+    address_model::AddressSnapshot,
+    coupon_model::Coupon,
+    order_items_model::OrderItem,
+    shipping_model::{ShippingMethodSnapshot, ShippingRate},
+    tax_rate_model::TaxRate,
+};
+use crate::{shipping, tax};
+
+/// Lifecycle state of an order.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    Pending,
+    AwaitingPayment,
+    Paid,
+    Fulfilling,
+    Shipped,
+    Delivered,
+    Cancelled,
+    PartiallyRefunded,
+    Refunded,
+}
+
+impl OrderStatus {
+    /// Returns the value stored in the `orders.status` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Pending => "pending",
+            OrderStatus::AwaitingPayment => "awaiting_payment",
+            OrderStatus::Paid => "paid",
+            OrderStatus::Fulfilling => "fulfilling",
+            OrderStatus::Shipped => "shipped",
+            OrderStatus::Delivered => "delivered",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::PartiallyRefunded => "partially_refunded",
+            OrderStatus::Refunded => "refunded",
+        }
+    }
+
+    /// Transition table of the order lifecycle: the states an order may move to from this one.
+    pub fn allowed_transitions(&self) -> &'static [OrderStatus] {
+        use OrderStatus::*;
+
+        match self {
+            Pending => &[AwaitingPayment, Cancelled],
+            AwaitingPayment => &[Paid, Cancelled],
+            Paid => &[Fulfilling, Cancelled, PartiallyRefunded, Refunded],
+            Fulfilling => &[Shipped, Cancelled, PartiallyRefunded, Refunded],
+            Shipped => &[Delivered, PartiallyRefunded, Refunded],
+            Delivered => &[PartiallyRefunded, Refunded],
+            // The rest of a partially refunded order can still be fulfilled
+            PartiallyRefunded => &[Fulfilling, Shipped, Delivered, Refunded],
+            Cancelled | Refunded => &[],
+        }
+    }
+
+    /// Returns true if an order may move from this state to `next`.
+    pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+        self.allowed_transitions().contains(&next)
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending" => Ok(OrderStatus::Pending),
+            "awaiting_payment" => Ok(OrderStatus::AwaitingPayment),
+            "paid" => Ok(OrderStatus::Paid),
+            "fulfilling" => Ok(OrderStatus::Fulfilling),
+            "shipped" => Ok(OrderStatus::Shipped),
+            "delivered" => Ok(OrderStatus::Delivered),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            "partially_refunded" => Ok(OrderStatus::PartiallyRefunded),
+            "refunded" => Ok(OrderStatus::Refunded),
+            other => Err(AppError::Internal(format!(
+                "unknown order status: {}",
+                other
+            ))),
+        }
+    }
+}
+
 /// Represents an order in the system.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Order {
     pub id: Uuid,
     pub user_id: Uuid,
-    pub status: String,
+    pub status: OrderStatus,
+    pub created_at: DateTime<Utc>,
+    pub coupon_id: Option<Uuid>, // Coupon redeemed when the order was placed
+    pub tax_region: Option<String>, // Region whose tax rates apply, untaxed when None
+    pub shipping_address: Option<AddressSnapshot>, // Destination, None for orders placed without one
+    pub shipping_method: Option<ShippingMethodSnapshot>, // Set together with the address
+    pub totals: OrderTotals,
+}
+
+/// Monetary summary of an order, computed by the server whenever its items change.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OrderTotals {
+    pub subtotal: Decimal, // Sum of price * quantity of every item
+    pub discount_total: Decimal,
+    pub tax_total: Decimal, // Sum of the tax of every item
+    pub shipping_total: Decimal,
+    pub grand_total: Decimal,     // Amount the customer has to pay
+    pub currency: String,         // ISO 4217 code, e.g. "USD"
+    pub prices_include_tax: bool, // Whether the tax is part of the prices or added to them
+}
+
+/// Returns the currency new orders are priced in, configured with STORE_CURRENCY (default USD).
+pub fn store_currency() -> String {
+    env::var("STORE_CURRENCY")
+        .ok()
+        .map(|currency| currency.trim().to_uppercase())
+        .filter(|currency| currency.len() == 3 && currency.chars().all(|c| c.is_ascii_alphabetic()))
+        .unwrap_or_else(|| "USD".to_string())
+}
+
+/// Criteria used to list the orders of a user.
+pub struct OrderFilter {
+    pub user_id: Uuid,
+    pub status: Option<OrderStatus>,
+    pub from: Option<DateTime<Utc>>, // Orders created at or after this instant
+    pub to: Option<DateTime<Utc>>,   // Orders created before this instant
+    pub limit: i64,
+    pub offset: i64,
+}
+
+// Columns selected for every order query, in the order expected by `Order::from_row`.
+const ORDER_COLUMNS: &str = "id, user_id, status, created_at, subtotal, discount_total, \
+                             tax_total, shipping_total, grand_total, currency, coupon_id, \
+                             tax_region, prices_include_tax, shipping_address, shipping_method";
+
+/// A single entry of an order's status history.
+#[derive(Serialize, Debug)]
+pub struct OrderStatusChange {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub from_status: Option<OrderStatus>, // None for the entry recorded when the order was created
+    pub to_status: OrderStatus,
+    pub changed_by: Option<Uuid>, // User who made the change, None for the payment provider or deleted users
+    pub created_at: DateTime<Utc>,
+}
+
+// Records a status change in the order's history.
+async fn record_status_change(
+    client: &impl GenericClient,
+    order_id: Uuid,
+    from_status: Option<OrderStatus>,
+    to_status: OrderStatus,
+    changed_by: Option<Uuid>,
+) -> Result<(), AppError> {
+    client
+        .execute(
+            "INSERT INTO order_status_history (id, order_id, from_status, to_status, changed_by)
+             VALUES ($1, $2, $3, $4, $5)",
+            &[
+                &Uuid::new_v4(),
+                &order_id,
+                &from_status.map(|status| status.as_str()),
+                &to_status.as_str(),
+                &changed_by,
+            ],
+        )
+        .await?;
+
+    Ok(())
 }
 
 impl Order {
+    fn from_row(row: &Row) -> Result<Order, AppError> {
+        Ok(Order {
+            id: row.get(0),
+            user_id: row.get(1),
+            status: row.get::<_, &str>(2).parse()?,
+            created_at: row.get(3),
+            totals: OrderTotals {
+                subtotal: row.get(4),
+                discount_total: row.get(5),
+                tax_total: row.get(6),
+                shipping_total: row.get(7),
+                grand_total: row.get(8),
+                currency: row.get(9),
+                prices_include_tax: row.get(12),
+            },
+            coupon_id: row.get(10),
+            tax_region: row.get(11),
+            shipping_address: row
+                .get::<_, Option<Json<AddressSnapshot>>>(13)
+                .map(|address| address.0),
+            shipping_method: row
+                .get::<_, Option<Json<ShippingMethodSnapshot>>>(14)
+                .map(|method| method.0),
+        })
+    }
+
     /// Creates a new order for a given user with a default 'pending' status.
-    /// The order is inserted into the database and the order details are returned.
-    pub async fn create_order(client: &Client, user_id: Uuid) -> Result<Order, Box<dyn Error>> {
+    /// The order is inserted into the database, the creation is recorded in its status
+    /// history and the order details are returned.
+    /// `shipping` is the destination and method of orders that are shipped.
+    pub async fn create_order(
+        client: &impl GenericClient,
+        user_id: Uuid,
+        tax_region: Option<&str>,
+        shipping: Option<(&AddressSnapshot, &ShippingMethodSnapshot)>,
+    ) -> Result<Order, AppError> {
         // Generate a new UUID for the order.
         let id = Uuid::new_v4();
 
         // Default status is set to 'pending'.
-        let status = "pending";
+        let status = OrderStatus::Pending.as_str();
+        let currency = store_currency();
+        let prices_include_tax = tax::prices_include_tax();
+        let shipping_address = shipping.map(|(address, _)| Json(address));
+        let shipping_method = shipping.map(|(_, method)| Json(method));
 
-        // Insert the new order into the database.
+        // Insert the new order into the database. Totals start at zero until items are added.
+        let query = format!(
+            "INSERT INTO orders (id, user_id, status, currency, tax_region, prices_include_tax,
+                                 shipping_address, shipping_method)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             RETURNING {}",
+            ORDER_COLUMNS
+        );
         let row = match client
             .query_one(
-                "INSERT INTO orders (id, user_id, status) 
-                 VALUES ($1, $2, $3) 
-                 RETURNING id, user_id, status",
-                &[&id, &user_id, &status],
+                &query,
+                &[
+                    &id,
+                    &user_id,
+                    &status,
+                    &currency,
+                    &tax_region,
+                    &prices_include_tax,
+                    &shipping_address,
+                    &shipping_method,
+                ],
             )
             .await
         {
@@ -36,57 +260,264 @@ impl Order {
             Err(err) => {
                 // Log error to help identify issues with the query.
                 eprintln!("Error inserting order into database: {:?}", err);
-                return Err(Box::new(err));
+                return Err(err.into());
             }
         };
 
+        let order = Self::from_row(&row)?;
+        record_status_change(client, order.id, None, order.status, Some(user_id)).await?;
+
         // Return the created order.
-        Ok(Order {
-            id: row.get(0),
-            user_id: row.get(1),
-            status: row.get(2), // Status returned from the database
-        })
+        Ok(order)
     }
 
     pub async fn verify_order_owner(
         client: &Client,
         order_id: Uuid,
         user_id: Uuid,
-    ) -> Result<bool, tokio_postgres::Error> {
+    ) -> Result<bool, AppError> {
         let query = "SELECT COUNT(*) FROM orders WHERE id = $1 AND user_id = $2";
         let row = client.query_one(query, &[&order_id, &user_id]).await?;
         Ok(row.get::<_, i64>(0) > 0)
     }
 
     /// Retrieves an order from the database by its ID.
-    pub async fn get_order(
+    pub async fn get_order(client: &Client, order_id: Uuid) -> Result<Option<Order>, AppError> {
+        // Query the database to retrieve the order by its ID.
+        let query = format!("SELECT {} FROM orders WHERE id = $1", ORDER_COLUMNS);
+        let rows = client.query(&query, &[&order_id]).await?;
+
+        // If a matching row is found, construct and return the Order struct.
+        // Return None if no order matches the given ID.
+        rows.first().map(Self::from_row).transpose()
+    }
+
+    /// Recomputes the totals of an order from its items: subtotal, discount, tax, shipping and
+    /// grand total, together with the discount and tax of every item.
+    /// Must be called, in the same transaction, whenever the items of an order change.
+    ///
+    /// The discount of a redeemed coupon follows the items: it shrinks or grows with the
+    /// eligible items, and drops to zero while the order is below the coupon's minimum value.
+    /// It is spread over the eligible items, and each item is taxed on its discounted amount.
+    /// Shipping is priced from the billable weight of the items and the subtotal after the
+    /// discount; it fails if the method no longer ships the items to the order's destination.
+    pub async fn recalculate_totals(
+        client: &impl GenericClient,
+        order_id: Uuid,
+    ) -> Result<Order, AppError> {
+        let order = Self::get_order_for_update(client, order_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+
+        let items = OrderItem::get_order_items(client, order_id).await?;
+        let subtotal: Decimal = items.iter().map(OrderItem::line_total).sum();
+
+        let coupon = match order.coupon_id {
+            Some(coupon_id) => Coupon::get_by_id(client, coupon_id).await?,
+            None => None,
+        };
+        let discount_total = coupon
+            .as_ref()
+            .map(|coupon| coupon.discount_for(&items))
+            .unwrap_or_default();
+        let eligible: Vec<Decimal> = items
+            .iter()
+            .map(|item| match &coupon {
+                Some(coupon) if coupon.applies_to(item.product_id) => item.line_total(),
+                _ => Decimal::ZERO,
+            })
+            .collect();
+        let discounts = tax::allocate(discount_total, &eligible);
+
+        let rates = match &order.tax_region {
+            Some(region) => TaxRate::rates_for_region(client, region).await?,
+            None => HashMap::new(),
+        };
+        let prices_include_tax = order.totals.prices_include_tax;
+
+        let mut tax_total = Decimal::ZERO;
+        for (item, discount) in items.iter().zip(discounts) {
+            let rate = rates.get(&item.tax_class).copied().unwrap_or_default();
+            let tax = tax::line_tax(item.line_total() - discount, rate, prices_include_tax);
+            tax_total += tax;
+
+            OrderItem::set_breakdown(client, item.id, discount, rate, tax).await?;
+        }
+
+        let shipping_total = match (&order.shipping_address, &order.shipping_method) {
+            (Some(address), Some(method)) => {
+                let weight = OrderItem::billable_weight(client, order_id).await?;
+                let rate = ShippingRate::find(client, &address.region, method.id, weight)
+                    .await?
+                    .ok_or_else(|| {
+                        AppError::validation(format!(
+                            "{} does not ship {} kg to {}",
+                            method.name, weight, address.region
+                        ))
+                    })?;
+                shipping::shipping_cost(rate.price, rate.free_over, subtotal - discount_total)
+            }
+            _ => Decimal::ZERO,
+        };
+
+        // Included tax is already part of the subtotal
+        let query = format!(
+            "UPDATE orders
+             SET subtotal = $1,
+                 discount_total = $2,
+                 tax_total = $3,
+                 shipping_total = $4,
+                 grand_total = GREATEST($1::NUMERIC - $2::NUMERIC, 0)
+                               + CASE WHEN prices_include_tax THEN 0 ELSE $3::NUMERIC END
+                               + $4::NUMERIC
+             WHERE id = $5
+             RETURNING {}",
+            ORDER_COLUMNS
+        );
+        let row = client
+            .query_one(
+                &query,
+                &[
+                    &subtotal,
+                    &discount_total,
+                    &tax_total,
+                    &shipping_total,
+                    &order_id,
+                ],
+            )
+            .await?;
+
+        Self::from_row(&row)
+    }
+
+    /// Lists the orders of a user matching the filter, newest first.
+    /// Returns the requested page together with the total number of matching orders.
+    pub async fn list_for_user(
         client: &Client,
+        filter: &OrderFilter,
+    ) -> Result<(Vec<Order>, i64), AppError> {
+        let mut conditions = vec!["user_id = $1".to_string()];
+        let mut params: Vec<Box<dyn ToSql + Sync + Send>> = vec![Box::new(filter.user_id)];
+
+        if let Some(status) = filter.status {
+            params.push(Box::new(status.as_str()));
+            conditions.push(format!("status = ${}", params.len()));
+        }
+        if let Some(from) = filter.from {
+            params.push(Box::new(from));
+            conditions.push(format!("created_at >= ${}", params.len()));
+        }
+        if let Some(to) = filter.to {
+            params.push(Box::new(to));
+            conditions.push(format!("created_at < ${}", params.len()));
+        }
+
+        let where_clause = conditions.join(" AND ");
+        let param_refs: Vec<&(dyn ToSql + Sync)> = params
+            .iter()
+            .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+            .collect();
+
+        let count_query = format!("SELECT COUNT(*) FROM orders WHERE {}", where_clause);
+        let total: i64 = client.query_one(&count_query, &param_refs).await?.get(0);
+
+        // The ID breaks ties so pages stay stable between requests
+        let list_query = format!(
+            "SELECT {} FROM orders WHERE {} ORDER BY created_at DESC, id DESC LIMIT {} OFFSET {}",
+            ORDER_COLUMNS, where_clause, filter.limit, filter.offset
+        );
+        let rows = client.query(&list_query, &param_refs).await?;
+
+        let orders = rows.iter().map(Self::from_row).collect::<Result<_, _>>()?;
+        Ok((orders, total))
+    }
+
+    /// Retrieves an order and locks its row until the end of the transaction,
+    /// so concurrent status changes are applied one after another.
+    pub async fn get_order_for_update(
+        client: &impl GenericClient,
         order_id: Uuid,
-    ) -> Result<Option<Order>, Box<dyn Error>> {
-        // Query the database to retrieve the order by its ID.
+    ) -> Result<Option<Order>, AppError> {
+        let query = format!(
+            "SELECT {} FROM orders WHERE id = $1 FOR UPDATE",
+            ORDER_COLUMNS
+        );
+        let row = client.query_opt(&query, &[&order_id]).await?;
+
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Moves a locked order to `next`, enforcing the transition table and recording the change.
+    /// The order must have been read with `get_order_for_update` in the same transaction.
+    /// `changed_by` is None for changes reported by the payment provider.
+    pub async fn transition(
+        client: &impl GenericClient,
+        order: &Order,
+        next: OrderStatus,
+        changed_by: Option<Uuid>,
+    ) -> Result<Order, AppError> {
+        if !order.status.can_transition_to(next) {
+            return Err(AppError::Conflict {
+                message: format!(
+                    "Cannot change the order status from {} to {}",
+                    order.status.as_str(),
+                    next.as_str()
+                ),
+                details: Some(json!({
+                    "status": order.status,
+                    "requested": next,
+                    "allowed": order.status.allowed_transitions(),
+                })),
+            });
+        }
+
+        let query = format!(
+            "UPDATE orders SET status = $1 WHERE id = $2 RETURNING {}",
+            ORDER_COLUMNS
+        );
+        let row = client
+            .query_one(&query, &[&next.as_str(), &order.id])
+            .await?;
+        record_status_change(client, order.id, Some(order.status), next, changed_by).await?;
+
+        Self::from_row(&row)
+    }
+
+    /// Returns the status history of an order, oldest change first.
+    pub async fn status_history(
+        client: &impl GenericClient,
+        order_id: Uuid,
+    ) -> Result<Vec<OrderStatusChange>, AppError> {
         let rows = client
             .query(
-                "SELECT id, user_id, status FROM orders WHERE id = $1",
+                "SELECT id, order_id, from_status, to_status, changed_by, created_at
+                 FROM order_status_history
+                 WHERE order_id = $1
+                 ORDER BY seq",
                 &[&order_id],
             )
             .await?;
 
-        // If a matching row is found, construct and return the Order struct.
-        if let Some(row) = rows.get(0) {
-            Ok(Some(Order {
-                id: row.get(0),
-                user_id: row.get(1),
-                status: row.get(2),
-            }))
-        } else {
-            // Return None if no order matches the given ID.
-            Ok(None)
-        }
+        rows.iter()
+            .map(|row| {
+                Ok(OrderStatusChange {
+                    id: row.get(0),
+                    order_id: row.get(1),
+                    from_status: row.get::<_, Option<&str>>(2).map(str::parse).transpose()?,
+                    to_status: row.get::<_, &str>(3).parse()?,
+                    changed_by: row.get(4),
+                    created_at: row.get(5),
+                })
+            })
+            .collect()
     }
 
     /// Deletes an order from the database by its ID.
     /// Returns true if the order was successfully deleted, otherwise false.
-    pub async fn delete_order(client: &Client, order_id: Uuid) -> Result<bool, Box<dyn Error>> {
+    pub async fn delete_order(
+        client: &impl GenericClient,
+        order_id: Uuid,
+    ) -> Result<bool, AppError> {
         // Execute the SQL delete query for the specified order ID.
         let result = client
             .execute("DELETE FROM orders WHERE id = $1", &[&order_id])
@@ -96,3 +527,153 @@ impl Order {
         Ok(result > 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::OrderStatus::{self, *};
+
+    const ALL: [OrderStatus; 9] = [
+        Pending,
+        AwaitingPayment,
+        Paid,
+        Fulfilling,
+        Shipped,
+        Delivered,
+        Cancelled,
+        PartiallyRefunded,
+        Refunded,
+    ];
+
+    // Asserts each move is allowed, or refused, naming it on failure.
+    fn assert_moves(moves: &[(OrderStatus, OrderStatus)], allowed: bool) {
+        for (from, to) in moves {
+            assert_eq!(
+                from.can_transition_to(*to),
+                allowed,
+                "{} -> {}",
+                from.as_str(),
+                to.as_str()
+            );
+        }
+    }
+
+    #[test]
+    fn the_lifecycle_moves_forward_one_step_at_a_time() {
+        assert_moves(
+            &[
+                (Pending, AwaitingPayment),
+                (AwaitingPayment, Paid),
+                (Paid, Fulfilling),
+                (Fulfilling, Shipped),
+                (Shipped, Delivered),
+            ],
+            true,
+        );
+        assert_moves(
+            &[
+                (Pending, Paid),
+                (Pending, Fulfilling),
+                (AwaitingPayment, Fulfilling),
+                (AwaitingPayment, Shipped),
+                (Paid, Shipped),
+                (Paid, Delivered),
+                (Fulfilling, Delivered),
+            ],
+            false,
+        );
+    }
+
+    #[test]
+    fn fulfillment_never_moves_back() {
+        assert_moves(
+            &[
+                (Fulfilling, Paid),
+                (Shipped, Fulfilling),
+                (Delivered, Shipped),
+                (Paid, AwaitingPayment),
+            ],
+            false,
+        );
+    }
+
+    #[test]
+    fn orders_cannot_be_cancelled_once_shipped() {
+        assert_moves(
+            &[
+                (Pending, Cancelled),
+                (AwaitingPayment, Cancelled),
+                (Paid, Cancelled),
+                (Fulfilling, Cancelled),
+            ],
+            true,
+        );
+        assert_moves(
+            &[
+                (Shipped, Cancelled),
+                (Delivered, Cancelled),
+                (PartiallyRefunded, Cancelled),
+                (Refunded, Cancelled),
+            ],
+            false,
+        );
+    }
+
+    #[test]
+    fn only_paid_orders_can_be_refunded() {
+        assert_moves(
+            &[
+                (Pending, PartiallyRefunded),
+                (Pending, Refunded),
+                (AwaitingPayment, PartiallyRefunded),
+                (AwaitingPayment, Refunded),
+                (Cancelled, Refunded),
+            ],
+            false,
+        );
+        assert_moves(
+            &[
+                (Paid, Refunded),
+                (Delivered, PartiallyRefunded),
+                (Delivered, Refunded),
+                (PartiallyRefunded, Refunded),
+            ],
+            true,
+        );
+    }
+
+    #[test]
+    fn partially_refunded_orders_can_still_be_fulfilled() {
+        assert_moves(
+            &[
+                (PartiallyRefunded, Fulfilling),
+                (PartiallyRefunded, Shipped),
+                (PartiallyRefunded, Delivered),
+            ],
+            true,
+        );
+        assert_moves(&[(PartiallyRefunded, Paid)], false);
+    }
+
+    #[test]
+    fn cancelled_and_refunded_orders_are_final() {
+        for to in ALL {
+            assert_moves(&[(Cancelled, to), (Refunded, to)], false);
+        }
+    }
+
+    #[test]
+    fn no_state_moves_to_itself_or_back_to_pending() {
+        for from in ALL {
+            assert!(!from.can_transition_to(from), "{}", from.as_str());
+            assert!(!from.can_transition_to(Pending), "{}", from.as_str());
+        }
+    }
+
+    #[test]
+    fn statuses_round_trip_through_their_column_value() {
+        for status in ALL {
+            assert_eq!(status.as_str().parse::<OrderStatus>().unwrap(), status);
+        }
+        assert!("unknown".parse::<OrderStatus>().is_err());
+    }
+}
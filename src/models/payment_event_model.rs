@@ -0,0 +1,144 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use serde::Serialize;
+use serde_json::Value;
+use tokio_postgres::{Client, Row};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// A webhook event received from a payment provider, stored as received so it can be replayed.
+#[derive(Serialize, Debug)]
+pub struct PaymentEvent {
+    pub id: Uuid,
+    pub provider: String,
+    pub event_id: String, // Provider's ID for the event, unique per provider
+    pub event_type: String,
+    pub payload: Value, // Raw event body
+    pub received_at: DateTime<Utc>,
+    pub processed_at: Option<DateTime<Utc>>, // None until the event has been applied
+    pub error: Option<String>,               // Why the last attempt to apply the event failed
+}
+
+// Columns selected for every event query, in the order expected by `PaymentEvent::from_row`.
+const EVENT_COLUMNS: &str =
+    "id, provider, event_id, event_type, payload, received_at, processed_at, error";
+
+impl PaymentEvent {
+    fn from_row(row: &Row) -> PaymentEvent {
+        PaymentEvent {
+            id: row.get(0),
+            provider: row.get(1),
+            event_id: row.get(2),
+            event_type: row.get(3),
+            payload: row.get(4),
+            received_at: row.get(5),
+            processed_at: row.get(6),
+            error: row.get(7),
+        }
+    }
+
+    /// Stores a received event, unless the provider already delivered it.
+    /// Returns the stored event, and whether it was new.
+    pub async fn record(
+        client: &impl GenericClient,
+        provider: &str,
+        event_id: &str,
+        event_type: &str,
+        payload: &Value,
+    ) -> Result<(PaymentEvent, bool), AppError> {
+        let query = format!(
+            "INSERT INTO payment_events (id, provider, event_id, event_type, payload)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (provider, event_id) DO NOTHING
+             RETURNING {}",
+            EVENT_COLUMNS
+        );
+        let inserted = client
+            .query_opt(
+                &query,
+                &[&Uuid::new_v4(), &provider, &event_id, &event_type, payload],
+            )
+            .await?;
+
+        if let Some(row) = inserted {
+            return Ok((Self::from_row(&row), true));
+        }
+
+        let query = format!(
+            "SELECT {} FROM payment_events WHERE provider = $1 AND event_id = $2",
+            EVENT_COLUMNS
+        );
+        let row = client.query_one(&query, &[&provider, &event_id]).await?;
+        Ok((Self::from_row(&row), false))
+    }
+
+    /// Retrieves an event and locks it until the end of the transaction, so concurrent
+    /// deliveries or replays of the same event are applied one at a time.
+    pub async fn get_for_update(
+        client: &impl GenericClient,
+        id: Uuid,
+    ) -> Result<Option<PaymentEvent>, AppError> {
+        let query = format!(
+            "SELECT {} FROM payment_events WHERE id = $1 FOR UPDATE",
+            EVENT_COLUMNS
+        );
+        let row = client.query_opt(&query, &[&id]).await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Lists events, newest first, optionally only those not applied yet.
+    /// Returns the requested page together with the total number of matching events.
+    pub async fn list(
+        client: &Client,
+        unprocessed_only: bool,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<PaymentEvent>, i64), AppError> {
+        let where_clause = if unprocessed_only {
+            "WHERE processed_at IS NULL"
+        } else {
+            ""
+        };
+
+        let count_query = format!("SELECT COUNT(*) FROM payment_events {}", where_clause);
+        let total: i64 = client.query_one(&count_query, &[]).await?.get(0);
+
+        let list_query = format!(
+            "SELECT {} FROM payment_events {} ORDER BY received_at DESC, id LIMIT $1 OFFSET $2",
+            EVENT_COLUMNS, where_clause
+        );
+        let rows = client.query(&list_query, &[&limit, &offset]).await?;
+
+        Ok((rows.iter().map(Self::from_row).collect(), total))
+    }
+
+    /// Marks an event as successfully applied.
+    pub async fn mark_processed(client: &impl GenericClient, id: Uuid) -> Result<(), AppError> {
+        client
+            .execute(
+                "UPDATE payment_events SET processed_at = now(), error = NULL WHERE id = $1",
+                &[&id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Records why applying an event failed; it stays unprocessed so it can be replayed.
+    pub async fn mark_failed(
+        client: &impl GenericClient,
+        id: Uuid,
+        error: &str,
+    ) -> Result<(), AppError> {
+        client
+            .execute(
+                "UPDATE payment_events SET error = $1 WHERE id = $2",
+                &[&error, &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
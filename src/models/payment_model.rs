@@ -1,92 +1,342 @@
+use std::str::FromStr;
+
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
-use tokio_postgres::Client;
+use tokio_postgres::{error::SqlState, Client, Row};
 use uuid::Uuid;
 
+use crate::errors::AppError;
+
+/// State of a payment with the payment provider.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    Pending,     // Not sent to the provider yet
+    Authorizing, // Sent to the provider for authorization, its answer is not recorded yet
+    Authorized,  // Funds reserved, waiting for capture
+    Capturing,   // Sent to the provider for capture, its answer is not recorded yet
+    Paid,        // Funds captured
+    Voided,      // Cancelled before any funds moved
+    Failed,      // Declined by the provider
+    PartiallyRefunded,
+    Refunded,
+}
+
+impl PaymentStatus {
+    /// Returns the value stored in the `payments.status` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentStatus::Pending => "pending",
+            PaymentStatus::Authorizing => "authorizing",
+            PaymentStatus::Authorized => "authorized",
+            PaymentStatus::Capturing => "capturing",
+            PaymentStatus::Paid => "paid",
+            PaymentStatus::Voided => "voided",
+            PaymentStatus::Failed => "failed",
+            PaymentStatus::PartiallyRefunded => "partially_refunded",
+            PaymentStatus::Refunded => "refunded",
+        }
+    }
+
+    /// Returns true if a payment may move from this state to `next`.
+    pub fn can_transition_to(&self, next: PaymentStatus) -> bool {
+        use PaymentStatus::*;
+
+        matches!(
+            (self, next),
+            (Pending | Failed, Authorizing | Authorized | Failed | Voided)
+                | (Authorizing, Authorized | Failed)
+                | (Authorized, Capturing | Paid | Failed | Voided)
+                | (Capturing, Paid | Failed | Voided)
+                | (Paid | PartiallyRefunded, PartiallyRefunded | Refunded)
+        )
+    }
+}
+
+impl FromStr for PaymentStatus {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending" => Ok(PaymentStatus::Pending),
+            "authorizing" => Ok(PaymentStatus::Authorizing),
+            "authorized" => Ok(PaymentStatus::Authorized),
+            "capturing" => Ok(PaymentStatus::Capturing),
+            "paid" => Ok(PaymentStatus::Paid),
+            "voided" => Ok(PaymentStatus::Voided),
+            "failed" => Ok(PaymentStatus::Failed),
+            "partially_refunded" => Ok(PaymentStatus::PartiallyRefunded),
+            "refunded" => Ok(PaymentStatus::Refunded),
+            other => Err(AppError::Internal(format!(
+                "unknown payment status: {}",
+                other
+            ))),
+        }
+    }
+}
+
 /// Represents a payment made for an order.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Payment {
     pub id: Uuid,
     pub order_id: Uuid,
     pub payment_method: String,
-    pub status: String,
+    pub status: PaymentStatus,
+    pub amount: Decimal, // Grand total of the order when the payment was last priced
+    pub currency: String,
+    pub provider: Option<String>, // Provider that handled the payment, once authorized
+    pub provider_reference: Option<String>, // Provider's ID for the payment, once authorized
+    pub refunded_amount: Decimal, // Sum of every refund, never more than `amount`
 }
 
+// Columns selected for every payment query, in the order expected by `Payment::from_row`.
+const PAYMENT_COLUMNS: &str =
+    "id, order_id, payment_method, status, amount, currency, provider, provider_reference, \
+     refunded_amount";
+
 impl Payment {
+    fn from_row(row: &Row) -> Result<Payment, AppError> {
+        Ok(Payment {
+            id: row.get(0),
+            order_id: row.get(1),
+            payment_method: row.get(2),
+            status: row.get::<_, &str>(3).parse()?,
+            amount: row.get(4),
+            currency: row.get(5),
+            provider: row.get(6),
+            provider_reference: row.get(7),
+            refunded_amount: row.get(8),
+        })
+    }
+
     /// Creates a new payment for a given order.
-    /// The payment is inserted into the database with a default 'pending' status.
+    /// The payment is inserted into the database with a default 'pending' status and the
+    /// order's current grand total as amount.
+    /// Fails with a conflict if the order already has a payment that is not failed or voided.
     pub async fn create_payment(
-        client: &Client,
+        client: &impl GenericClient,
         order_id: Uuid,
         payment_method: &str,
-    ) -> Result<Payment, Box<dyn Error>> {
+    ) -> Result<Payment, AppError> {
         // Generate a new UUID for the payment.
         let id = Uuid::new_v4();
 
         // The default status will be 'pending'.
-        let status = "pending";
+        let status = PaymentStatus::Pending.as_str();
 
-        // Insert the payment into the database.
+        // Insert the payment into the database, priced from the order.
+        let query = format!(
+            "INSERT INTO payments (id, order_id, payment_method, status, amount, currency)
+             SELECT $1, id, $3, $4, grand_total, currency FROM orders WHERE id = $2
+             RETURNING {}",
+            PAYMENT_COLUMNS
+        );
         let row = client
-            .query_one(
-                "INSERT INTO payments (id, order_id, payment_method, status) 
-                 VALUES ($1, $2, $3, $4) 
-                 RETURNING id, order_id, payment_method, status",
-                &[&id, &order_id, &payment_method, &status],
-            )
-            .await?;
+            .query_opt(&query, &[&id, &order_id, &payment_method, &status])
+            .await
+            .map_err(|err| match err.code() {
+                Some(code) if *code == SqlState::UNIQUE_VIOLATION => {
+                    AppError::conflict("The order already has an active payment")
+                }
+                _ => err.into(),
+            })?
+            .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
 
         // Return the created payment.
-        Ok(Payment {
-            id: row.get(0),
-            order_id: row.get(1),
-            payment_method: row.get(2),
-            status: row.get(3),
-        })
+        Self::from_row(&row)
     }
 
     pub async fn verify_payment_owner(
         client: &Client,
         payment_id: &Uuid,
         user_id: Uuid,
-    ) -> Result<bool, tokio_postgres::Error> {
+    ) -> Result<bool, AppError> {
         let query = "
             SELECT COUNT(*)
             FROM payments
             INNER JOIN orders ON payments.order_id = orders.id
             WHERE payments.id = $1 AND orders.user_id = $2
         ";
-    
+
         let row = client.query_one(query, &[payment_id, &user_id]).await?;
         Ok(row.get::<_, i64>(0) > 0)
     }
-    
 
-    pub async fn update_payment_status(
-        client: &Client,
+    /// Retrieves a payment by its ID.
+    pub async fn get_payment_by_id(
+        client: &impl GenericClient,
         payment_id: Uuid,
-    ) -> Result<bool, Box<dyn Error>> {
-        // Updates the payment status to "paid" or "completed"
-        let result = client
-            .execute(
-                "UPDATE payments SET status = 'paid' WHERE id = $1",
-                &[&payment_id],
+    ) -> Result<Option<Payment>, AppError> {
+        let query = format!("SELECT {} FROM payments WHERE id = $1", PAYMENT_COLUMNS);
+        let row = client.query_opt(&query, &[&payment_id]).await?;
+
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Retrieves a payment by the reference its provider assigned to it.
+    pub async fn find_by_provider_reference(
+        client: &impl GenericClient,
+        provider: &str,
+        provider_reference: &str,
+    ) -> Result<Option<Payment>, AppError> {
+        let query = format!(
+            "SELECT {} FROM payments WHERE provider = $1 AND provider_reference = $2",
+            PAYMENT_COLUMNS
+        );
+        let row = client
+            .query_opt(&query, &[&provider, &provider_reference])
+            .await?;
+
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Retrieves a payment and locks its row until the end of the transaction.
+    /// Lock the payment's order first, so every flow takes the locks in the same order.
+    pub async fn get_payment_for_update(
+        client: &impl GenericClient,
+        payment_id: Uuid,
+    ) -> Result<Option<Payment>, AppError> {
+        let query = format!(
+            "SELECT {} FROM payments WHERE id = $1 FOR UPDATE",
+            PAYMENT_COLUMNS
+        );
+        let row = client.query_opt(&query, &[&payment_id]).await?;
+
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Retrieves every payment of an order, oldest first.
+    pub async fn list_for_order(
+        client: &impl GenericClient,
+        order_id: Uuid,
+    ) -> Result<Vec<Payment>, AppError> {
+        let query = format!(
+            "SELECT {} FROM payments WHERE order_id = $1 ORDER BY created_at, id",
+            PAYMENT_COLUMNS
+        );
+        let rows = client.query(&query, &[&order_id]).await?;
+
+        rows.iter().map(Self::from_row).collect()
+    }
+
+    /// Records a successful authorization: the provider, its reference and the authorized amount.
+    pub async fn mark_authorized(
+        client: &impl GenericClient,
+        payment_id: Uuid,
+        provider: &str,
+        provider_reference: &str,
+        amount: Decimal,
+    ) -> Result<Payment, AppError> {
+        let query = format!(
+            "UPDATE payments
+             SET status = $1, provider = $2, provider_reference = $3, amount = $4, updated_at = now()
+             WHERE id = $5
+             RETURNING {}",
+            PAYMENT_COLUMNS
+        );
+        let row = client
+            .query_one(
+                &query,
+                &[
+                    &PaymentStatus::Authorized.as_str(),
+                    &provider,
+                    &provider_reference,
+                    &amount,
+                    &payment_id,
+                ],
             )
             .await?;
 
-        // Returns true if the status was updated
-        Ok(result > 0)
+        Self::from_row(&row)
     }
 
+    /// Moves a payment to authorizing for `amount`, before it is sent to the provider.
+    /// The transaction must be committed before the provider is called.
+    pub async fn begin_authorization(
+        client: &impl GenericClient,
+        payment_id: Uuid,
+        amount: Decimal,
+    ) -> Result<Payment, AppError> {
+        let query = format!(
+            "UPDATE payments SET status = $1, amount = $2, updated_at = now()
+             WHERE id = $3
+             RETURNING {}",
+            PAYMENT_COLUMNS
+        );
+        let row = client
+            .query_one(
+                &query,
+                &[&PaymentStatus::Authorizing.as_str(), &amount, &payment_id],
+            )
+            .await?;
+
+        Self::from_row(&row)
+    }
+
+    /// Moves a payment to another status.
+    /// The caller is responsible for only doing so after the provider confirmed the change.
+    pub async fn set_status(
+        client: &impl GenericClient,
+        payment_id: Uuid,
+        status: PaymentStatus,
+    ) -> Result<Payment, AppError> {
+        let query = format!(
+            "UPDATE payments SET status = $1, updated_at = now() WHERE id = $2 RETURNING {}",
+            PAYMENT_COLUMNS
+        );
+        let row = client
+            .query_opt(&query, &[&status.as_str(), &payment_id])
+            .await?
+            .ok_or_else(|| AppError::NotFound("Payment not found".to_string()))?;
+
+        Self::from_row(&row)
+    }
+
+    /// Returns the amount that can still be refunded, zero unless the payment was captured.
+    pub fn refundable_amount(&self) -> Decimal {
+        match self.status {
+            PaymentStatus::Paid | PaymentStatus::PartiallyRefunded => {
+                self.amount - self.refunded_amount
+            }
+            _ => Decimal::ZERO,
+        }
+    }
+
+    /// Adds a refund to the payment and moves it to refunded or partially_refunded.
+    /// The database rejects refunds that would exceed the captured amount.
+    pub async fn add_refund(
+        client: &impl GenericClient,
+        payment_id: Uuid,
+        amount: Decimal,
+    ) -> Result<Payment, AppError> {
+        let query = format!(
+            "UPDATE payments
+             SET refunded_amount = refunded_amount + $1,
+                 status = CASE WHEN refunded_amount + $1 >= amount
+                               THEN 'refunded' ELSE 'partially_refunded' END,
+                 updated_at = now()
+             WHERE id = $2
+             RETURNING {}",
+            PAYMENT_COLUMNS
+        );
+        let row = client.query_one(&query, &[&amount, &payment_id]).await?;
+
+        Self::from_row(&row)
+    }
+
+    /// Changes the payment method of a payment that has not been sent to the provider yet.
+    /// Returns false if the payment does not exist or is no longer pending.
     pub async fn update_payment_method(
         client: &Client,
         payment_id: Uuid,
         new_payment_method: &str,
-    ) -> Result<bool, Box<dyn Error>> {
+    ) -> Result<bool, AppError> {
         // Updates the payment method in the database.
         let result = client
             .execute(
-                "UPDATE payments SET payment_method = $1 WHERE id = $2",
+                "UPDATE payments SET payment_method = $1, updated_at = now()
+                 WHERE id = $2 AND status = 'pending'",
                 &[&new_payment_method, &payment_id],
             )
             .await?;
@@ -95,31 +345,17 @@ impl Payment {
         Ok(result > 0)
     }
 
-    /// Retrieves a payment associated with a given order.
-    pub async fn get_payment(
-        client: &Client,
-        order_id: Uuid,
-    ) -> Result<Option<Payment>, Box<dyn Error>> {
+    /// Retrieves the most recent payment associated with a given order.
+    pub async fn get_payment(client: &Client, order_id: Uuid) -> Result<Option<Payment>, AppError> {
         // Query the database to retrieve the payment for the given order ID.
-        let rows = client
-            .query(
-                "SELECT id, order_id, payment_method, status 
-                 FROM payments WHERE order_id = $1",
-                &[&order_id],
-            )
-            .await?;
+        let query = format!(
+            "SELECT {} FROM payments WHERE order_id = $1 ORDER BY created_at DESC, id LIMIT 1",
+            PAYMENT_COLUMNS
+        );
+        let rows = client.query(&query, &[&order_id]).await?;
 
         // If a matching row is found, construct and return the Payment struct.
-        if let Some(row) = rows.get(0) {
-            Ok(Some(Payment {
-                id: row.get(0),
-                order_id: row.get(1),
-                payment_method: row.get(2),
-                status: row.get(3),
-            }))
-        } else {
-            // Return None if no payment matches the given order ID.
-            Ok(None)
-        }
+        // Return None if no payment matches the given order ID.
+        rows.first().map(Self::from_row).transpose()
     }
 }
@@ -1,8 +1,13 @@
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use tokio_postgres::{Client, Error};
+use tokio_postgres::{types::ToSql, Client, Row};
 use uuid::Uuid;
 
+use crate::errors::AppError;
+use crate::models::category_model::subtree_sql;
+use crate::pagination::SortOrder;
+
 /// Represents a product entity with its attributes.
 #[derive(Serialize, Deserialize)]
 pub struct Product {
@@ -11,67 +16,391 @@ pub struct Product {
     pub description: Option<String>, // Optional description of the product, can be null.
     pub price: Decimal,              // Price of the product, using Decimal for precision.
     pub stock: i32,                  // Quantity of the product available in stock.
+    pub created_at: DateTime<Utc>,   // When the product was added to the catalog.
+    pub version: i32,                // Incremented on every update, exposed as the ETag.
+    pub tax_class: String,           // Selects the tax rates applied to the product.
+    pub weight_kg: Decimal,          // Weight of one packed unit, for shipping.
+    pub length_cm: Decimal,          // Package dimensions of one unit, for shipping.
+    pub width_cm: Decimal,
+    pub height_cm: Decimal,
+    pub category_ids: Vec<Uuid>, // Categories the product is listed in.
+}
+
+/// Attributes of a product to create.
+pub struct NewProduct<'a> {
+    pub name: &'a str,
+    pub description: Option<&'a str>,
+    pub price: Decimal,
+    pub stock: i32,
+    pub tax_class: &'a str,
+    pub weight_kg: Decimal,
+    pub length_cm: Decimal,
+    pub width_cm: Decimal,
+    pub height_cm: Decimal,
+}
+
+/// Changes applied by a partial product update; `None` leaves a field untouched.
+#[derive(Default)]
+pub struct ProductChanges {
+    pub name: Option<String>,
+    pub description: Option<Option<String>>, // `Some(None)` clears the description
+    pub price: Option<Decimal>,
+    pub stock: Option<i32>,
+    pub tax_class: Option<String>,
+    pub weight_kg: Option<Decimal>,
+    pub length_cm: Option<Decimal>,
+    pub width_cm: Option<Decimal>,
+    pub height_cm: Option<Decimal>,
+}
+
+/// Outcome of an update guarded by the product version.
+pub enum ProductUpdate {
+    Updated(Box<Product>),
+    NotFound,
+    VersionMismatch { current_version: i32 },
+}
+
+/// A product matching a search, with its relevance and the matched words highlighted.
+#[derive(Serialize)]
+pub struct ProductSearchHit {
+    #[serde(flatten)]
+    pub product: Product,
+    pub rank: f32,              // Relevance to the search, best matches first
+    pub name_highlight: String, // Name with the matched words in <mark> tags
+    pub description_snippet: Option<String>, // Fragments of the description around the matches
+}
+
+/// Field a product listing can be sorted by.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ProductSort {
+    Price,
+    Name,
+    #[default]
+    CreatedAt,
+}
+
+/// Criteria used to filter, sort and paginate the product catalog.
+#[derive(Default)]
+pub struct ProductFilter {
+    pub min_price: Option<Decimal>,
+    pub max_price: Option<Decimal>,
+    pub in_stock: bool,            // Only products with stock left, in any variant
+    pub category_id: Option<Uuid>, // Only products of the category or its subcategories
+    pub name: Option<String>,      // Case insensitive substring of the name
+    pub sort: ProductSort,
+    pub order: SortOrder,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+// Columns selected for every product query, in the order expected by `Product::from_row`.
+const PRODUCT_COLUMNS: &str = "id, name, description, price, stock, created_at, version, \
+                               tax_class, weight_kg, length_cm, width_cm, height_cm, \
+                               ARRAY(SELECT category_id FROM product_categories \
+                                     WHERE product_id = products.id \
+                                     ORDER BY category_id) AS category_ids";
+
+// Largest number of words of a search that are matched.
+const MAX_SEARCH_WORDS: usize = 16;
+
+// Options of the highlighted name and description snippet of search results.
+const NAME_HIGHLIGHT: &str = "HighlightAll=true, StartSel=<mark>, StopSel=</mark>";
+const DESCRIPTION_SNIPPET: &str =
+    "StartSel=<mark>, StopSel=</mark>, MaxFragments=2, MaxWords=20, MinWords=5";
+
+// Turns the text typed by a user into a `tsquery` matching products holding every word, the
+// last ones possibly not typed in full. Only letters and digits are kept, so the text cannot
+// inject `tsquery` operators. Returns None if the text holds no word.
+fn search_query(text: &str) -> Option<String> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .take(MAX_SEARCH_WORDS)
+        .map(|word| format!("{}:*", word.to_lowercase()))
+        .collect();
+
+    (!words.is_empty()).then(|| words.join(" & "))
+}
+
+// Escapes the wildcard characters of a LIKE pattern so user input is matched literally.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
 }
 
 impl Product {
+    fn from_row(row: &Row) -> Product {
+        Product {
+            id: row.get(0),
+            name: row.get(1),
+            description: row.get(2),
+            price: row.get(3),
+            stock: row.get(4),
+            created_at: row.get(5),
+            version: row.get(6),
+            tax_class: row.get(7),
+            weight_kg: row.get(8),
+            length_cm: row.get(9),
+            width_cm: row.get(10),
+            height_cm: row.get(11),
+            category_ids: row.get(12),
+        }
+    }
+
+    /// Returns the strong ETag identifying the current version of the product.
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.version)
+    }
+
     /// Creates a new product in the database and returns the created product.
     pub async fn create_product(
         client: &Client,
-        name: &str,
-        description: Option<&str>,
-        price: Decimal,
-        stock: i32,
-    ) -> Result<Product, Error> {
+        product: &NewProduct<'_>,
+    ) -> Result<Product, AppError> {
         // Generate a new UUID for the product.
         let id = Uuid::new_v4();
 
         // Execute the SQL insert query, returning the newly created product attributes.
+        let query = format!(
+            "INSERT INTO products (id, name, description, price, stock, tax_class, weight_kg,
+                                   length_cm, width_cm, height_cm)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+             RETURNING {}",
+            PRODUCT_COLUMNS
+        );
         let row = client
             .query_one(
-                "INSERT INTO products (id, name, description, price, stock) 
-                 VALUES ($1, $2, $3, $4, $5) 
-                 RETURNING id, name, description, price, stock",
-                &[&id, &name, &description, &price, &stock], // Pass Decimal directly.
+                &query,
+                &[
+                    &id,
+                    &product.name,
+                    &product.description,
+                    &product.price, // Pass Decimal directly.
+                    &product.stock,
+                    &product.tax_class,
+                    &product.weight_kg,
+                    &product.length_cm,
+                    &product.width_cm,
+                    &product.height_cm,
+                ],
             )
             .await?;
 
         // Construct and return the Product struct from the database response.
-        Ok(Product {
-            id: row.get(0),
-            name: row.get(1),
-            description: row.get(2),
-            price: row.get(3),
-            stock: row.get(4),
-        })
+        Ok(Self::from_row(&row))
     }
 
     /// Retrieves a product from the database by its ID.
-    pub async fn get_product(client: &Client, product_id: Uuid) -> Result<Option<Product>, Error> {
+    pub async fn get_product(
+        client: &Client,
+        product_id: Uuid,
+    ) -> Result<Option<Product>, AppError> {
         // Query the database to fetch the product with the given ID.
+        let query = format!("SELECT {} FROM products WHERE id = $1", PRODUCT_COLUMNS);
+        let row = client.query_opt(&query, &[&product_id]).await?;
+
+        // Return None if no product matches the given ID.
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Lists products matching the filter.
+    /// Returns the requested page together with the total number of matching products.
+    pub async fn list(
+        client: &Client,
+        filter: &ProductFilter,
+    ) -> Result<(Vec<Product>, i64), AppError> {
+        let mut conditions: Vec<String> = Vec::new();
+        let mut params: Vec<Box<dyn ToSql + Sync + Send>> = Vec::new();
+
+        if let Some(min_price) = filter.min_price {
+            params.push(Box::new(min_price));
+            conditions.push(format!("price >= ${}", params.len()));
+        }
+        if let Some(max_price) = filter.max_price {
+            params.push(Box::new(max_price));
+            conditions.push(format!("price <= ${}", params.len()));
+        }
+        if filter.in_stock {
+            // Products sold in variants are in stock while one of their variants is
+            conditions.push(
+                "CASE WHEN EXISTS (SELECT 1 FROM product_variants WHERE product_id = products.id)
+                      THEN EXISTS (SELECT 1 FROM product_variants
+                                   WHERE product_id = products.id AND stock > 0)
+                      ELSE stock > 0 END"
+                    .to_string(),
+            );
+        }
+        if let Some(category_id) = filter.category_id {
+            params.push(Box::new(category_id));
+            let subtree = subtree_sql(&format!("id = ${}", params.len()));
+            conditions.push(format!(
+                "id IN (SELECT product_id FROM product_categories WHERE category_id IN ({}))",
+                subtree
+            ));
+        }
+        if let Some(name) = filter.name.as_deref().filter(|name| !name.is_empty()) {
+            params.push(Box::new(format!("%{}%", escape_like(name))));
+            conditions.push(format!("name ILIKE ${}", params.len()));
+        }
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        // The sort column comes from a fixed set, never from user input
+        let sort_column = match filter.sort {
+            ProductSort::Price => "price",
+            ProductSort::Name => "name",
+            ProductSort::CreatedAt => "created_at",
+        };
+        let direction = filter.order.as_sql();
+
+        let param_refs: Vec<&(dyn ToSql + Sync)> = params
+            .iter()
+            .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+            .collect();
+
+        let count_query = format!("SELECT COUNT(*) FROM products {}", where_clause);
+        let total: i64 = client.query_one(&count_query, &param_refs).await?.get(0);
+
+        // The ID breaks ties so pages stay stable between requests
+        let list_query = format!(
+            "SELECT {} FROM products {} ORDER BY {} {}, id {} LIMIT {} OFFSET {}",
+            PRODUCT_COLUMNS,
+            where_clause,
+            sort_column,
+            direction,
+            direction,
+            filter.limit,
+            filter.offset
+        );
+        let rows = client.query(&list_query, &param_refs).await?;
+
+        Ok((rows.iter().map(Self::from_row).collect(), total))
+    }
+
+    /// Searches the name and description of products, best matches first: words found in the
+    /// name count more than words found in the description. Every word of `text` must match,
+    /// as a prefix so results show up while typing. Returns the page of hits along with the
+    /// total number of matching products.
+    pub async fn search(
+        client: &Client,
+        text: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<ProductSearchHit>, i64), AppError> {
+        let query = search_query(text)
+            .ok_or_else(|| AppError::validation("q must contain at least one letter or digit"))?;
+
+        let total: i64 = client
+            .query_one(
+                "SELECT COUNT(*) FROM products WHERE search_vector @@ to_tsquery('simple', $1)",
+                &[&query],
+            )
+            .await?
+            .get(0);
+
+        // The ID breaks ties so pages stay stable between requests
+        let search_query = format!(
+            "SELECT {},
+                    ts_rank(search_vector, search.query) AS rank,
+                    ts_headline('simple', name, search.query, $2),
+                    ts_headline('simple', description, search.query, $3)
+             FROM products, (SELECT to_tsquery('simple', $1) AS query) AS search
+             WHERE search_vector @@ search.query
+             ORDER BY rank DESC, id
+             LIMIT {} OFFSET {}",
+            PRODUCT_COLUMNS, limit, offset
+        );
         let rows = client
             .query(
-                "SELECT id, name, description, price, stock FROM products WHERE id = $1",
-                &[&product_id],
+                &search_query,
+                &[&query, &NAME_HIGHLIGHT, &DESCRIPTION_SNIPPET],
             )
             .await?;
 
-        // If a matching row is found, construct and return the Product struct.
-        if let Some(row) = rows.get(0) {
-            Ok(Some(Product {
-                id: row.get(0),
-                name: row.get(1),
-                description: row.get(2),
-                price: row.get(3),
-                stock: row.get(4),
-            }))
-        } else {
-            // Return None if no product matches the given ID.
-            Ok(None)
+        let hits = rows
+            .iter()
+            .map(|row| ProductSearchHit {
+                product: Self::from_row(row),
+                rank: row.get(13),
+                name_highlight: row.get(14),
+                description_snippet: row.get(15),
+            })
+            .collect();
+
+        Ok((hits, total))
+    }
+
+    /// Applies a partial update to a product.
+    /// When `expected_version` is given the update only happens if the product is still at
+    /// that version, so concurrent edits cannot silently overwrite each other.
+    pub async fn update_product(
+        client: &Client,
+        product_id: Uuid,
+        changes: &ProductChanges,
+        expected_version: Option<i32>,
+    ) -> Result<ProductUpdate, AppError> {
+        let clear_or_set_description = changes.description.is_some();
+        let description = changes.description.clone().flatten();
+
+        let query = format!(
+            "UPDATE products SET
+                name = COALESCE($2, name),
+                description = CASE WHEN $3 THEN $4 ELSE description END,
+                price = COALESCE($5, price),
+                stock = COALESCE($6, stock),
+                tax_class = COALESCE($8, tax_class),
+                weight_kg = COALESCE($9, weight_kg),
+                length_cm = COALESCE($10, length_cm),
+                width_cm = COALESCE($11, width_cm),
+                height_cm = COALESCE($12, height_cm),
+                version = version + 1,
+                updated_at = now()
+             WHERE id = $1 AND ($7::INTEGER IS NULL OR version = $7)
+             RETURNING {}",
+            PRODUCT_COLUMNS
+        );
+        let row = client
+            .query_opt(
+                &query,
+                &[
+                    &product_id,
+                    &changes.name,
+                    &clear_or_set_description,
+                    &description,
+                    &changes.price,
+                    &changes.stock,
+                    &expected_version,
+                    &changes.tax_class,
+                    &changes.weight_kg,
+                    &changes.length_cm,
+                    &changes.width_cm,
+                    &changes.height_cm,
+                ],
+            )
+            .await?;
+
+        if let Some(row) = row {
+            return Ok(ProductUpdate::Updated(Box::new(Self::from_row(&row))));
+        }
+
+        // Nothing was updated: either the product is gone or someone else changed it first
+        match Self::get_product(client, product_id).await? {
+            Some(product) => Ok(ProductUpdate::VersionMismatch {
+                current_version: product.version,
+            }),
+            None => Ok(ProductUpdate::NotFound),
         }
     }
 
     /// Deletes a product from the database by its ID.
-    pub async fn delete_product(client: &Client, product_id: Uuid) -> Result<bool, Error> {
+    pub async fn delete_product(client: &Client, product_id: Uuid) -> Result<bool, AppError> {
         // Execute the SQL delete query for the specified product ID.
         let result = client
             .execute("DELETE FROM products WHERE id = $1", &[&product_id])
@@ -81,4 +410,3 @@ impl Product {
         Ok(result > 0)
     }
 }
-
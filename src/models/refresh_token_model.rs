@@ -0,0 +1,108 @@
+use chrono::{DateTime, Duration, Utc};
+use deadpool_postgres::GenericClient;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::crypto::{random_token, sha256_hex};
+use crate::errors::AppError;
+
+/// Represents a refresh token issued to a user.
+/// Only the SHA-256 hash of the token is stored; the plain value is returned once, on issue.
+/// Tokens obtained from each other through rotation share the same `family_id`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>, // Set once the token has been rotated
+    pub revoked_at: Option<DateTime<Utc>>, // Set when the token family is revoked
+}
+
+impl RefreshToken {
+    fn from_row(row: &Row) -> RefreshToken {
+        RefreshToken {
+            id: row.get(0),
+            user_id: row.get(1),
+            family_id: row.get(2),
+            expires_at: row.get(3),
+            used_at: row.get(4),
+            revoked_at: row.get(5),
+        }
+    }
+
+    /// Issues a new refresh token in the given family.
+    /// Returns the stored token together with its plain value.
+    pub async fn issue(
+        client: &impl GenericClient,
+        user_id: Uuid,
+        family_id: Uuid,
+        ttl: Duration,
+    ) -> Result<(RefreshToken, String), AppError> {
+        let id = Uuid::new_v4();
+        let token = random_token();
+        let expires_at = Utc::now() + ttl;
+
+        let row = client
+            .query_one(
+                "INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at)
+                 VALUES ($1, $2, $3, $4, $5)
+                 RETURNING id, user_id, family_id, expires_at, used_at, revoked_at",
+                &[&id, &user_id, &family_id, &sha256_hex(&token), &expires_at],
+            )
+            .await?;
+
+        Ok((Self::from_row(&row), token))
+    }
+
+    /// Finds a refresh token by its plain value, locking it until the transaction ends.
+    pub async fn find_for_update(
+        client: &impl GenericClient,
+        token: &str,
+    ) -> Result<Option<RefreshToken>, AppError> {
+        let row = client
+            .query_opt(
+                "SELECT id, user_id, family_id, expires_at, used_at, revoked_at
+                 FROM refresh_tokens WHERE token_hash = $1 FOR UPDATE",
+                &[&sha256_hex(token)],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Returns true if the token is past its expiration time.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+
+    /// Marks a token as used, so presenting it again is detected as reuse.
+    pub async fn mark_used(client: &impl GenericClient, token_id: Uuid) -> Result<(), AppError> {
+        client
+            .execute(
+                "UPDATE refresh_tokens SET used_at = now() WHERE id = $1",
+                &[&token_id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Revokes every token of a family.
+    /// Returns the number of tokens that were still active.
+    pub async fn revoke_family(
+        client: &impl GenericClient,
+        family_id: Uuid,
+    ) -> Result<u64, AppError> {
+        let revoked = client
+            .execute(
+                "UPDATE refresh_tokens SET revoked_at = now()
+                 WHERE family_id = $1 AND revoked_at IS NULL",
+                &[&family_id],
+            )
+            .await?;
+
+        Ok(revoked)
+    }
+}
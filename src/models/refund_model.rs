@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// State of a refund with the payment provider.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RefundStatus {
+    Pending,   // Recorded, the provider has not confirmed it yet
+    Succeeded, // Returned by the provider and added to the payment's refunded amount
+    Failed,    // Declined by the provider, nothing was returned
+}
+
+impl RefundStatus {
+    /// Returns the value stored in the `refunds.status` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefundStatus::Pending => "pending",
+            RefundStatus::Succeeded => "succeeded",
+            RefundStatus::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for RefundStatus {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending" => Ok(RefundStatus::Pending),
+            "succeeded" => Ok(RefundStatus::Succeeded),
+            "failed" => Ok(RefundStatus::Failed),
+            other => Err(AppError::Internal(format!(
+                "unknown refund status: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Money returned to the customer for a captured payment.
+#[derive(Serialize, Debug)]
+pub struct Refund {
+    pub id: Uuid,
+    pub payment_id: Uuid,
+    pub amount: Decimal,
+    pub status: RefundStatus,
+    pub reason: Option<String>,
+    pub restocked: bool, // Whether the refunded items were put back in stock
+    pub provider_reference: Option<String>, // Provider's ID for the refund, once it succeeded
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub items: Vec<RefundItem>, // Empty for refunds of an arbitrary amount
+}
+
+/// Quantity of an order item covered by a refund.
+#[derive(Serialize, Debug, Clone)]
+pub struct RefundItem {
+    pub order_item_id: Uuid,
+    pub quantity: i32,
+    pub amount: Decimal,
+}
+
+/// Data needed to record a pending refund.
+pub struct NewRefund<'a> {
+    pub id: Uuid,
+    pub payment_id: Uuid,
+    pub amount: Decimal,
+    pub reason: Option<&'a str>,
+    pub restocked: bool,
+    pub created_by: Option<Uuid>, // None for refunds made on the provider's side
+    pub items: Vec<RefundItem>,
+}
+
+// Columns selected for every refund query, in the order expected by `Refund::from_row`.
+const REFUND_COLUMNS: &str =
+    "id, payment_id, amount, status, reason, restocked, provider_reference, created_by, \
+     created_at";
+
+impl Refund {
+    fn from_row(row: &Row, items: Vec<RefundItem>) -> Result<Refund, AppError> {
+        Ok(Refund {
+            id: row.get(0),
+            payment_id: row.get(1),
+            amount: row.get(2),
+            status: row.get::<_, &str>(3).parse()?,
+            reason: row.get(4),
+            restocked: row.get(5),
+            provider_reference: row.get(6),
+            created_by: row.get(7),
+            created_at: row.get(8),
+            items,
+        })
+    }
+
+    /// Stores a pending refund and its items.
+    /// The transaction must be committed before the provider is asked for the money, so a
+    /// refund the provider made is never lost; `complete` or `fail` records the outcome.
+    pub async fn create(
+        client: &impl GenericClient,
+        refund: NewRefund<'_>,
+    ) -> Result<Refund, AppError> {
+        let query = format!(
+            "INSERT INTO refunds (id, payment_id, amount, status, reason, restocked, created_by)
+             VALUES ($1, $2, $3, 'pending', $4, $5, $6)
+             RETURNING {}",
+            REFUND_COLUMNS
+        );
+        let row = client
+            .query_one(
+                &query,
+                &[
+                    &refund.id,
+                    &refund.payment_id,
+                    &refund.amount,
+                    &refund.reason,
+                    &refund.restocked,
+                    &refund.created_by,
+                ],
+            )
+            .await?;
+
+        for item in &refund.items {
+            client
+                .execute(
+                    "INSERT INTO refund_items (id, refund_id, order_item_id, quantity, amount)
+                     VALUES ($1, $2, $3, $4, $5)",
+                    &[
+                        &Uuid::new_v4(),
+                        &refund.id,
+                        &item.order_item_id,
+                        &item.quantity,
+                        &item.amount,
+                    ],
+                )
+                .await?;
+        }
+
+        Self::from_row(&row, refund.items)
+    }
+
+    // Loads the items of a refund.
+    async fn items(
+        client: &impl GenericClient,
+        refund_id: Uuid,
+    ) -> Result<Vec<RefundItem>, AppError> {
+        let rows = client
+            .query(
+                "SELECT order_item_id, quantity, amount FROM refund_items WHERE refund_id = $1",
+                &[&refund_id],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| RefundItem {
+                order_item_id: row.get(0),
+                quantity: row.get(1),
+                amount: row.get(2),
+            })
+            .collect())
+    }
+
+    /// Retrieves a refund of a payment with its items.
+    pub async fn get(
+        client: &impl GenericClient,
+        payment_id: Uuid,
+        refund_id: Uuid,
+    ) -> Result<Option<Refund>, AppError> {
+        Self::fetch(client, payment_id, refund_id, "").await
+    }
+
+    /// Retrieves a refund of a payment with its items, locking it until the end of the
+    /// transaction.
+    pub async fn get_for_update(
+        client: &impl GenericClient,
+        payment_id: Uuid,
+        refund_id: Uuid,
+    ) -> Result<Option<Refund>, AppError> {
+        Self::fetch(client, payment_id, refund_id, " FOR UPDATE").await
+    }
+
+    async fn fetch(
+        client: &impl GenericClient,
+        payment_id: Uuid,
+        refund_id: Uuid,
+        lock: &str,
+    ) -> Result<Option<Refund>, AppError> {
+        let query = format!(
+            "SELECT {} FROM refunds WHERE id = $1 AND payment_id = $2{}",
+            REFUND_COLUMNS, lock
+        );
+        match client.query_opt(&query, &[&refund_id, &payment_id]).await? {
+            Some(row) => Ok(Some(Self::from_row(
+                &row,
+                Self::items(client, refund_id).await?,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Marks a pending refund as succeeded with the provider's reference.
+    /// The payment's refunded amount must be updated in the same transaction.
+    pub async fn complete(
+        client: &impl GenericClient,
+        refund: Refund,
+        provider_reference: &str,
+    ) -> Result<Refund, AppError> {
+        client
+            .execute(
+                "UPDATE refunds
+                 SET status = 'succeeded', provider_reference = $1, updated_at = now()
+                 WHERE id = $2",
+                &[&provider_reference, &refund.id],
+            )
+            .await?;
+
+        Ok(Refund {
+            status: RefundStatus::Succeeded,
+            provider_reference: Some(provider_reference.to_string()),
+            ..refund
+        })
+    }
+
+    /// Marks a pending refund as failed, releasing its amount and items.
+    /// Returns false if the refund is no longer pending.
+    pub async fn fail(client: &impl GenericClient, refund_id: Uuid) -> Result<bool, AppError> {
+        let updated = client
+            .execute(
+                "UPDATE refunds SET status = 'failed', updated_at = now()
+                 WHERE id = $1 AND status = 'pending'",
+                &[&refund_id],
+            )
+            .await?;
+
+        Ok(updated == 1)
+    }
+
+    /// Returns the sum of the pending refunds of a payment. This money may already have been
+    /// returned by the provider, so it cannot be refunded again.
+    pub async fn pending_amount(
+        client: &impl GenericClient,
+        payment_id: Uuid,
+    ) -> Result<Decimal, AppError> {
+        let row = client
+            .query_one(
+                "SELECT COALESCE(SUM(amount), 0) FROM refunds
+                 WHERE payment_id = $1 AND status = 'pending'",
+                &[&payment_id],
+            )
+            .await?;
+
+        Ok(row.get(0))
+    }
+
+    /// Lists the refunds of a payment with their items, oldest first.
+    pub async fn list_for_payment(
+        client: &impl GenericClient,
+        payment_id: Uuid,
+    ) -> Result<Vec<Refund>, AppError> {
+        let query = format!(
+            "SELECT {} FROM refunds WHERE payment_id = $1 ORDER BY created_at, id",
+            REFUND_COLUMNS
+        );
+        let rows = client.query(&query, &[&payment_id]).await?;
+
+        let item_rows = client
+            .query(
+                "SELECT refund_items.refund_id, refund_items.order_item_id,
+                        refund_items.quantity, refund_items.amount
+                 FROM refund_items
+                 JOIN refunds ON refunds.id = refund_items.refund_id
+                 WHERE refunds.payment_id = $1",
+                &[&payment_id],
+            )
+            .await?;
+
+        let mut items: HashMap<Uuid, Vec<RefundItem>> = HashMap::new();
+        for row in item_rows {
+            items.entry(row.get(0)).or_default().push(RefundItem {
+                order_item_id: row.get(1),
+                quantity: row.get(2),
+                amount: row.get(3),
+            });
+        }
+
+        rows.iter()
+            .map(|row| {
+                let id: Uuid = row.get(0);
+                Self::from_row(row, items.remove(&id).unwrap_or_default())
+            })
+            .collect()
+    }
+
+    /// Returns how many units of each item of an order have already been refunded.
+    /// Pending refunds count as well, failed ones do not.
+    pub async fn refunded_quantities(
+        client: &impl GenericClient,
+        order_id: Uuid,
+    ) -> Result<HashMap<Uuid, i32>, AppError> {
+        let rows = client
+            .query(
+                "SELECT refund_items.order_item_id, SUM(refund_items.quantity)::INTEGER
+                 FROM refund_items
+                 JOIN refunds ON refunds.id = refund_items.refund_id
+                 JOIN order_items ON order_items.id = refund_items.order_item_id
+                 WHERE order_items.order_id = $1 AND refunds.status <> 'failed'
+                 GROUP BY refund_items.order_item_id",
+                &[&order_id],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    /// Returns how many units of each item of an order have been refunded and the amount
+    /// refunded for them. Pending refunds count as well, failed ones do not.
+    pub async fn refunded_totals(
+        client: &impl GenericClient,
+        order_id: Uuid,
+    ) -> Result<HashMap<Uuid, (i32, Decimal)>, AppError> {
+        let rows = client
+            .query(
+                "SELECT refund_items.order_item_id, SUM(refund_items.quantity)::INTEGER,
+                        SUM(refund_items.amount)
+                 FROM refund_items
+                 JOIN refunds ON refunds.id = refund_items.refund_id
+                 JOIN order_items ON order_items.id = refund_items.order_item_id
+                 WHERE order_items.order_id = $1 AND refunds.status <> 'failed'
+                 GROUP BY refund_items.order_item_id",
+                &[&order_id],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get(0), (row.get(1), row.get(2))))
+            .collect())
+    }
+}
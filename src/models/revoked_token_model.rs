@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// Represents an access token revoked before its expiration, identified by its `jti` claim.
+/// Entries are only needed until the token would have expired anyway.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RevokedToken {
+    pub jti: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl RevokedToken {
+    /// Adds an access token to the revocation list and prunes entries that have expired.
+    pub async fn revoke(
+        client: &impl GenericClient,
+        jti: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        client
+            .execute(
+                "INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, $2)
+                 ON CONFLICT (jti) DO NOTHING",
+                &[&jti, &expires_at],
+            )
+            .await?;
+
+        client
+            .execute("DELETE FROM revoked_tokens WHERE expires_at < now()", &[])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns true if the access token with the given `jti` has been revoked.
+    pub async fn is_revoked(client: &impl GenericClient, jti: Uuid) -> Result<bool, AppError> {
+        let row = client
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM revoked_tokens WHERE jti = $1)",
+                &[&jti],
+            )
+            .await?;
+
+        Ok(row.get(0))
+    }
+}
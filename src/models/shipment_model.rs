@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{types::Json, Row};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// Progress of a shipment.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ShipmentStatus {
+    Pending,   // Being packed
+    Shipped,   // Handed to the carrier
+    Delivered, // Received by the customer
+}
+
+impl ShipmentStatus {
+    /// Returns the value stored in the `shipments.status` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShipmentStatus::Pending => "pending",
+            ShipmentStatus::Shipped => "shipped",
+            ShipmentStatus::Delivered => "delivered",
+        }
+    }
+}
+
+impl FromStr for ShipmentStatus {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending" => Ok(ShipmentStatus::Pending),
+            "shipped" => Ok(ShipmentStatus::Shipped),
+            "delivered" => Ok(ShipmentStatus::Delivered),
+            other => Err(AppError::Internal(format!(
+                "unknown shipment status: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Units of an order item packed in a shipment.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShipmentItem {
+    pub order_item_id: Uuid,
+    pub quantity: i32,
+}
+
+/// A parcel sent for an order, holding some or all of its items.
+#[derive(Serialize, Debug)]
+pub struct Shipment {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub carrier: Option<String>, // e.g. "UPS"
+    pub tracking_number: Option<String>,
+    pub status: ShipmentStatus,
+    pub items: Vec<ShipmentItem>,
+    pub shipped_at: Option<DateTime<Utc>>, // Set when the shipment leaves
+    pub delivered_at: Option<DateTime<Utc>>, // Set when the shipment arrives
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Changes applied to a shipment; `None` leaves a field untouched.
+#[derive(Default)]
+pub struct ShipmentChanges {
+    pub carrier: Option<String>,
+    pub tracking_number: Option<String>,
+    pub status: Option<ShipmentStatus>,
+}
+
+/// How many units of an order item are in shipments, by progress.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ShippedQuantities {
+    pub assigned: i32,  // In any shipment
+    pub shipped: i32,   // In shipments that left, delivered or not
+    pub delivered: i32, // In delivered shipments
+}
+
+// Columns selected for every shipment query, in the order expected by `Shipment::from_row`.
+const SHIPMENT_COLUMNS: &str = "id, order_id, carrier, tracking_number, status, shipped_at, \
+     delivered_at, created_at, updated_at, \
+     COALESCE((SELECT json_agg(json_build_object('order_item_id', order_item_id, \
+                                                 'quantity', quantity) ORDER BY order_item_id) \
+               FROM shipment_items WHERE shipment_id = shipments.id), '[]') AS items";
+
+impl Shipment {
+    fn from_row(row: &Row) -> Result<Shipment, AppError> {
+        Ok(Shipment {
+            id: row.get(0),
+            order_id: row.get(1),
+            carrier: row.get(2),
+            tracking_number: row.get(3),
+            status: row.get::<_, &str>(4).parse()?,
+            shipped_at: row.get(5),
+            delivered_at: row.get(6),
+            created_at: row.get(7),
+            updated_at: row.get(8),
+            items: row.get::<_, Json<Vec<ShipmentItem>>>(9).0,
+        })
+    }
+
+    /// Creates a pending shipment of an order holding `items`.
+    /// The caller checks that the items belong to the order and are not shipped yet.
+    pub async fn create(
+        client: &impl GenericClient,
+        order_id: Uuid,
+        carrier: Option<&str>,
+        tracking_number: Option<&str>,
+        items: &[ShipmentItem],
+    ) -> Result<Shipment, AppError> {
+        let id = Uuid::new_v4();
+
+        client
+            .execute(
+                "INSERT INTO shipments (id, order_id, carrier, tracking_number)
+                 VALUES ($1, $2, $3, $4)",
+                &[&id, &order_id, &carrier, &tracking_number],
+            )
+            .await?;
+
+        for item in items {
+            client
+                .execute(
+                    "INSERT INTO shipment_items (shipment_id, order_item_id, quantity)
+                     VALUES ($1, $2, $3)",
+                    &[&id, &item.order_item_id, &item.quantity],
+                )
+                .await?;
+        }
+
+        Self::get_by_id(client, id)
+            .await?
+            .ok_or_else(|| AppError::Internal(format!("shipment {} vanished", id)))
+    }
+
+    /// Retrieves a shipment by its ID.
+    pub async fn get_by_id(
+        client: &impl GenericClient,
+        shipment_id: Uuid,
+    ) -> Result<Option<Shipment>, AppError> {
+        let query = format!("SELECT {} FROM shipments WHERE id = $1", SHIPMENT_COLUMNS);
+        let row = client.query_opt(&query, &[&shipment_id]).await?;
+
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Retrieves a shipment and locks its row until the end of the transaction.
+    /// Its order must be locked first, like for payments.
+    pub async fn get_for_update(
+        client: &impl GenericClient,
+        shipment_id: Uuid,
+    ) -> Result<Option<Shipment>, AppError> {
+        let query = format!(
+            "SELECT {} FROM shipments WHERE id = $1 FOR UPDATE",
+            SHIPMENT_COLUMNS
+        );
+        let row = client.query_opt(&query, &[&shipment_id]).await?;
+
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Lists the shipments of an order, oldest first.
+    pub async fn list_for_order(
+        client: &impl GenericClient,
+        order_id: Uuid,
+    ) -> Result<Vec<Shipment>, AppError> {
+        let query = format!(
+            "SELECT {} FROM shipments WHERE order_id = $1 ORDER BY created_at, id",
+            SHIPMENT_COLUMNS
+        );
+        let rows = client.query(&query, &[&order_id]).await?;
+
+        rows.iter().map(Self::from_row).collect()
+    }
+
+    /// Applies changes to a shipment. A new status records when the shipment left or
+    /// arrived; a shipment delivered without having been marked as shipped gets both times.
+    /// The caller checks that the status only moves forward.
+    pub async fn update(
+        client: &impl GenericClient,
+        shipment_id: Uuid,
+        changes: &ShipmentChanges,
+    ) -> Result<Shipment, AppError> {
+        let status = changes.status.map(|status| status.as_str());
+
+        let query = format!(
+            "UPDATE shipments SET
+                carrier = COALESCE($2, carrier),
+                tracking_number = COALESCE($3, tracking_number),
+                status = COALESCE($4, status),
+                shipped_at = CASE WHEN $4 IN ('shipped', 'delivered')
+                                  THEN COALESCE(shipped_at, now()) ELSE shipped_at END,
+                delivered_at = CASE WHEN $4 = 'delivered'
+                                    THEN COALESCE(delivered_at, now()) ELSE delivered_at END,
+                updated_at = now()
+             WHERE id = $1
+             RETURNING {}",
+            SHIPMENT_COLUMNS
+        );
+        let row = client
+            .query_one(
+                &query,
+                &[
+                    &shipment_id,
+                    &changes.carrier,
+                    &changes.tracking_number,
+                    &status,
+                ],
+            )
+            .await?;
+
+        Self::from_row(&row)
+    }
+
+    /// Returns how many units of each item of an order are in its shipments.
+    pub async fn quantities(
+        client: &impl GenericClient,
+        order_id: Uuid,
+    ) -> Result<HashMap<Uuid, ShippedQuantities>, AppError> {
+        let rows = client
+            .query(
+                "SELECT shipment_items.order_item_id,
+                        SUM(shipment_items.quantity)::INTEGER,
+                        (SUM(shipment_items.quantity)
+                            FILTER (WHERE shipments.status <> 'pending'))::INTEGER,
+                        (SUM(shipment_items.quantity)
+                            FILTER (WHERE shipments.status = 'delivered'))::INTEGER
+                 FROM shipment_items
+                 INNER JOIN shipments ON shipments.id = shipment_items.shipment_id
+                 WHERE shipments.order_id = $1
+                 GROUP BY shipment_items.order_item_id",
+                &[&order_id],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let quantities = ShippedQuantities {
+                    assigned: row.get(1),
+                    shipped: row.get::<_, Option<i32>>(2).unwrap_or_default(),
+                    delivered: row.get::<_, Option<i32>>(3).unwrap_or_default(),
+                };
+                (row.get(0), quantities)
+            })
+            .collect())
+    }
+}
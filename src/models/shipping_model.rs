@@ -0,0 +1,329 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// A way of shipping orders customers can choose, e.g. standard or express.
+#[derive(Serialize, Debug)]
+pub struct ShippingMethod {
+    pub id: Uuid,
+    pub code: String, // Chosen by customers when placing an order, e.g. "express"
+    pub name: String, // Shown to customers, e.g. "Express (1-2 days)"
+    pub active: bool, // Inactive methods cannot be chosen for new orders
+    pub created_at: DateTime<Utc>,
+}
+
+/// Copy of a shipping method stored on an order.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShippingMethodSnapshot {
+    pub id: Uuid,
+    pub code: String,
+    pub name: String,
+}
+
+/// A group of regions sharing the same shipping rates.
+#[derive(Serialize, Debug)]
+pub struct ShippingZone {
+    pub id: Uuid,
+    pub name: String,
+    pub regions: Vec<String>, // Countries ("DE") and subdivisions ("US-HI") served by the zone
+    pub created_at: DateTime<Utc>,
+}
+
+/// Price of a shipping method in a zone, for parcels up to a weight.
+#[derive(Serialize, Debug)]
+pub struct ShippingRate {
+    pub id: Uuid,
+    pub zone_id: Uuid,
+    pub method_id: Uuid,
+    pub max_weight_kg: Option<Decimal>, // Heaviest billable weight covered, None for any weight
+    pub price: Decimal,
+    pub free_over: Option<Decimal>, // Subtotal after discounts from which shipping is free
+    pub created_at: DateTime<Utc>,
+}
+
+/// Attributes of a shipping rate to create.
+pub struct NewShippingRate {
+    pub method_id: Uuid,
+    pub max_weight_kg: Option<Decimal>,
+    pub price: Decimal,
+    pub free_over: Option<Decimal>,
+}
+
+// Columns selected for every query, in the order expected by the `from_row` functions.
+const METHOD_COLUMNS: &str = "id, code, name, active, created_at";
+const ZONE_COLUMNS: &str = "id, name, created_at, \
+     ARRAY(SELECT region FROM shipping_zone_regions \
+           WHERE zone_id = shipping_zones.id ORDER BY region) AS regions";
+const RATE_COLUMNS: &str = "id, zone_id, method_id, max_weight_kg, price, free_over, created_at";
+
+impl ShippingMethod {
+    fn from_row(row: &Row) -> ShippingMethod {
+        ShippingMethod {
+            id: row.get(0),
+            code: row.get(1),
+            name: row.get(2),
+            active: row.get(3),
+            created_at: row.get(4),
+        }
+    }
+
+    /// Returns the copy of the method to store on an order.
+    pub fn snapshot(&self) -> ShippingMethodSnapshot {
+        ShippingMethodSnapshot {
+            id: self.id,
+            code: self.code.clone(),
+            name: self.name.clone(),
+        }
+    }
+
+    /// Creates a method. Fails with a conflict if the code is taken.
+    pub async fn create(
+        client: &impl GenericClient,
+        code: &str,
+        name: &str,
+    ) -> Result<ShippingMethod, AppError> {
+        let query = format!(
+            "INSERT INTO shipping_methods (id, code, name) VALUES ($1, $2, $3) RETURNING {}",
+            METHOD_COLUMNS
+        );
+        let row = client
+            .query_one(&query, &[&Uuid::new_v4(), &code, &name])
+            .await?;
+
+        Ok(Self::from_row(&row))
+    }
+
+    /// Lists methods by name, only the active ones unless `include_inactive` is set.
+    pub async fn list(
+        client: &impl GenericClient,
+        include_inactive: bool,
+    ) -> Result<Vec<ShippingMethod>, AppError> {
+        let query = format!(
+            "SELECT {} FROM shipping_methods WHERE active OR $1 ORDER BY name, id",
+            METHOD_COLUMNS
+        );
+        let rows = client.query(&query, &[&include_inactive]).await?;
+
+        Ok(rows.iter().map(Self::from_row).collect())
+    }
+
+    /// Retrieves a method by its ID.
+    pub async fn get_by_id(
+        client: &impl GenericClient,
+        method_id: Uuid,
+    ) -> Result<Option<ShippingMethod>, AppError> {
+        let query = format!(
+            "SELECT {} FROM shipping_methods WHERE id = $1",
+            METHOD_COLUMNS
+        );
+        let row = client.query_opt(&query, &[&method_id]).await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Retrieves an active method by its code.
+    pub async fn get_active_by_code(
+        client: &impl GenericClient,
+        code: &str,
+    ) -> Result<Option<ShippingMethod>, AppError> {
+        let query = format!(
+            "SELECT {} FROM shipping_methods WHERE code = $1 AND active",
+            METHOD_COLUMNS
+        );
+        let row = client.query_opt(&query, &[&code]).await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Stops a method from being chosen. Orders already using it keep it.
+    /// Returns None if the method does not exist.
+    pub async fn deactivate(
+        client: &impl GenericClient,
+        method_id: Uuid,
+    ) -> Result<Option<ShippingMethod>, AppError> {
+        let query = format!(
+            "UPDATE shipping_methods SET active = FALSE WHERE id = $1 RETURNING {}",
+            METHOD_COLUMNS
+        );
+        let row = client.query_opt(&query, &[&method_id]).await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+}
+
+impl ShippingZone {
+    fn from_row(row: &Row) -> ShippingZone {
+        ShippingZone {
+            id: row.get(0),
+            name: row.get(1),
+            created_at: row.get(2),
+            regions: row.get(3),
+        }
+    }
+
+    /// Creates a zone serving `regions`. Fails with a conflict if the name is taken or a
+    /// region is already served by another zone.
+    pub async fn create(
+        client: &impl GenericClient,
+        name: &str,
+        regions: &[String],
+    ) -> Result<ShippingZone, AppError> {
+        let id = Uuid::new_v4();
+
+        client
+            .execute(
+                "INSERT INTO shipping_zones (id, name) VALUES ($1, $2)",
+                &[&id, &name],
+            )
+            .await?;
+
+        for region in regions {
+            client
+                .execute(
+                    "INSERT INTO shipping_zone_regions (region, zone_id) VALUES ($1, $2)",
+                    &[region, &id],
+                )
+                .await?;
+        }
+
+        Self::get_by_id(client, id)
+            .await?
+            .ok_or_else(|| AppError::Internal(format!("shipping zone {} vanished", id)))
+    }
+
+    /// Retrieves a zone by its ID.
+    pub async fn get_by_id(
+        client: &impl GenericClient,
+        zone_id: Uuid,
+    ) -> Result<Option<ShippingZone>, AppError> {
+        let query = format!("SELECT {} FROM shipping_zones WHERE id = $1", ZONE_COLUMNS);
+        let row = client.query_opt(&query, &[&zone_id]).await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Lists every zone, by name.
+    pub async fn list(client: &impl GenericClient) -> Result<Vec<ShippingZone>, AppError> {
+        let query = format!(
+            "SELECT {} FROM shipping_zones ORDER BY name, id",
+            ZONE_COLUMNS
+        );
+        let rows = client.query(&query, &[]).await?;
+
+        Ok(rows.iter().map(Self::from_row).collect())
+    }
+
+    /// Deletes a zone together with its rates. Returns false if it does not exist.
+    pub async fn delete(client: &impl GenericClient, zone_id: Uuid) -> Result<bool, AppError> {
+        let deleted = client
+            .execute("DELETE FROM shipping_zones WHERE id = $1", &[&zone_id])
+            .await?;
+
+        Ok(deleted > 0)
+    }
+}
+
+impl ShippingRate {
+    fn from_row(row: &Row) -> ShippingRate {
+        ShippingRate {
+            id: row.get(0),
+            zone_id: row.get(1),
+            method_id: row.get(2),
+            max_weight_kg: row.get(3),
+            price: row.get(4),
+            free_over: row.get(5),
+            created_at: row.get(6),
+        }
+    }
+
+    /// Adds a weight bracket to a zone. Fails with a conflict if the zone already has a
+    /// bracket with the same method and maximum weight.
+    pub async fn create(
+        client: &impl GenericClient,
+        zone_id: Uuid,
+        rate: &NewShippingRate,
+    ) -> Result<ShippingRate, AppError> {
+        let query = format!(
+            "INSERT INTO shipping_rates (id, zone_id, method_id, max_weight_kg, price, free_over)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             RETURNING {}",
+            RATE_COLUMNS
+        );
+        let row = client
+            .query_one(
+                &query,
+                &[
+                    &Uuid::new_v4(),
+                    &zone_id,
+                    &rate.method_id,
+                    &rate.max_weight_kg,
+                    &rate.price,
+                    &rate.free_over,
+                ],
+            )
+            .await?;
+
+        Ok(Self::from_row(&row))
+    }
+
+    /// Lists the rates of a zone, by method and weight.
+    pub async fn list_for_zone(
+        client: &impl GenericClient,
+        zone_id: Uuid,
+    ) -> Result<Vec<ShippingRate>, AppError> {
+        let query = format!(
+            "SELECT {} FROM shipping_rates WHERE zone_id = $1
+             ORDER BY method_id, max_weight_kg NULLS LAST",
+            RATE_COLUMNS
+        );
+        let rows = client.query(&query, &[&zone_id]).await?;
+
+        Ok(rows.iter().map(Self::from_row).collect())
+    }
+
+    /// Deletes a rate. Returns false if it does not exist.
+    pub async fn delete(client: &impl GenericClient, rate_id: Uuid) -> Result<bool, AppError> {
+        let deleted = client
+            .execute("DELETE FROM shipping_rates WHERE id = $1", &[&rate_id])
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Finds the rate of a method for a parcel of `weight_kg` sent to `region`: the smallest
+    /// bracket covering the weight, in the zone serving the region. A zone listing the
+    /// subdivision ("US-HI") takes precedence over the zone listing its country ("US").
+    /// Returns None if the region is not served or the parcel is too heavy for the method.
+    pub async fn find(
+        client: &impl GenericClient,
+        region: &str,
+        method_id: Uuid,
+        weight_kg: Decimal,
+    ) -> Result<Option<ShippingRate>, AppError> {
+        let query = format!(
+            "SELECT {}
+             FROM shipping_rates
+             WHERE method_id = $2
+               AND (max_weight_kg IS NULL OR max_weight_kg >= $3)
+               AND zone_id = (
+                   SELECT zone_id FROM shipping_zone_regions
+                   WHERE region = $1 OR region = split_part($1, '-', 1)
+                   ORDER BY length(region) DESC
+                   LIMIT 1
+               )
+             ORDER BY max_weight_kg NULLS LAST
+             LIMIT 1",
+            RATE_COLUMNS
+        );
+        let row = client
+            .query_opt(&query, &[&region, &method_id, &weight_kg])
+            .await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+}
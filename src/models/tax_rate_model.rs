@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// Tax rate applied to the products of a tax class sold to a region.
+#[derive(Serialize, Debug)]
+pub struct TaxRate {
+    pub id: Uuid,
+    pub region: String, // ISO 3166-1 country ("DE") or ISO 3166-2 subdivision ("US-CA")
+    pub tax_class: String, // Matches `products.tax_class`, e.g. "standard" or "reduced"
+    pub rate: Decimal,  // Percentage, e.g. 7.25
+    pub name: String,   // Shown to customers, e.g. "VAT"
+    pub created_at: DateTime<Utc>,
+}
+
+// Columns selected for every rate query, in the order expected by `TaxRate::from_row`.
+const TAX_RATE_COLUMNS: &str = "id, region, tax_class, rate, name, created_at";
+
+impl TaxRate {
+    fn from_row(row: &Row) -> TaxRate {
+        TaxRate {
+            id: row.get(0),
+            region: row.get(1),
+            tax_class: row.get(2),
+            rate: row.get(3),
+            name: row.get(4),
+            created_at: row.get(5),
+        }
+    }
+
+    /// Creates a rate. Fails with a conflict if the region already has a rate for the class.
+    pub async fn create(
+        client: &impl GenericClient,
+        region: &str,
+        tax_class: &str,
+        rate: Decimal,
+        name: &str,
+    ) -> Result<TaxRate, AppError> {
+        let query = format!(
+            "INSERT INTO tax_rates (id, region, tax_class, rate, name)
+             VALUES ($1, $2, $3, $4, $5)
+             RETURNING {}",
+            TAX_RATE_COLUMNS
+        );
+        let row = client
+            .query_one(
+                &query,
+                &[&Uuid::new_v4(), &region, &tax_class, &rate, &name],
+            )
+            .await?;
+
+        Ok(Self::from_row(&row))
+    }
+
+    /// Lists every rate, by region and class.
+    pub async fn list(client: &impl GenericClient) -> Result<Vec<TaxRate>, AppError> {
+        let query = format!(
+            "SELECT {} FROM tax_rates ORDER BY region, tax_class",
+            TAX_RATE_COLUMNS
+        );
+        let rows = client.query(&query, &[]).await?;
+
+        Ok(rows.iter().map(Self::from_row).collect())
+    }
+
+    /// Deletes a rate. Returns false if it does not exist.
+    /// Orders keep the tax they were charged until their items change.
+    pub async fn delete(client: &impl GenericClient, rate_id: Uuid) -> Result<bool, AppError> {
+        let deleted = client
+            .execute("DELETE FROM tax_rates WHERE id = $1", &[&rate_id])
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Returns the rate of every tax class in a region, keyed by class.
+    /// The rates of a subdivision ("US-CA") take precedence over those of its country ("US").
+    pub async fn rates_for_region(
+        client: &impl GenericClient,
+        region: &str,
+    ) -> Result<HashMap<String, Decimal>, AppError> {
+        let rows = client
+            .query(
+                "SELECT DISTINCT ON (tax_class) tax_class, rate
+                 FROM tax_rates
+                 WHERE region = $1 OR region = split_part($1, '-', 1)
+                 ORDER BY tax_class, length(region) DESC",
+                &[&region],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+}
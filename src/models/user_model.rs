@@ -1,24 +1,58 @@
 use bcrypt::{hash, verify, DEFAULT_COST};
+use deadpool_postgres::GenericClient;
 use serde::{Deserialize, Serialize};
-use tokio_postgres::{types::ToSql, Client, Error};
+use tokio_postgres::Client;
 use uuid::Uuid;
 
+use crate::errors::AppError;
+
+/// Role of a user, which determines the endpoints they may access.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    #[default]
+    Customer,
+    Admin,
+}
+
+impl UserRole {
+    /// Returns the value stored in the `users.role` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Customer => "customer",
+            UserRole::Admin => "admin",
+        }
+    }
+}
+
+impl From<&str> for UserRole {
+    // Unknown values fall back to the least privileged role.
+    fn from(value: &str) -> Self {
+        match value {
+            "admin" => UserRole::Admin,
+            _ => UserRole::Customer,
+        }
+    }
+}
+
 /// Struct that represents a user in the system.
 #[derive(Serialize, Deserialize)]
 pub struct User {
-    pub id: Uuid,        // Unique identifier for the user (UUID format).
-    pub name: String,    // Name of the user.
-    pub email: String,   // Email address of the user.
-    pub password: String, // Hashed password of the user.
+    pub id: Uuid,      // Unique identifier for the user (UUID format).
+    pub name: String,  // Name of the user.
+    pub email: String, // Email address of the user.
+    #[serde(skip_serializing)]
+    pub password: String, // Hashed password of the user, never sent to clients.
+    pub role: UserRole, // Role of the user.
 }
 
 impl User {
     /// Checks if a given email already exists in the database.
-    pub async fn email_exists(client: &Client, email: &str) -> Result<bool, Error> {
+    pub async fn email_exists(client: &Client, email: &str) -> Result<bool, AppError> {
         let query = "SELECT COUNT(*) FROM users WHERE email = $1";
         let rows = client.query(query, &[&email]).await?;
 
-        if let Some(row) = rows.get(0) {
+        if let Some(row) = rows.first() {
             let count: i64 = row.get(0);
             Ok(count > 0)
         } else {
@@ -32,17 +66,20 @@ impl User {
         name: &str,
         email: &str,
         password: &str,
-    ) -> Result<User, Error> {
+    ) -> Result<User, AppError> {
         // Hash the user's password.
-        let hashed_password = hash(password, DEFAULT_COST).unwrap();
+        let hashed_password = hash(password, DEFAULT_COST)?;
         // Generate a new UUID for the user.
         let id = Uuid::new_v4();
 
+        // New users always start as customers.
+        let role = UserRole::Customer;
+
         // Execute the insert query.
         client
             .execute(
-                "INSERT INTO users (id, name, email, password) VALUES ($1, $2, $3, $4)",
-                &[&id, &name, &email, &hashed_password],
+                "INSERT INTO users (id, name, email, password, role) VALUES ($1, $2, $3, $4, $5)",
+                &[&id, &name, &email, &hashed_password, &role.as_str()],
             )
             .await?;
 
@@ -52,53 +89,71 @@ impl User {
             name: name.to_string(),
             email: email.to_string(),
             password: hashed_password,
+            role,
         })
     }
 
     /// Retrieves a user from the database by their ID.
-    pub async fn get_user(client: &Client, user_id: Uuid) -> Result<Option<User>, Error> {
+    pub async fn get_user(
+        client: &impl GenericClient,
+        user_id: Uuid,
+    ) -> Result<Option<User>, AppError> {
         // Query the database for the user with the given ID.
         let rows = client
             .query(
-                "SELECT id, name, email, password FROM users WHERE id = $1",
+                "SELECT id, name, email, password, role FROM users WHERE id = $1",
                 &[&user_id],
             )
             .await?;
 
-        if let Some(row) = rows.get(0) {
+        if let Some(row) = rows.first() {
             // Construct and return the user if found.
             Ok(Some(User {
                 id: row.get(0),
                 name: row.get(1),
                 email: row.get(2),
                 password: row.get(3),
+                role: UserRole::from(row.get::<_, &str>(4)),
             }))
         } else {
             Ok(None)
         }
     }
 
+    /// Returns the current role of a user, or `None` if the user no longer exists.
+    pub async fn current_role(
+        client: &impl GenericClient,
+        user_id: Uuid,
+    ) -> Result<Option<UserRole>, AppError> {
+        let row = client
+            .query_opt("SELECT role FROM users WHERE id = $1", &[&user_id])
+            .await?;
+
+        Ok(row.map(|row| UserRole::from(row.get::<_, &str>(0))))
+    }
+
     /// Authenticates a user by verifying their email and password.
     pub async fn authenticate_user(
         client: &Client,
         email: &str,
         password: &str,
-    ) -> Result<Option<User>, Box<dyn std::error::Error>> {
+    ) -> Result<Option<User>, AppError> {
         // Query the database for the user by email.
         let rows = client
             .query(
-                "SELECT id, name, email, password FROM users WHERE email = $1",
+                "SELECT id, name, email, password, role FROM users WHERE email = $1",
                 &[&email],
             )
             .await?;
 
-        if let Some(row) = rows.get(0) {
+        if let Some(row) = rows.first() {
             // Construct the user struct from the query result.
             let user = User {
                 id: row.get(0),
                 name: row.get(1),
                 email: row.get(2),
                 password: row.get(3),
+                role: UserRole::from(row.get::<_, &str>(4)),
             };
 
             // Verify the provided password against the stored hash.
@@ -111,7 +166,7 @@ impl User {
     }
 
     /// Deletes a user from the database by their ID.
-    pub async fn delete_user(client: &Client, user_id: Uuid) -> Result<bool, Error> {
+    pub async fn delete_user(client: &Client, user_id: Uuid) -> Result<bool, AppError> {
         // Execute the delete query for the given user ID.
         let result = client
             .execute("DELETE FROM users WHERE id = $1", &[&user_id])
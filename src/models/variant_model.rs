@@ -0,0 +1,454 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{types::Json, Row};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// A value of an option type, e.g. "M" for the size.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OptionValue {
+    pub id: Uuid,
+    pub value: String,
+    pub position: i32, // Order among the values of the option type, then by value
+}
+
+/// An option a product is sold in, e.g. its size or color, with the values it can take.
+#[derive(Serialize, Debug)]
+pub struct OptionType {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub name: String,
+    pub position: i32, // Order among the option types of the product, then by name
+    pub values: Vec<OptionValue>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The value a variant takes for one option type of its product.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VariantOption {
+    pub option_type_id: Uuid,
+    pub name: String, // Name of the option type, e.g. "Size"
+    pub option_value_id: Uuid,
+    pub value: String, // e.g. "M"
+}
+
+/// A combination of option values of a product, sold with its own SKU and stock.
+#[derive(Serialize, Debug)]
+pub struct Variant {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub sku: String,
+    pub price: Option<Decimal>, // Overrides the product's price when set
+    pub stock: i32,
+    pub options: Vec<VariantOption>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Attributes of an option type to create.
+pub struct NewOptionType<'a> {
+    pub name: &'a str,
+    pub position: i32,
+    pub values: &'a [String], // Positioned in the given order
+}
+
+/// Attributes of a variant to create.
+pub struct NewVariant<'a> {
+    pub sku: &'a str,
+    pub price: Option<Decimal>,
+    pub stock: i32,
+    pub option_value_ids: &'a [Uuid], // One value of each option type of the product
+}
+
+/// Changes applied by a partial variant update; `None` leaves a field untouched.
+#[derive(Default)]
+pub struct VariantChanges {
+    pub sku: Option<String>,
+    pub price: Option<Option<Decimal>>, // `Some(None)` sells the variant at the product's price
+    pub stock: Option<i32>,
+}
+
+// Columns selected for every query, in the order expected by the `from_row` functions.
+const OPTION_TYPE_COLUMNS: &str = "id, product_id, name, position, created_at, \
+     COALESCE((SELECT json_agg(json_build_object('id', id, 'value', value, \
+                                                 'position', position) ORDER BY position, value) \
+               FROM option_values WHERE option_type_id = option_types.id), '[]') AS values";
+const VARIANT_COLUMNS: &str = "id, product_id, sku, price, stock, created_at, updated_at, \
+     COALESCE((SELECT json_agg(json_build_object('option_type_id', option_types.id, \
+                                                 'name', option_types.name, \
+                                                 'option_value_id', option_values.id, \
+                                                 'value', option_values.value) \
+                               ORDER BY option_types.position, option_types.name) \
+               FROM variant_option_values \
+               INNER JOIN option_types \
+                   ON option_types.id = variant_option_values.option_type_id \
+               INNER JOIN option_values \
+                   ON option_values.id = variant_option_values.option_value_id \
+               WHERE variant_option_values.variant_id = product_variants.id), '[]') AS options";
+
+// Locks a product while its options or variants change, so option types cannot be added
+// while a variant is created. Returns false if the product does not exist.
+async fn lock_product(client: &impl GenericClient, product_id: Uuid) -> Result<bool, AppError> {
+    let row = client
+        .query_opt(
+            "SELECT id FROM products WHERE id = $1 FOR UPDATE",
+            &[&product_id],
+        )
+        .await?;
+
+    Ok(row.is_some())
+}
+
+impl OptionType {
+    fn from_row(row: &Row) -> OptionType {
+        OptionType {
+            id: row.get(0),
+            product_id: row.get(1),
+            name: row.get(2),
+            position: row.get(3),
+            created_at: row.get(4),
+            values: row.get::<_, Json<Vec<OptionValue>>>(5).0,
+        }
+    }
+
+    /// Adds an option type with its values to a product. Returns None if the product does not
+    /// exist. Products that already have variants cannot get new option types, as their
+    /// variants would have no value for them. Must run in a transaction.
+    pub async fn create(
+        client: &impl GenericClient,
+        product_id: Uuid,
+        option_type: &NewOptionType<'_>,
+    ) -> Result<Option<OptionType>, AppError> {
+        if !lock_product(client, product_id).await? {
+            return Ok(None);
+        }
+
+        let has_variants: bool = client
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM product_variants WHERE product_id = $1)",
+                &[&product_id],
+            )
+            .await?
+            .get(0);
+        if has_variants {
+            return Err(AppError::conflict(
+                "Option types cannot be added to a product that already has variants",
+            ));
+        }
+
+        let id = Uuid::new_v4();
+        client
+            .execute(
+                "INSERT INTO option_types (id, product_id, name, position) VALUES ($1, $2, $3, $4)",
+                &[&id, &product_id, &option_type.name, &option_type.position],
+            )
+            .await?;
+
+        for (position, value) in (0..).zip(option_type.values) {
+            Self::insert_value(client, id, value, position).await?;
+        }
+
+        Self::get_for_product(client, product_id, id).await
+    }
+
+    /// Lists the option types of a product, with their values.
+    pub async fn list_for_product(
+        client: &impl GenericClient,
+        product_id: Uuid,
+    ) -> Result<Vec<OptionType>, AppError> {
+        let query = format!(
+            "SELECT {} FROM option_types WHERE product_id = $1 ORDER BY position, name",
+            OPTION_TYPE_COLUMNS
+        );
+        let rows = client.query(&query, &[&product_id]).await?;
+
+        Ok(rows.iter().map(Self::from_row).collect())
+    }
+
+    /// Retrieves an option type of a product. Returns None if the product has no such option.
+    pub async fn get_for_product(
+        client: &impl GenericClient,
+        product_id: Uuid,
+        option_type_id: Uuid,
+    ) -> Result<Option<OptionType>, AppError> {
+        let query = format!(
+            "SELECT {} FROM option_types WHERE id = $1 AND product_id = $2",
+            OPTION_TYPE_COLUMNS
+        );
+        let row = client
+            .query_opt(&query, &[&option_type_id, &product_id])
+            .await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Adds a value to an option type of a product, after its existing values.
+    /// Returns None if the product has no such option type. Fails with a conflict if the
+    /// option type already has the value.
+    pub async fn add_value(
+        client: &impl GenericClient,
+        product_id: Uuid,
+        option_type_id: Uuid,
+        value: &str,
+    ) -> Result<Option<OptionType>, AppError> {
+        let row = client
+            .query_opt(
+                "SELECT COALESCE(MAX(option_values.position) + 1, 0)
+                 FROM option_types
+                 LEFT JOIN option_values ON option_values.option_type_id = option_types.id
+                 WHERE option_types.id = $1 AND option_types.product_id = $2
+                 GROUP BY option_types.id",
+                &[&option_type_id, &product_id],
+            )
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Self::insert_value(client, option_type_id, value, row.get(0)).await?;
+
+        Self::get_for_product(client, product_id, option_type_id).await
+    }
+
+    /// Deletes an option type of a product with its values. Returns false if the product has
+    /// no such option type. Option types used by variants cannot be deleted. Must run in a
+    /// transaction.
+    pub async fn delete(
+        client: &impl GenericClient,
+        product_id: Uuid,
+        option_type_id: Uuid,
+    ) -> Result<bool, AppError> {
+        if !lock_product(client, product_id).await? {
+            return Ok(false);
+        }
+
+        let in_use: bool = client
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM variant_option_values WHERE option_type_id = $1)",
+                &[&option_type_id],
+            )
+            .await?
+            .get(0);
+        if in_use {
+            return Err(AppError::conflict(
+                "Delete the variants of the product before its option types",
+            ));
+        }
+
+        let deleted = client
+            .execute(
+                "DELETE FROM option_types WHERE id = $1 AND product_id = $2",
+                &[&option_type_id, &product_id],
+            )
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    // Inserts a value of an option type.
+    async fn insert_value(
+        client: &impl GenericClient,
+        option_type_id: Uuid,
+        value: &str,
+        position: i32,
+    ) -> Result<(), AppError> {
+        client
+            .execute(
+                "INSERT INTO option_values (id, option_type_id, value, position)
+                 VALUES ($1, $2, $3, $4)",
+                &[&Uuid::new_v4(), &option_type_id, &value, &position],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl Variant {
+    fn from_row(row: &Row) -> Variant {
+        Variant {
+            id: row.get(0),
+            product_id: row.get(1),
+            sku: row.get(2),
+            price: row.get(3),
+            stock: row.get(4),
+            created_at: row.get(5),
+            updated_at: row.get(6),
+            options: row.get::<_, Json<Vec<VariantOption>>>(7).0,
+        }
+    }
+
+    /// Creates a variant of a product. Returns None if the product does not exist.
+    ///
+    /// The variant must take exactly one value of each option type of the product, and no
+    /// other variant may have the same values. Fails with a conflict if the SKU is taken.
+    /// Must run in a transaction.
+    pub async fn create(
+        client: &impl GenericClient,
+        product_id: Uuid,
+        variant: &NewVariant<'_>,
+    ) -> Result<Option<Variant>, AppError> {
+        if !lock_product(client, product_id).await? {
+            return Ok(None);
+        }
+
+        let option_types = OptionType::list_for_product(client, product_id).await?;
+
+        let mut options = Vec::with_capacity(option_types.len());
+        for option_type in &option_types {
+            let mut values = option_type
+                .values
+                .iter()
+                .filter(|value| variant.option_value_ids.contains(&value.id));
+
+            match (values.next(), values.next()) {
+                (Some(value), None) => options.push((option_type.id, value.id)),
+                _ => {
+                    return Err(AppError::validation(format!(
+                        "A variant takes exactly one value of each option type, \"{}\" included",
+                        option_type.name
+                    )))
+                }
+            }
+        }
+        if options.len() != variant.option_value_ids.len() {
+            return Err(AppError::validation(
+                "option_value_ids must only hold values of the product's option types",
+            ));
+        }
+
+        let mut value_ids: Vec<Uuid> = options.iter().map(|(_, value_id)| *value_id).collect();
+        value_ids.sort();
+        let taken: bool = client
+            .query_one(
+                "SELECT EXISTS (
+                     SELECT 1 FROM product_variants
+                     WHERE product_id = $1
+                       AND ARRAY(SELECT option_value_id FROM variant_option_values
+                                 WHERE variant_id = product_variants.id
+                                 ORDER BY option_value_id) = $2
+                 )",
+                &[&product_id, &value_ids],
+            )
+            .await?
+            .get(0);
+        if taken {
+            return Err(AppError::conflict(
+                "The product already has a variant with these options",
+            ));
+        }
+
+        let id = Uuid::new_v4();
+        client
+            .execute(
+                "INSERT INTO product_variants (id, product_id, sku, price, stock)
+                 VALUES ($1, $2, $3, $4, $5)",
+                &[
+                    &id,
+                    &product_id,
+                    &variant.sku,
+                    &variant.price,
+                    &variant.stock,
+                ],
+            )
+            .await?;
+
+        for (option_type_id, option_value_id) in &options {
+            client
+                .execute(
+                    "INSERT INTO variant_option_values (variant_id, option_type_id, option_value_id)
+                     VALUES ($1, $2, $3)",
+                    &[&id, option_type_id, option_value_id],
+                )
+                .await?;
+        }
+
+        Self::get_for_product(client, product_id, id).await
+    }
+
+    /// Lists the variants of a product, oldest first.
+    pub async fn list_for_product(
+        client: &impl GenericClient,
+        product_id: Uuid,
+    ) -> Result<Vec<Variant>, AppError> {
+        let query = format!(
+            "SELECT {} FROM product_variants WHERE product_id = $1 ORDER BY created_at, id",
+            VARIANT_COLUMNS
+        );
+        let rows = client.query(&query, &[&product_id]).await?;
+
+        Ok(rows.iter().map(Self::from_row).collect())
+    }
+
+    /// Retrieves a variant of a product. Returns None if the product has no such variant.
+    pub async fn get_for_product(
+        client: &impl GenericClient,
+        product_id: Uuid,
+        variant_id: Uuid,
+    ) -> Result<Option<Variant>, AppError> {
+        let query = format!(
+            "SELECT {} FROM product_variants WHERE id = $1 AND product_id = $2",
+            VARIANT_COLUMNS
+        );
+        let row = client
+            .query_opt(&query, &[&variant_id, &product_id])
+            .await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Applies a partial update to a variant of a product. Returns None if the product has no
+    /// such variant. Fails with a conflict if the SKU is taken.
+    pub async fn update(
+        client: &impl GenericClient,
+        product_id: Uuid,
+        variant_id: Uuid,
+        changes: &VariantChanges,
+    ) -> Result<Option<Variant>, AppError> {
+        let query = format!(
+            "UPDATE product_variants SET
+                sku = COALESCE($3, sku),
+                price = CASE WHEN $4 THEN $5 ELSE price END,
+                stock = COALESCE($6, stock),
+                updated_at = now()
+             WHERE id = $1 AND product_id = $2
+             RETURNING {}",
+            VARIANT_COLUMNS
+        );
+        let row = client
+            .query_opt(
+                &query,
+                &[
+                    &variant_id,
+                    &product_id,
+                    &changes.sku,
+                    &changes.price.is_some(),
+                    &changes.price.flatten(),
+                    &changes.stock,
+                ],
+            )
+            .await?;
+
+        Ok(row.as_ref().map(Self::from_row))
+    }
+
+    /// Deletes a variant of a product. Returns false if the product has no such variant.
+    /// Variants that were ordered cannot be deleted.
+    pub async fn delete(
+        client: &impl GenericClient,
+        product_id: Uuid,
+        variant_id: Uuid,
+    ) -> Result<bool, AppError> {
+        let deleted = client
+            .execute(
+                "DELETE FROM product_variants WHERE id = $1 AND product_id = $2",
+                &[&variant_id, &product_id],
+            )
+            .await?;
+
+        Ok(deleted > 0)
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+
+use crate::errors::AppError;
+
+/// Number of items returned per page when the client does not ask for a limit.
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+
+/// Largest page a client may request.
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// Direction in which a listing is sorted.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    /// Returns the SQL keyword for the direction.
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+/// Envelope returned by every paginated listing.
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64, // Number of items matching the query, across all pages
+    pub limit: i64,
+    pub offset: i64,
+    pub next_cursor: Option<i64>, // Offset of the next page, absent on the last page
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: i64, limit: i64, offset: i64) -> Self {
+        let next_offset = offset + items.len() as i64;
+        let next_cursor = (next_offset < total && !items.is_empty()).then_some(next_offset);
+
+        Page {
+            items,
+            total,
+            limit,
+            offset,
+            next_cursor,
+        }
+    }
+}
+
+/// Validates the requested limit and offset, applying the defaults.
+pub fn page_bounds(limit: Option<i64>, offset: Option<i64>) -> Result<(i64, i64), AppError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = offset.unwrap_or(0);
+
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::validation(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+
+    if offset < 0 {
+        return Err(AppError::validation("offset must not be negative"));
+    }
+
+    Ok((limit, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_bounds_applies_defaults() {
+        assert_eq!(page_bounds(None, None).unwrap(), (DEFAULT_PAGE_SIZE, 0));
+    }
+
+    #[test]
+    fn page_bounds_accepts_limits_in_range() {
+        assert_eq!(page_bounds(Some(1), Some(0)).unwrap(), (1, 0));
+        assert_eq!(
+            page_bounds(Some(MAX_PAGE_SIZE), Some(40)).unwrap(),
+            (MAX_PAGE_SIZE, 40)
+        );
+    }
+
+    #[test]
+    fn page_bounds_rejects_limits_out_of_range() {
+        assert!(page_bounds(Some(0), None).is_err());
+        assert!(page_bounds(Some(-1), None).is_err());
+        assert!(page_bounds(Some(MAX_PAGE_SIZE + 1), None).is_err());
+    }
+
+    #[test]
+    fn page_bounds_rejects_negative_offsets() {
+        assert!(page_bounds(None, Some(-1)).is_err());
+    }
+
+    #[test]
+    fn next_cursor_points_to_the_next_page() {
+        let page = Page::new(vec![1, 2], 5, 2, 0);
+        assert_eq!(page.next_cursor, Some(2));
+
+        let page = Page::new(vec![3, 4], 5, 2, 2);
+        assert_eq!(page.next_cursor, Some(4));
+    }
+
+    #[test]
+    fn next_cursor_is_absent_on_the_last_page() {
+        let page = Page::new(vec![5], 5, 2, 4);
+        assert_eq!(page.next_cursor, None);
+
+        // A last page that is exactly full
+        let page = Page::new(vec![3, 4], 4, 2, 2);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn next_cursor_is_absent_past_the_end() {
+        let page: Page<i32> = Page::new(Vec::new(), 5, 2, 10);
+        assert_eq!(page.next_cursor, None);
+
+        let page: Page<i32> = Page::new(Vec::new(), 0, 20, 0);
+        assert_eq!(page.next_cursor, None);
+    }
+}
@@ -0,0 +1,173 @@
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use rust_decimal::Decimal;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Failure reported by a payment provider.
+#[derive(Debug, Error)]
+pub enum GatewayError {
+    #[error("The payment was declined: {0}")]
+    Declined(String),
+
+    #[error("The payment provider did not respond in time")]
+    Timeout,
+
+    #[error("The payment provider failed: {0}")]
+    Provider(String),
+}
+
+/// Data sent to the provider to reserve funds for a payment.
+pub struct AuthorizationRequest<'a> {
+    pub payment_id: Uuid,
+    pub amount: Decimal,
+    pub currency: &'a str,
+    pub payment_method: &'a str,
+}
+
+/// A payment service provider.
+///
+/// Authorizing reserves the amount and returns the provider's reference for the payment,
+/// which identifies it in every later call. Funds only move on `capture`.
+pub trait PaymentGateway: Send + Sync {
+    /// Name stored in `payments.provider`.
+    fn name(&self) -> &'static str;
+
+    /// Reserves the amount of a payment and returns the provider's reference for it.
+    /// Authorizing the same `payment_id` again returns the same reference instead of reserving
+    /// the funds twice, so calls whose answer was lost can be repeated.
+    fn authorize<'a>(
+        &'a self,
+        request: AuthorizationRequest<'a>,
+    ) -> BoxFuture<'a, Result<String, GatewayError>>;
+
+    /// Collects the reserved funds. Capturing an authorization that was already captured
+    /// succeeds without collecting anything again.
+    fn capture<'a>(
+        &'a self,
+        reference: &'a str,
+        amount: Decimal,
+    ) -> BoxFuture<'a, Result<(), GatewayError>>;
+
+    fn void<'a>(&'a self, reference: &'a str) -> BoxFuture<'a, Result<(), GatewayError>>;
+
+    /// Returns `amount` of a captured payment and the provider's reference for the refund.
+    /// `refund_id` identifies the refund on our side so retries are not paid out twice.
+    fn refund<'a>(
+        &'a self,
+        reference: &'a str,
+        refund_id: Uuid,
+        amount: Decimal,
+    ) -> BoxFuture<'a, Result<String, GatewayError>>;
+}
+
+/// Returns how long a provider call may take, configured with PAYMENT_GATEWAY_TIMEOUT_SECS
+/// (default 10).
+pub fn gateway_timeout() -> Duration {
+    let secs = env::var("PAYMENT_GATEWAY_TIMEOUT_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(10);
+
+    Duration::from_secs(secs)
+}
+
+/// Runs a provider call, giving up with `GatewayError::Timeout` once `gateway_timeout` elapses.
+pub async fn with_timeout<T>(
+    call: BoxFuture<'_, Result<T, GatewayError>>,
+) -> Result<T, GatewayError> {
+    tokio::time::timeout(gateway_timeout(), call)
+        .await
+        .unwrap_or(Err(GatewayError::Timeout))
+}
+
+/// Builds the gateway selected with PAYMENT_PROVIDER (default `mock`).
+pub fn from_env() -> Result<Arc<dyn PaymentGateway>, String> {
+    let provider = env::var("PAYMENT_PROVIDER").unwrap_or_else(|_| "mock".to_string());
+
+    match provider.as_str() {
+        "mock" => Ok(Arc::new(MockGateway::from_env()?)),
+        other => Err(format!("Unknown payment provider: {}", other)),
+    }
+}
+
+/// How the mock provider answers every call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockOutcome {
+    Succeed,
+    Decline,
+    Timeout, // Never answers, so the call runs into the gateway timeout
+}
+
+/// In-process provider for tests and local development.
+/// Its answers only depend on the configured outcome, and references are derived from the
+/// payment ID, so runs are reproducible.
+pub struct MockGateway {
+    outcome: MockOutcome,
+}
+
+impl MockGateway {
+    pub fn new(outcome: MockOutcome) -> Self {
+        MockGateway { outcome }
+    }
+
+    /// Reads the outcome from MOCK_GATEWAY_OUTCOME: `succeed` (default), `decline` or `timeout`.
+    pub fn from_env() -> Result<Self, String> {
+        let outcome = match env::var("MOCK_GATEWAY_OUTCOME").as_deref() {
+            Err(_) | Ok("succeed") => MockOutcome::Succeed,
+            Ok("decline") => MockOutcome::Decline,
+            Ok("timeout") => MockOutcome::Timeout,
+            Ok(other) => return Err(format!("Unknown MOCK_GATEWAY_OUTCOME: {}", other)),
+        };
+
+        Ok(MockGateway::new(outcome))
+    }
+
+    // Answers a call according to the configured outcome.
+    async fn respond<T>(&self, success: T) -> Result<T, GatewayError> {
+        match self.outcome {
+            MockOutcome::Succeed => Ok(success),
+            MockOutcome::Decline => {
+                Err(GatewayError::Declined("mock provider declined".to_string()))
+            }
+            MockOutcome::Timeout => futures::future::pending().await,
+        }
+    }
+}
+
+impl PaymentGateway for MockGateway {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn authorize<'a>(
+        &'a self,
+        request: AuthorizationRequest<'a>,
+    ) -> BoxFuture<'a, Result<String, GatewayError>> {
+        Box::pin(self.respond(format!("mock_{}", request.payment_id.simple())))
+    }
+
+    fn capture<'a>(
+        &'a self,
+        _reference: &'a str,
+        _amount: Decimal,
+    ) -> BoxFuture<'a, Result<(), GatewayError>> {
+        Box::pin(self.respond(()))
+    }
+
+    fn void<'a>(&'a self, _reference: &'a str) -> BoxFuture<'a, Result<(), GatewayError>> {
+        Box::pin(self.respond(()))
+    }
+
+    fn refund<'a>(
+        &'a self,
+        _reference: &'a str,
+        refund_id: Uuid,
+        _amount: Decimal,
+    ) -> BoxFuture<'a, Result<String, GatewayError>> {
+        Box::pin(self.respond(format!("mock_refund_{}", refund_id.simple())))
+    }
+}
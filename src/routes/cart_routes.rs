@@ -0,0 +1,20 @@
+use actix_web::web;
+
+use crate::controllers::cart_controller::{
+    add_cart_item, checkout, clear_cart, get_cart, remove_cart_item, update_cart_item,
+};
+
+pub fn cart_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/cart")
+            .route(web::get().to(get_cart))
+            .route(web::delete().to(clear_cart)),
+    )
+    .service(web::resource("/cart/items").route(web::post().to(add_cart_item)))
+    .service(
+        web::resource("/cart/items/{item_id}")
+            .route(web::patch().to(update_cart_item))
+            .route(web::delete().to(remove_cart_item)),
+    )
+    .service(web::resource("/cart/checkout").route(web::post().to(checkout)));
+}
@@ -0,0 +1,21 @@
+use actix_web::web;
+
+use crate::controllers::category_controller::{
+    create_category, delete_category, list_categories, list_category_products, update_category,
+};
+
+pub fn category_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/categories")
+            .route(web::get().to(list_categories))
+            .route(web::post().to(create_category)),
+    )
+    .service(
+        web::resource("/categories/{category_id}")
+            .route(web::patch().to(update_category))
+            .route(web::delete().to(delete_category)),
+    )
+    .service(
+        web::resource("/categories/{slug}/products").route(web::get().to(list_category_products)),
+    );
+}
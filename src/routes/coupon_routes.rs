@@ -0,0 +1,17 @@
+use actix_web::web;
+
+use crate::controllers::coupon_controller::{
+    create_coupon, deactivate_coupon, get_coupon, list_coupons,
+};
+
+pub fn coupon_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/coupons")
+            .route(web::post().to(create_coupon))
+            .route(web::get().to(list_coupons)),
+    )
+    .service(web::resource("/coupons/{coupon_id}").route(web::get().to(get_coupon)))
+    .service(
+        web::resource("/coupons/{coupon_id}/deactivate").route(web::post().to(deactivate_coupon)),
+    );
+}
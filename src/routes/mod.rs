@@ -1,6 +1,13 @@
+pub mod cart_routes;
+pub mod category_routes;
+pub mod coupon_routes;
+pub mod order_items_routes;
+pub mod order_routes;
+pub mod payment_routes;
+pub mod product_routes;
+#[allow(clippy::module_inception)]
 pub mod routes;
+pub mod shipment_routes;
+pub mod shipping_routes;
+pub mod tax_rate_routes;
 pub mod user_routes;
-pub mod product_routes;
-pub mod order_routes;
-pub mod order_items_routes;
-pub mod payment_routes;
\ No newline at end of file
@@ -1,7 +1,7 @@
 use actix_web::web;
 
 use crate::controllers::order_items_controller::{
-    create_order_item, get_order_items, delete_order_item,
+    create_order_item, delete_order_item, get_order_items,
 };
 
 pub fn order_item_routes(cfg: &mut web::ServiceConfig) {
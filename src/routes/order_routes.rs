@@ -1,12 +1,22 @@
 use actix_web::web;
 
-use crate::controllers::order_controller::{create_order, delete_order, get_order};
+use crate::controllers::order_controller::{
+    cancel_order, create_order, delete_order, get_order, get_order_history, list_orders,
+    update_order_status,
+};
 
 pub fn order_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/orders")
             .route(web::post().to(create_order))
-            .route(web::get().to(get_order)),
+            .route(web::get().to(list_orders)),
     )
-    .service(web::resource("/orders/{order_id}").route(web::delete().to(delete_order)));
+    .service(
+        web::resource("/orders/{order_id}")
+            .route(web::get().to(get_order))
+            .route(web::delete().to(delete_order)),
+    )
+    .service(web::resource("/orders/{order_id}/status").route(web::post().to(update_order_status)))
+    .service(web::resource("/orders/{order_id}/cancel").route(web::post().to(cancel_order)))
+    .service(web::resource("/orders/{order_id}/history").route(web::get().to(get_order_history)));
 }
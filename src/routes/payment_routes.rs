@@ -1,17 +1,41 @@
 use actix_web::web;
 
 use crate::controllers::payment_controller::{
-    create_payment, get_payment, update_payment
+    authorize_payment, capture_payment, create_payment, create_refund, get_payment, get_refunds,
+    retry_refund, update_payment, void_payment,
 };
+use crate::controllers::webhook_controller::{list_events, receive_webhook, replay_event};
 
 pub fn payment_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::resource("/payments/orders/{order_id}")
-            .route(web::post().to(create_payment))
-            .route(web::get().to(get_payment)),
-    )
-    .service(
-        web::resource("/payments/{payment_id}")
-            .route(web::patch().to(update_payment)),
-    );
+    cfg.service(web::resource("/payments/webhooks/events").route(web::get().to(list_events)))
+        .service(
+            web::resource("/payments/webhooks/events/{event_id}/replay")
+                .route(web::post().to(replay_event)),
+        )
+        .service(
+            web::resource("/payments/webhooks/{provider}").route(web::post().to(receive_webhook)),
+        )
+        .service(
+            web::resource("/payments/orders/{order_id}")
+                .route(web::post().to(create_payment))
+                .route(web::get().to(get_payment)),
+        )
+        .service(web::resource("/payments/{payment_id}").route(web::patch().to(update_payment)))
+        .service(
+            web::resource("/payments/{payment_id}/authorize")
+                .route(web::post().to(authorize_payment)),
+        )
+        .service(
+            web::resource("/payments/{payment_id}/capture").route(web::post().to(capture_payment)),
+        )
+        .service(web::resource("/payments/{payment_id}/void").route(web::post().to(void_payment)))
+        .service(
+            web::resource("/payments/{payment_id}/refunds")
+                .route(web::post().to(create_refund))
+                .route(web::get().to(get_refunds)),
+        )
+        .service(
+            web::resource("/payments/{payment_id}/refunds/{refund_id}/retry")
+                .route(web::post().to(retry_refund)),
+        );
 }
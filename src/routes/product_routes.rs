@@ -1,15 +1,47 @@
 use actix_web::web;
 
-use crate::controllers::product_controller::{create_product, delete_product, get_product};
-
-
+use crate::controllers::category_controller::set_product_categories;
+use crate::controllers::product_controller::{
+    create_product, delete_product, get_product, list_products, search_products, update_product,
+};
+use crate::controllers::variant_controller::{
+    add_option_value, create_option_type, create_variant, delete_option_type, delete_variant,
+    list_option_types, list_variants, update_variant,
+};
 
 pub fn product_router(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/products")
+            .route("", web::get().to(list_products))
             .route("/create", web::post().to(create_product))
-            /* .route("/users/{user_id}", web::put().to(update_user))  */
-            .route("/users/{product_id}", web::delete().to(delete_product)) // Excluir usuário
-            .route("/{product_id}", web::get().to(get_product)),
+            .route("/search", web::get().to(search_products))
+            .route("/{product_id}", web::get().to(get_product))
+            .route("/{product_id}", web::put().to(update_product))
+            .route("/{product_id}", web::patch().to(update_product))
+            .route("/{product_id}", web::delete().to(delete_product)) // Delete a product
+            .route(
+                "/{product_id}/categories",
+                web::put().to(set_product_categories),
+            )
+            .route("/{product_id}/options", web::get().to(list_option_types))
+            .route("/{product_id}/options", web::post().to(create_option_type))
+            .route(
+                "/{product_id}/options/{option_type_id}",
+                web::delete().to(delete_option_type),
+            )
+            .route(
+                "/{product_id}/options/{option_type_id}/values",
+                web::post().to(add_option_value),
+            )
+            .route("/{product_id}/variants", web::get().to(list_variants))
+            .route("/{product_id}/variants", web::post().to(create_variant))
+            .route(
+                "/{product_id}/variants/{variant_id}",
+                web::patch().to(update_variant),
+            )
+            .route(
+                "/{product_id}/variants/{variant_id}",
+                web::delete().to(delete_variant),
+            ),
     );
-}
\ No newline at end of file
+}
@@ -1,20 +1,28 @@
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{web, HttpResponse, Responder};
 
-use super::{order_items_routes, order_routes::{self, order_routes}, payment_routes::{self, payment_routes}, product_routes, user_routes};
+use super::{
+    cart_routes, category_routes, coupon_routes, order_items_routes, order_routes, payment_routes,
+    product_routes, shipment_routes, shipping_routes, tax_rate_routes, user_routes,
+};
 
 // Health check endpoint to verify if the server is running
 async fn health_check() -> impl Responder {
     HttpResponse::Ok().body("Server is running!")
 }
 
-
 // Function to configure the routes for the application
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     // Configures the health check route and user routes
     cfg.route("/", web::get().to(health_check))
-    .configure(user_routes::user_router) // Configures the user-related routes
-    .configure(product_routes::product_router) // Configures the user-related routes
-    .configure(order_routes::order_routes)
-    .configure(order_items_routes::order_item_routes)
-    .configure(payment_routes::payment_routes);
-}
\ No newline at end of file
+        .configure(user_routes::user_router) // Configures the user-related routes
+        .configure(product_routes::product_router) // Configures the product-related routes
+        .configure(order_routes::order_routes)
+        .configure(order_items_routes::order_item_routes)
+        .configure(payment_routes::payment_routes)
+        .configure(cart_routes::cart_routes)
+        .configure(coupon_routes::coupon_routes)
+        .configure(tax_rate_routes::tax_rate_routes)
+        .configure(shipping_routes::shipping_routes)
+        .configure(shipment_routes::shipment_routes)
+        .configure(category_routes::category_routes);
+}
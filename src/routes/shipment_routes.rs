@@ -0,0 +1,12 @@
+use actix_web::web;
+
+use crate::controllers::shipment_controller::{create_shipment, list_shipments, update_shipment};
+
+pub fn shipment_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/orders/{order_id}/shipments")
+            .route(web::post().to(create_shipment))
+            .route(web::get().to(list_shipments)),
+    )
+    .service(web::resource("/shipments/{shipment_id}").route(web::patch().to(update_shipment)));
+}
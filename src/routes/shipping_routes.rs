@@ -0,0 +1,35 @@
+use actix_web::web;
+
+use crate::controllers::shipping_controller::{
+    create_shipping_method, create_shipping_rate, create_shipping_zone, deactivate_shipping_method,
+    delete_shipping_rate, delete_shipping_zone, list_shipping_methods, list_shipping_rates,
+    list_shipping_zones,
+};
+
+pub fn shipping_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/shipping/methods")
+            .route(web::get().to(list_shipping_methods))
+            .route(web::post().to(create_shipping_method)),
+    )
+    .service(
+        web::resource("/shipping/methods/{method_id}/deactivate")
+            .route(web::post().to(deactivate_shipping_method)),
+    )
+    .service(
+        web::resource("/shipping/zones")
+            .route(web::get().to(list_shipping_zones))
+            .route(web::post().to(create_shipping_zone)),
+    )
+    .service(
+        web::resource("/shipping/zones/{zone_id}").route(web::delete().to(delete_shipping_zone)),
+    )
+    .service(
+        web::resource("/shipping/zones/{zone_id}/rates")
+            .route(web::get().to(list_shipping_rates))
+            .route(web::post().to(create_shipping_rate)),
+    )
+    .service(
+        web::resource("/shipping/rates/{rate_id}").route(web::delete().to(delete_shipping_rate)),
+    );
+}
@@ -0,0 +1,12 @@
+use actix_web::web;
+
+use crate::controllers::tax_rate_controller::{create_tax_rate, delete_tax_rate, list_tax_rates};
+
+pub fn tax_rate_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/tax_rates")
+            .route(web::post().to(create_tax_rate))
+            .route(web::get().to(list_tax_rates)),
+    )
+    .service(web::resource("/tax_rates/{rate_id}").route(web::delete().to(delete_tax_rate)));
+}
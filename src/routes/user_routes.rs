@@ -1,13 +1,31 @@
 use actix_web::web;
 
-use crate::controllers::user_controller::{create_user, delete_user, get_user, login_user};
+use crate::controllers::address_controller::{
+    create_address, delete_address, get_address, list_addresses, update_address,
+};
+use crate::controllers::user_controller::{
+    create_user, delete_user, get_user, login_user, logout_user, refresh_token,
+};
 
 pub fn user_router(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/users")
             .route("/create", web::post().to(create_user))
             .route("/login", web::post().to(login_user))
-            /* .route("/users/{user_id}", web::put().to(update_user)) */ 
+            .route("/refresh", web::post().to(refresh_token))
+            .route("/logout", web::post().to(logout_user))
+            .route("/me/addresses", web::get().to(list_addresses))
+            .route("/me/addresses", web::post().to(create_address))
+            .route("/me/addresses/{address_id}", web::get().to(get_address))
+            .route(
+                "/me/addresses/{address_id}",
+                web::patch().to(update_address),
+            )
+            .route(
+                "/me/addresses/{address_id}",
+                web::delete().to(delete_address),
+            )
+            /* .route("/users/{user_id}", web::put().to(update_user)) */
             .route("/users/{user_id}", web::delete().to(delete_user)) // Excluir usuário
             .route("/{user_id}", web::get().to(get_user)),
     );
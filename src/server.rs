@@ -1,23 +1,20 @@
 use actix_web::{web, App, HttpServer};
+use deadpool_postgres::Pool;
 use dotenv::dotenv;
 use std::env;
 use std::sync::Arc;
-use tokio_postgres::Client;
 
+use crate::errors::AppError;
+use crate::payment_gateway::PaymentGateway;
 use crate::routes::routes::configure_routes;
 
-pub struct AppState {
-    pub db_client: Arc<Client>,
+// Reports malformed request bodies, paths and query strings with the standard error body
+fn extractor_error(err: impl std::fmt::Display) -> actix_web::Error {
+    AppError::validation(err.to_string()).into()
 }
 
-impl AppState {
-    // Altera para aceitar um Arc<Client> e não apenas um Client
-    pub fn new(client: Arc<Client>) -> Self {
-        AppState { db_client: client }
-    }
-}
 // Function to start the server and bind it to a host and port
-pub async fn run_server(client: Arc<Client>) -> std::io::Result<()> {
+pub async fn run_server(pool: Pool, gateway: Arc<dyn PaymentGateway>) -> std::io::Result<()> {
     // Loads environment variables from the .env file
     dotenv().ok();
 
@@ -31,8 +28,15 @@ pub async fn run_server(client: Arc<Client>) -> std::io::Result<()> {
     // Creates and runs the Actix web server
     HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(client.clone())) // Passa o AppState para as rotas// Passes the database client as application data
+            .app_data(web::Data::new(pool.clone())) // Passes the connection pool as application data
+            .app_data(web::Data::from(gateway.clone())) // Payment provider used by the payment handlers
+            .app_data(web::JsonConfig::default().error_handler(|err, _| extractor_error(err)))
+            .app_data(web::PathConfig::default().error_handler(|err, _| extractor_error(err)))
+            .app_data(web::QueryConfig::default().error_handler(|err, _| extractor_error(err)))
             .configure(configure_routes) // Configures the routes (health check and user routes)
+            .default_service(web::to(|| async {
+                Err::<actix_web::HttpResponse, _>(AppError::NotFound("Route not found".to_string()))
+            }))
     })
     .bind((host.as_str(), port.parse::<u16>().unwrap()))? // Binds the server to the specified host and port
     .run() // Runs the server
@@ -0,0 +1,98 @@
+use std::env;
+
+use rust_decimal::Decimal;
+
+/// Returns the cubic centimetres counted as one kilogram when a parcel is bulkier than it is
+/// heavy, configured with SHIPPING_VOLUMETRIC_DIVISOR (default 5000, the usual carrier value).
+pub fn volumetric_divisor() -> Decimal {
+    env::var("SHIPPING_VOLUMETRIC_DIVISOR")
+        .ok()
+        .and_then(|divisor| divisor.parse::<Decimal>().ok())
+        .filter(|divisor| *divisor > Decimal::ZERO)
+        .unwrap_or_else(|| Decimal::from(5000))
+}
+
+/// Returns the weight a carrier charges for one package: its actual weight, or its volumetric
+/// weight if that is higher.
+pub fn billable_weight(
+    weight_kg: Decimal,
+    length_cm: Decimal,
+    width_cm: Decimal,
+    height_cm: Decimal,
+) -> Decimal {
+    let volumetric = length_cm * width_cm * height_cm / volumetric_divisor();
+    weight_kg.max(volumetric).round_dp(3)
+}
+
+/// Returns the cost of shipping at `price`, free once `merchandise` (the order subtotal after
+/// discounts) reaches the `free_over` threshold.
+pub fn shipping_cost(price: Decimal, free_over: Option<Decimal>, merchandise: Decimal) -> Decimal {
+    if free_over.is_some_and(|threshold| merchandise >= threshold) {
+        return Decimal::ZERO;
+    }
+
+    price
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    // The tests rely on the default divisor of 5000 cm³ per kilogram.
+
+    #[test]
+    fn billable_weight_is_the_actual_weight_of_dense_parcels() {
+        // 10 x 10 x 10 cm weighs 0.2 kg volumetrically
+        assert_eq!(
+            billable_weight(dec!(2), dec!(10), dec!(10), dec!(10)),
+            dec!(2.000)
+        );
+    }
+
+    #[test]
+    fn billable_weight_is_the_volumetric_weight_of_bulky_parcels() {
+        // 50 x 40 x 30 cm weighs 12 kg volumetrically
+        assert_eq!(
+            billable_weight(dec!(1), dec!(50), dec!(40), dec!(30)),
+            dec!(12.000)
+        );
+        // 11 x 11 x 11 cm weighs 0.2662 kg, rounded to grams
+        assert_eq!(
+            billable_weight(dec!(0.1), dec!(11), dec!(11), dec!(11)),
+            dec!(0.266)
+        );
+    }
+
+    #[test]
+    fn billable_weight_without_dimensions_is_the_actual_weight() {
+        assert_eq!(
+            billable_weight(dec!(1.5), dec!(0), dec!(40), dec!(30)),
+            dec!(1.500)
+        );
+        assert_eq!(
+            billable_weight(dec!(1.5), dec!(0), dec!(0), dec!(0)),
+            dec!(1.500)
+        );
+        assert_eq!(billable_weight(dec!(0), dec!(0), dec!(0), dec!(0)), dec!(0));
+    }
+
+    #[test]
+    fn shipping_is_free_from_the_threshold() {
+        assert_eq!(
+            shipping_cost(dec!(4.90), Some(dec!(50)), dec!(49.99)),
+            dec!(4.90)
+        );
+        assert_eq!(shipping_cost(dec!(4.90), Some(dec!(50)), dec!(50)), dec!(0));
+        assert_eq!(
+            shipping_cost(dec!(4.90), Some(dec!(50)), dec!(120)),
+            dec!(0)
+        );
+    }
+
+    #[test]
+    fn shipping_without_threshold_is_never_free() {
+        assert_eq!(shipping_cost(dec!(4.90), None, dec!(1000)), dec!(4.90));
+        assert_eq!(shipping_cost(dec!(0), None, dec!(10)), dec!(0));
+    }
+}
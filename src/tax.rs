@@ -0,0 +1,166 @@
+use std::env;
+
+use rust_decimal::{Decimal, RoundingStrategy};
+
+/// Rounds a monetary amount to cents with banker's rounding (half to even), so rounding
+/// errors do not drift in one direction across many lines.
+pub fn round_money(amount: Decimal) -> Decimal {
+    amount.round_dp_with_strategy(2, RoundingStrategy::MidpointNearestEven)
+}
+
+/// Returns the tax of a line at `rate` percent.
+/// With tax-inclusive pricing the tax is the part of `amount` that is tax; otherwise it is
+/// owed on top of `amount`.
+pub fn line_tax(amount: Decimal, rate: Decimal, prices_include_tax: bool) -> Decimal {
+    let rate = rate / Decimal::ONE_HUNDRED;
+
+    let tax = if prices_include_tax {
+        amount - amount / (Decimal::ONE + rate)
+    } else {
+        amount * rate
+    };
+
+    round_money(tax)
+}
+
+/// Splits `total` across lines in proportion to their weights, e.g. an order discount across
+/// the lines it applies to. The shares add up to `total` exactly: the rounding remainder goes
+/// to the last line with a weight.
+pub fn allocate(total: Decimal, weights: &[Decimal]) -> Vec<Decimal> {
+    let weight_sum: Decimal = weights.iter().sum();
+    if weight_sum.is_zero() {
+        return vec![Decimal::ZERO; weights.len()];
+    }
+
+    let mut shares: Vec<Decimal> = weights
+        .iter()
+        .map(|weight| round_money(total * weight / weight_sum))
+        .collect();
+
+    if let Some(last) = weights.iter().rposition(|weight| !weight.is_zero()) {
+        let allocated: Decimal = shares.iter().sum();
+        shares[last] += total - allocated;
+    }
+
+    shares
+}
+
+/// Returns true if `region` is an ISO 3166-1 country ("DE") or ISO 3166-2 subdivision ("US-CA")
+/// code, the format tax rates are keyed by.
+pub fn is_valid_region(region: &str) -> bool {
+    let (country, subdivision) = match region.split_once('-') {
+        Some((country, subdivision)) => (country, Some(subdivision)),
+        None => (region, None),
+    };
+
+    country.len() == 2
+        && country.chars().all(|c| c.is_ascii_uppercase())
+        && subdivision.is_none_or(|subdivision| {
+            (1..=3).contains(&subdivision.len())
+                && subdivision
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        })
+}
+
+/// Returns true if `tax_class` is a valid class name: 1 to 32 lower case letters, digits or
+/// underscores.
+pub fn is_valid_tax_class(tax_class: &str) -> bool {
+    (1..=32).contains(&tax_class.len())
+        && tax_class
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Returns the region taxed when an order does not name one, configured with
+/// STORE_TAX_REGION. Without it such orders are not taxed.
+pub fn default_tax_region() -> Option<String> {
+    env::var("STORE_TAX_REGION")
+        .ok()
+        .map(|region| region.trim().to_uppercase())
+        .filter(|region| is_valid_region(region))
+}
+
+/// Returns whether catalog prices include tax, configured with PRICES_INCLUDE_TAX
+/// (default false). Orders keep the setting they were placed with.
+pub fn prices_include_tax() -> bool {
+    env::var("PRICES_INCLUDE_TAX")
+        .map(|value| value == "true")
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn round_money_rounds_half_to_even() {
+        assert_eq!(round_money(dec!(2.345)), dec!(2.34));
+        assert_eq!(round_money(dec!(2.355)), dec!(2.36));
+        assert_eq!(round_money(dec!(2.3451)), dec!(2.35));
+        assert_eq!(round_money(dec!(-2.345)), dec!(-2.34));
+        assert_eq!(round_money(dec!(7)), dec!(7));
+    }
+
+    #[test]
+    fn allocate_gives_the_remainder_to_the_last_weighted_line() {
+        assert_eq!(
+            allocate(dec!(10.00), &[dec!(1), dec!(1), dec!(1)]),
+            vec![dec!(3.33), dec!(3.33), dec!(3.34)]
+        );
+        assert_eq!(
+            allocate(dec!(0.10), &[dec!(1), dec!(1), dec!(1), dec!(0)]),
+            vec![dec!(0.03), dec!(0.03), dec!(0.04), dec!(0)]
+        );
+        assert_eq!(
+            allocate(dec!(1.00), &[dec!(20), dec!(0), dec!(10)]),
+            vec![dec!(0.67), dec!(0), dec!(0.33)]
+        );
+    }
+
+    #[test]
+    fn allocate_shares_always_add_up_to_the_total() {
+        let weights = [dec!(19.99), dec!(5.01), dec!(0.99), dec!(120)];
+
+        for total in [dec!(0.01), dec!(3.33), dec!(10), dec!(99.99)] {
+            let shares = allocate(total, &weights);
+            assert_eq!(shares.iter().sum::<Decimal>(), total);
+        }
+    }
+
+    #[test]
+    fn allocate_without_weights_allocates_nothing() {
+        assert_eq!(
+            allocate(dec!(5), &[dec!(0), dec!(0)]),
+            vec![dec!(0), dec!(0)]
+        );
+        assert!(allocate(dec!(5), &[]).is_empty());
+    }
+
+    #[test]
+    fn line_tax_is_added_to_exclusive_prices() {
+        assert_eq!(line_tax(dec!(100), dec!(19), false), dec!(19.00));
+        assert_eq!(line_tax(dec!(10.00), dec!(7.25), false), dec!(0.72));
+        assert_eq!(line_tax(dec!(10.00), dec!(0), false), dec!(0));
+    }
+
+    #[test]
+    fn line_tax_is_extracted_from_inclusive_prices() {
+        assert_eq!(line_tax(dec!(119), dec!(19), true), dec!(19.00));
+        assert_eq!(line_tax(dec!(10.00), dec!(7.25), true), dec!(0.68));
+        assert_eq!(line_tax(dec!(10.00), dec!(0), true), dec!(0));
+    }
+
+    #[test]
+    fn regions_are_countries_or_subdivisions() {
+        for region in ["DE", "US-CA", "GB-ENG", "FR-75", "ES-M"] {
+            assert!(is_valid_region(region), "{} should be valid", region);
+        }
+        for region in [
+            "", "D", "de", "DEU", "US-", "US-ca", "US-CALI", "US_CA", "US-CA-1",
+        ] {
+            assert!(!is_valid_region(region), "{} should be invalid", region);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use std::env;
+
+use deadpool_postgres::Client;
+use rustify_store::{db::create_pool, migrations};
+
+/// Connects to the database named by DATABASE_URL and brings its schema up to date.
+/// Returns None when no database is configured, in which case the calling test is skipped.
+/// Tests run inside a transaction they never commit, so they leave no data behind.
+pub async fn connect() -> Option<Client> {
+    dotenv::dotenv().ok();
+    if env::var("DATABASE_URL").is_err() {
+        eprintln!("DATABASE_URL is not set, skipping database test");
+        return None;
+    }
+
+    let pool = create_pool().expect("Failed to configure database pool");
+    let mut client = pool.get().await.expect("Failed to connect to database");
+    migrations::run_pending(&mut client)
+        .await
+        .expect("Failed to apply database migrations");
+
+    Some(client)
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use rust_decimal_macros::dec;
+use rustify_store::{
+    controllers::shipment_controller::sync_order_status,
+    models::{
+        order_items_model::OrderItem,
+        order_model::{Order, OrderStatus},
+        shipment_model::{Shipment, ShipmentChanges, ShipmentItem, ShipmentStatus},
+    },
+};
+use uuid::Uuid;
+
+#[actix_web::test]
+async fn chained_transitions_are_recorded_in_order() {
+    let Some(mut client) = common::connect().await else {
+        return;
+    };
+    let transaction = client.transaction().await.unwrap();
+
+    let user_id = Uuid::new_v4();
+    transaction
+        .execute(
+            "INSERT INTO users (id, name, email, password) VALUES ($1, 'Test', $2, 'x')",
+            &[&user_id, &format!("{}@example.com", user_id.simple())],
+        )
+        .await
+        .unwrap();
+    let product_id = Uuid::new_v4();
+    transaction
+        .execute(
+            "INSERT INTO products (id, name, price, stock) VALUES ($1, 'Test', $2, 10)",
+            &[&product_id, &dec!(10.00)],
+        )
+        .await
+        .unwrap();
+
+    let order = Order::create_order(&transaction, user_id, None, None)
+        .await
+        .unwrap();
+    let item = OrderItem::create_order_item(&transaction, order.id, product_id, None, 2)
+        .await
+        .unwrap();
+
+    let mut order = order;
+    for next in [OrderStatus::AwaitingPayment, OrderStatus::Paid] {
+        order = Order::transition(&transaction, &order, next, Some(user_id))
+            .await
+            .unwrap();
+    }
+
+    // A shipment delivered at once moves the order through every fulfillment step
+    // within one transaction
+    let shipment = Shipment::create(
+        &transaction,
+        order.id,
+        None,
+        None,
+        &[ShipmentItem {
+            order_item_id: item.id,
+            quantity: 2,
+        }],
+    )
+    .await
+    .unwrap();
+    Shipment::update(
+        &transaction,
+        shipment.id,
+        &ShipmentChanges {
+            status: Some(ShipmentStatus::Delivered),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    sync_order_status(&transaction, order.id, user_id)
+        .await
+        .unwrap();
+
+    let history = Order::status_history(&transaction, order.id).await.unwrap();
+    let steps: Vec<(Option<OrderStatus>, OrderStatus)> = history
+        .iter()
+        .map(|change| (change.from_status, change.to_status))
+        .collect();
+
+    assert_eq!(
+        steps,
+        vec![
+            (None, OrderStatus::Pending),
+            (Some(OrderStatus::Pending), OrderStatus::AwaitingPayment),
+            (Some(OrderStatus::AwaitingPayment), OrderStatus::Paid),
+            (Some(OrderStatus::Paid), OrderStatus::Fulfilling),
+            (Some(OrderStatus::Fulfilling), OrderStatus::Shipped),
+            (Some(OrderStatus::Shipped), OrderStatus::Delivered),
+        ]
+    );
+}
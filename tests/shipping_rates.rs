@@ -0,0 +1,104 @@
+mod common;
+
+use rust_decimal_macros::dec;
+use rustify_store::models::shipping_model::{
+    NewShippingRate, ShippingMethod, ShippingRate, ShippingZone,
+};
+use uuid::Uuid;
+
+#[actix_web::test]
+async fn find_prefers_the_subdivision_zone_over_its_country_zone() {
+    let Some(mut client) = common::connect().await else {
+        return;
+    };
+    let transaction = client.transaction().await.unwrap();
+
+    // "ZZ" is a user-assigned code, so no real zone serves it
+    let suffix = Uuid::new_v4().simple().to_string();
+    let method = ShippingMethod::create(&transaction, &format!("test_{}", &suffix[..16]), "Test")
+        .await
+        .unwrap();
+    let country = ShippingZone::create(
+        &transaction,
+        &format!("Country {}", suffix),
+        &["ZZ".to_string()],
+    )
+    .await
+    .unwrap();
+    let islands = ShippingZone::create(
+        &transaction,
+        &format!("Islands {}", suffix),
+        &["ZZ-HI".to_string()],
+    )
+    .await
+    .unwrap();
+
+    for (zone, price) in [(&country, dec!(5.00)), (&islands, dec!(25.00))] {
+        ShippingRate::create(
+            &transaction,
+            zone.id,
+            &NewShippingRate {
+                method_id: method.id,
+                max_weight_kg: None,
+                price,
+                free_over: None,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let find = |region: &'static str| ShippingRate::find(&transaction, region, method.id, dec!(1));
+
+    let subdivision = find("ZZ-HI").await.unwrap().unwrap();
+    assert_eq!(subdivision.zone_id, islands.id);
+    assert_eq!(subdivision.price, dec!(25.00));
+
+    // Other subdivisions fall back to the zone of their country
+    let other = find("ZZ-CA").await.unwrap().unwrap();
+    assert_eq!(other.zone_id, country.id);
+    assert_eq!(find("ZZ").await.unwrap().unwrap().zone_id, country.id);
+
+    assert!(find("ZY").await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn find_picks_the_smallest_bracket_covering_the_weight() {
+    let Some(mut client) = common::connect().await else {
+        return;
+    };
+    let transaction = client.transaction().await.unwrap();
+
+    let suffix = Uuid::new_v4().simple().to_string();
+    let method = ShippingMethod::create(&transaction, &format!("test_{}", &suffix[..16]), "Test")
+        .await
+        .unwrap();
+    let zone = ShippingZone::create(
+        &transaction,
+        &format!("Zone {}", suffix),
+        &["ZZ".to_string()],
+    )
+    .await
+    .unwrap();
+
+    for (max_weight_kg, price) in [(Some(dec!(2)), dec!(5.00)), (Some(dec!(10)), dec!(9.00))] {
+        ShippingRate::create(
+            &transaction,
+            zone.id,
+            &NewShippingRate {
+                method_id: method.id,
+                max_weight_kg,
+                price,
+                free_over: None,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let find = |weight| ShippingRate::find(&transaction, "ZZ", method.id, weight);
+
+    assert_eq!(find(dec!(2)).await.unwrap().unwrap().price, dec!(5.00));
+    assert_eq!(find(dec!(2.001)).await.unwrap().unwrap().price, dec!(9.00));
+    assert!(find(dec!(10.5)).await.unwrap().is_none());
+}